@@ -27,9 +27,11 @@ pub struct ClientExitTunnelConfig {
     /// peer discovery has been changed since it was first needed
     pub rita_hello_port: u16,
     /// This is a user provided bandwidth limit (upload and download) to be enforced
-    /// by cake. Traffic is shaped incoming on wg_exit and outgoing on br_lan resulting
-    /// in a symmetrical limit of the users choice. Specified in mbit/s
+    /// by cake. Traffic is shaped incoming on wg_exit and outgoing on the lan interfaces
+    /// resulting in a symmetrical limit of the users choice. Specified in mbit/s
     pub user_specified_speed: Option<usize>,
+    /// The lan bridge devices the user specified speed is applied to
+    pub lan_interfaces: Vec<String>,
 }
 
 impl dyn KernelInterface {
@@ -152,7 +154,11 @@ impl dyn KernelInterface {
             )));
         }
 
-        let _res = self.set_codel_shaping("br-lan", args.user_specified_speed);
+        for iface in args.lan_interfaces.iter() {
+            if let Err(e) = self.set_codel_shaping(iface, args.user_specified_speed) {
+                error!("Failed to apply bandwidth limit to {} with {:?}", iface, e);
+            }
+        }
 
         Ok(())
     }
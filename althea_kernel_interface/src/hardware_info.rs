@@ -39,6 +39,7 @@ pub fn get_hardware_info(device_name: Option<String>) -> Result<HardwareInfo, Er
     let num_cpus = get_numcpus()?;
 
     let sensor_readings = get_sensor_readings();
+    let cpu_temp_centicelsius = sensor_readings.as_deref().and_then(get_cpu_temp);
     let allocated_memory = match mem_total.checked_sub(mem_free) {
         Some(val) => val,
         None => return Err(Error::FailedToGetMemoryUsage),
@@ -75,6 +76,7 @@ pub fn get_hardware_info(device_name: Option<String>) -> Result<HardwareInfo, Er
         wifi_devices,
         extender_list,
         conntrack: conntrack_info,
+        cpu_temp_centicelsius,
     })
 }
 
@@ -292,6 +294,22 @@ fn get_sensor_readings() -> Option<Vec<SensorReading>> {
     }
 }
 
+/// Hwmon sensor names that report the cpu temperature, in order of preference. coretemp is
+/// used on x86, cpu_thermal on most arm socs and the remainder are more generic fallbacks
+const CPU_SENSOR_NAMES: [&str; 4] = ["coretemp", "cpu_thermal", "cpu", "soc_thermal"];
+
+/// Picks the cpu temperature out of the sensor readings, returns None if none of the
+/// sensors are recognizably attached to the cpu. Hwmon reports millidegrees, the result is
+/// in centi-celsius
+fn get_cpu_temp(sensors: &[SensorReading]) -> Option<u64> {
+    for name in CPU_SENSOR_NAMES {
+        if let Some(sensor) = sensors.iter().find(|s| s.name.contains(name)) {
+            return Some(sensor.reading / 10);
+        }
+    }
+    None
+}
+
 fn get_ethernet_stats() -> Option<Vec<EthernetStats>> {
     let mut eth = 0;
     let mut ret = Vec::new();
@@ -586,6 +604,27 @@ mod test {
         println!("{res:?}");
     }
 
+    #[test]
+    fn test_cpu_temp() {
+        let sensor = |name: &str, reading| SensorReading {
+            name: name.to_string(),
+            reading,
+            min: None,
+            max: None,
+            crit: None,
+        };
+        let sensors = vec![
+            sensor("ath10k_hwmon", 52000),
+            sensor("cpu_thermal", 61000),
+            sensor("coretemp", 48000),
+        ];
+        assert_eq!(get_cpu_temp(&sensors), Some(4800));
+        assert_eq!(get_cpu_temp(&sensors[..2]), Some(6100));
+        // a wifi radio sensor alone is not a cpu temperature
+        assert_eq!(get_cpu_temp(&sensors[..1]), None);
+        assert_eq!(get_cpu_temp(&[]), None);
+    }
+
     #[test]
     fn test_ethernet_stats() {
         let res = get_ethernet_stats();
@@ -270,6 +270,12 @@ impl dyn KernelInterface {
         }
     }
 
+    /// removes the route set for a peer by manual_peers_route
+    pub fn remove_manual_peers_route(&self, endpoint_ip: &IpAddr) -> Result<(), Error> {
+        self.run_command("ip", &["route", "del", &format!("{endpoint_ip}/32")])?;
+        Ok(())
+    }
+
     /// restore the default route, if we find a default route is already in place that is not
     /// our wg_exit route we grab that one, save it off, and make no changes.
     pub fn restore_default_route(
@@ -2,16 +2,33 @@ use base64::DecodeError;
 use std::error::Error;
 use std::fmt;
 use std::fmt::Result as FormatResult;
+use std::net::IpAddr;
 
 #[derive(Clone, Debug)]
 pub enum AltheaTypesError {
     WgParseError(DecodeError),
+    /// A WgKey is a curve25519 key, it must decode to exactly 32 bytes
+    WgKeyLength(usize),
+    /// Mesh ips are always in fd00::/8, an identity with any other mesh ip is a provisioning error
+    InvalidMeshIp(IpAddr),
+    /// A payment that should not be acted on, see PaymentTx::validate
+    InvalidPayment(String),
 }
 
 impl fmt::Display for AltheaTypesError {
     fn fmt(&self, f: &mut fmt::Formatter) -> FormatResult {
         match self {
             AltheaTypesError::WgParseError(val) => write!(f, "Failed to parse WgKey with {val}"),
+            AltheaTypesError::WgKeyLength(len) => {
+                write!(f, "WgKey must decode to 32 bytes, got {len}")
+            }
+            AltheaTypesError::InvalidMeshIp(ip) => {
+                write!(
+                    f,
+                    "Mesh ip {ip} is not in fd00::/8, the mesh only uses fd00::/8 IPv6 addresses"
+                )
+            }
+            AltheaTypesError::InvalidPayment(reason) => write!(f, "Invalid payment: {reason}"),
         }
     }
 }
@@ -1,3 +1,4 @@
+use crate::error::AltheaTypesError;
 use crate::regions::Regions;
 use crate::{contact_info::ContactType, wg_key::WgKey, BillingDetails, InstallationDetails};
 use crate::{ClientExtender, UsageTrackerFlat, UsageTrackerTransfer, WifiDevice};
@@ -23,6 +24,7 @@ use std::time::{Duration, SystemTime};
 /// This is how nodes are identified.
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub struct Identity {
+    #[serde(deserialize_with = "deserialize_mesh_ip")]
     pub mesh_ip: IpAddr,
     pub eth_address: Address,
     pub wg_public_key: WgKey,
@@ -53,6 +55,16 @@ impl Display for Identity {
 
 pub const ALTHEA_PREFIX: &str = "althea";
 
+/// Rejects mesh ips outside of fd00::/8 when deserializing an identity, see Identity::validate_mesh_ip
+fn deserialize_mesh_ip<'de, D>(deserializer: D) -> Result<IpAddr, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mesh_ip = IpAddr::deserialize(deserializer)?;
+    Identity::validate_mesh_ip(mesh_ip).map_err(serde::de::Error::custom)?;
+    Ok(mesh_ip)
+}
+
 impl Identity {
     pub fn new(
         mesh_ip: IpAddr,
@@ -68,6 +80,28 @@ impl Identity {
         }
     }
 
+    /// Same as new but rejects an invalid mesh ip, see validate_mesh_ip
+    pub fn try_new(
+        mesh_ip: IpAddr,
+        eth_address: Address,
+        wg_public_key: WgKey,
+        nickname: Option<ArrayString<32>>,
+    ) -> Result<Identity, AltheaTypesError> {
+        Identity::validate_mesh_ip(mesh_ip)?;
+        Ok(Identity::new(mesh_ip, eth_address, wg_public_key, nickname))
+    }
+
+    /// Mesh ips are always in fd00::/8, anything else is a provisioning error that would otherwise
+    /// surface later as obscure tunnel setup or babel route correlation failures. The loopback
+    /// address is also accepted, it is the placeholder mesh ip of payees that are not on the mesh,
+    /// such as the operator, and is stored in the payment history
+    pub fn validate_mesh_ip(mesh_ip: IpAddr) -> Result<(), AltheaTypesError> {
+        match mesh_ip {
+            IpAddr::V6(ip) if ip.octets()[0] == 0xfd || ip.is_loopback() => Ok(()),
+            _ => Err(AltheaTypesError::InvalidMeshIp(mesh_ip)),
+        }
+    }
+
     /// Returns true if this identity is converged, meaning the Althea address is
     /// derived from and is interchangeable with the ETH address. If false we have
     /// to avoid assumptions avoid these being the same private key
@@ -198,6 +232,18 @@ impl Display for SystemChain {
     }
 }
 
+impl SystemChain {
+    /// The EIP-155 chain id of this chain, this is also the net_version reported by its full nodes
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            SystemChain::Ethereum => 1,
+            SystemChain::Sepolia => 11155111,
+            SystemChain::Xdai => 100,
+            SystemChain::AltheaL1 => 258432,
+        }
+    }
+}
+
 fn default_system_chain() -> SystemChain {
     SystemChain::default()
 }
@@ -338,6 +384,21 @@ impl ExitState {
             ExitState::Denied { ref message, .. } => message.clone(),
         }
     }
+
+    /// True if we are registered with this exit and can use it
+    pub fn is_registered(&self) -> bool {
+        matches!(self, ExitState::Registered { .. })
+    }
+
+    /// True if registration is waiting on the user to enter a verification code
+    pub fn is_pending(&self) -> bool {
+        matches!(self, ExitState::Pending { .. })
+    }
+
+    /// True if the exit has refused our registration
+    pub fn is_denied(&self) -> bool {
+        matches!(self, ExitState::Denied { .. })
+    }
 }
 
 /// This is all the data we need to send to an exit
@@ -406,6 +467,10 @@ pub struct ExitDetails {
     pub description: String,
     #[serde(default = "default_verif_mode")]
     pub verif_mode: ExitVerifMode,
+    /// A free form region tag for this exit, for example 'us-west', clients with a matching
+    /// preferred_region give this exit a bonus in exit selection
+    #[serde(default)]
+    pub region: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
@@ -442,6 +507,39 @@ impl Hash for PaymentTx {
     }
 }
 
+impl PaymentTx {
+    /// Returns the key identifying this logical payment, a payment that is retried or reported
+    /// more than once keeps the same key and so should only be recorded once
+    pub fn idempotency_key(&self) -> PaymentIdempotencyKey {
+        PaymentIdempotencyKey { txid: self.txid }
+    }
+
+    /// True if this payment is from a node to itself
+    pub fn is_self_payment(&self) -> bool {
+        self.to == self.from
+    }
+
+    /// Sanity checks a payment before we act on it, rejecting zero amount payments,
+    /// self payments and payments that we are not a party to
+    pub fn validate(&self, our_id: &Identity) -> Result<(), AltheaTypesError> {
+        UnpublishedPaymentTx {
+            to: self.to,
+            from: self.from,
+            amount: self.amount,
+        }
+        .validate(our_id)
+    }
+}
+
+/// Identifies a single logical payment, since a PaymentTx is published the txid is always
+/// known and a txid names exactly one transaction. A report of the same txid with a different
+/// recipient or amount is a bad report of a payment we already have, not a new payment, which
+/// matches how PaymentTx is hashed
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct PaymentIdempotencyKey {
+    pub txid: Uint256,
+}
+
 /// This represents a generic payment that may be to or from us, it does not contain a txid meaning it is
 /// unpublished
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -460,6 +558,29 @@ impl UnpublishedPaymentTx {
             txid,
         }
     }
+
+    /// See PaymentTx::validate, this is checked before a payment is sent so that an invalid
+    /// payment is never published
+    pub fn validate(&self, our_id: &Identity) -> Result<(), AltheaTypesError> {
+        if self.amount == Uint256::from(0u32) {
+            return Err(AltheaTypesError::InvalidPayment(
+                "zero amount payment".to_string(),
+            ));
+        }
+        if self.to == self.from {
+            return Err(AltheaTypesError::InvalidPayment(format!(
+                "payment from {} to itself",
+                self.from.wg_public_key
+            )));
+        }
+        if self.to != *our_id && self.from != *our_id {
+            return Err(AltheaTypesError::InvalidPayment(format!(
+                "payment from {} to {} does not involve us",
+                self.from.wg_public_key, self.to.wg_public_key
+            )));
+        }
+        Ok(())
+    }
 }
 
 /// This enum contains information about what type of update we need to perform on a router initiated from op tools.
@@ -564,6 +685,29 @@ pub enum ReleaseStatus {
     GeneralAvailability,
 }
 
+impl ReleaseStatus {
+    /// Returns the opkg feed url for this release status given the router's current althea feed url.
+    /// Feeds are laid out as {base}/{release}/packages/{arch}/althea so only the release segment is
+    /// replaced, keeping the base and arch. A custom release status carries its own complete url.
+    /// Returns None if the current url does not have that layout
+    pub fn feed_url(&self, current: &str) -> Option<String> {
+        let release = match self {
+            ReleaseStatus::Custom(url) => return Some(url.clone()),
+            ReleaseStatus::ReleaseCandidate => "rc",
+            ReleaseStatus::PreRelease => "pr",
+            ReleaseStatus::GeneralAvailability => "ga",
+        };
+        let (scheme, rest) = current.split_once("://")?;
+        let mut segments: Vec<&str> = rest.split('/').collect();
+        // the host, the release and then at least the package path
+        if segments.len() < 3 || segments.iter().any(|s| s.is_empty()) {
+            return None;
+        }
+        segments[1] = release;
+        Some(format!("{scheme}://{}", segments.join("/")))
+    }
+}
+
 #[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct WifiChannel {
     pub radio: String,
@@ -706,7 +850,8 @@ pub struct OperatorUpdateMessage {
     /// A json payload to be merged into the existing settings, this payload is checked
     /// not to include a variety of things that might break the router but is still not
     /// risk free for example the url fields require http:// or https:// or the router will
-    /// crash even though the value will be accepted as a valid string
+    /// crash even though the value will be accepted as a valid string, routers prepend https://
+    /// to schemeless urls and reject urls with any other scheme before merging
     pub merge_json: serde_json::Value,
     /// An action the operator wants to take to affect this router, examples may include reset
     /// password or change the wifi ssid
@@ -734,6 +879,10 @@ pub struct OperatorUpdateMessage {
     /// side how much history we need to send in with the next checkin cycle
     #[serde(default = "default_ops_last_seen_usage_hour")]
     pub ops_last_seen_usage_hour: u64,
+    /// The firmware release feed this router should pull opkg updates from, if it is
+    /// 'none' here it is interpreted as "don't change anything"
+    #[serde(default)]
+    pub firmware_feed: Option<ReleaseStatus>,
 }
 
 /// Serializes a ContactType as a string
@@ -788,6 +937,10 @@ pub struct CurExitInfo {
 pub struct ExitConnection {
     pub cur_exit: Option<CurExitInfo>,
     pub client_pub_ipv6: Option<IpNetwork>,
+    /// How many times the router has switched to or started tracking each exit over the last day,
+    /// exits that show up here with a high count have flapping routes
+    #[serde(default)]
+    pub exit_switch_counts: Vec<(IpAddr, u32)>,
 }
 
 fn default_ops_last_seen_usage_hour() -> u64 {
@@ -881,16 +1034,19 @@ pub struct HardwareInfo {
     /// see this reference before making decisions based on this value
     /// http://www.brendangregg.com/blog/2017-08-08/linux-load-averages.html
     /// parsed from /proc/loadvg
+    #[serde(deserialize_with = "deserialize_load_avg")]
     pub load_avg_one_minute: f32,
     /// The load average of the system over the last 5 minutes please
     /// see this reference before making decisions based on this value
     /// http://www.brendangregg.com/blog/2017-08-08/linux-load-averages.html
     /// parsed from /proc/loadavg
+    #[serde(deserialize_with = "deserialize_load_avg")]
     pub load_avg_five_minute: f32,
     /// The load average of the system over the last 15 minutes please
     /// see this reference before making decisions based on this value
     /// http://www.brendangregg.com/blog/2017-08-08/linux-load-averages.html
     /// parsed from /proc/loadavg
+    #[serde(deserialize_with = "deserialize_load_avg")]
     pub load_avg_fifteen_minute: f32,
     /// Available system memory in kilobytes parsed from /proc/meminfo
     pub system_memory: u64,
@@ -929,12 +1085,29 @@ pub struct HardwareInfo {
     // Info about the max connections, number of rows in conntrack table and current number of connections made by router
     #[serde(default)]
     pub conntrack: Option<ConntrackInfo>,
+    /// The temperature of the cpu in centi-celsius, taken from the most relevant entry in
+    /// sensor_readings. None if no sensor on this device reports the cpu temperature
+    #[serde(default)]
+    pub cpu_temp_centicelsius: Option<u64>,
 }
 
 fn default_kernel_version() -> String {
     "Unknown".to_string()
 }
 
+/// Rejects load averages that can't come from a real /proc/loadavg, negative, NaN or infinite
+/// values would otherwise break anything charting or averaging these reports
+fn deserialize_load_avg<'de, D>(deserializer: D) -> Result<f32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let load = f32::deserialize(deserializer)?;
+    if !load.is_finite() || load < 0.0 {
+        return Err(D::Error::custom(format!("Invalid load average {load}")));
+    }
+    Ok(load)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// Representation of a sensor discovered in /sys/class/hwmon
 /// https://www.kernel.org/doc/Documentation/hwmon/sysfs-interface
@@ -1018,18 +1191,35 @@ pub struct HeartbeatMessage {
     /// in the usual unit of wei/byte
     pub exit_dest_price: u64,
     /// The identity of the upstream neighbor, being defined as the one
-    /// closer to the exit
-    pub upstream_id: Identity,
+    /// closer to the exit, None if we are not connected to an exit
+    #[serde(default)]
+    pub upstream_id: Option<Identity>,
     /// The babel Route to the exit, including details such as metric and
-    /// full path rtt
-    pub exit_route: Route,
+    /// full path rtt, None if we are not connected to an exit
+    #[serde(default)]
+    pub exit_route: Option<Route>,
     /// The babel Neighbor over which our traffic flows, this gives us the Reach
-    /// (packet loss over 16 seconds) as well as the neighbor RTT
-    pub exit_neighbor: Neighbor,
+    /// (packet loss over 16 seconds) as well as the neighbor RTT, None if we are
+    /// not connected to an exit
+    #[serde(default)]
+    pub exit_neighbor: Option<Neighbor>,
     /// If this user wants to be notified when they have a low balance
     pub notify_balance: bool,
     /// The router version stored in semver format as found in the Cargo.toml
     pub version: String,
+    /// Incremented for every heartbeat this router sends, the operator server echos it back
+    /// in a HeartbeatAck so that lost heartbeats can be detected and resent. A resent heartbeat
+    /// carries the same sequence number as the original
+    #[serde(default)]
+    pub sequence: u64,
+}
+
+/// Optionally sent by the operator server in response to a heartbeat, this is encrypted the same
+/// way as the heartbeat itself and sent as Nonce, Ciphertext to the port the heartbeat came from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatAck {
+    /// The sequence number of the heartbeat being acknowledged
+    pub sequence: u64,
 }
 
 /// An exit's unix time stamp that can be queried by a downstream router
@@ -1063,7 +1253,14 @@ mod test {
     }
     use lettre::Address;
 
-    use crate::{data_deserialize, data_serialize, ContactType};
+    use super::deserialize_load_avg;
+    use crate::{
+        data_deserialize, data_serialize, ContactType, ExitClientDetails, ExitDetails, ExitState,
+        ExitVerifMode, HardwareInfo, HeartbeatMessage, Identity, PaymentTx, ReleaseStatus,
+        SystemChain,
+    };
+    use serde::de::value::{Error as ValueError, F32Deserializer};
+    use serde::de::IntoDeserializer;
     #[test]
     fn test_operator_update_serialize() {
         let entry: DummyStruct = DummyStruct {
@@ -1075,4 +1272,231 @@ mod test {
         let data = bincode::serialize(&entry).unwrap();
         let _try_bincode: DummyStruct = bincode::deserialize(&data).unwrap();
     }
+
+    #[test]
+    fn test_exit_state_predicates() {
+        let general_details = ExitDetails {
+            server_internal_ip: "172.168.0.254".parse().unwrap(),
+            netmask: 16,
+            wg_exit_port: 59998,
+            exit_price: 50,
+            exit_currency: SystemChain::Xdai,
+            description: "".to_string(),
+            verif_mode: ExitVerifMode::Off,
+            region: None,
+        };
+        let our_details = ExitClientDetails {
+            client_internal_ip: "172.168.0.1".parse().unwrap(),
+            internet_ipv6_subnet: None,
+        };
+        // (state, is_registered, is_pending, is_denied)
+        let cases = vec![
+            (ExitState::New, false, false, false),
+            (
+                ExitState::GotInfo {
+                    general_details: general_details.clone(),
+                    message: "".to_string(),
+                },
+                false,
+                false,
+                false,
+            ),
+            (
+                ExitState::Pending {
+                    general_details: general_details.clone(),
+                    message: "".to_string(),
+                    email_code: None,
+                    phone_code: None,
+                },
+                false,
+                true,
+                false,
+            ),
+            (
+                ExitState::Registered {
+                    general_details,
+                    our_details,
+                    message: "".to_string(),
+                },
+                true,
+                false,
+                false,
+            ),
+            (
+                ExitState::Denied {
+                    message: "".to_string(),
+                },
+                false,
+                false,
+                true,
+            ),
+        ];
+        for (state, registered, pending, denied) in cases {
+            assert_eq!(state.is_registered(), registered, "{state:?}");
+            assert_eq!(state.is_pending(), pending, "{state:?}");
+            assert_eq!(state.is_denied(), denied, "{state:?}");
+        }
+    }
+
+    #[test]
+    fn test_hardware_info_validation() {
+        let hardware_info = |load: &str| {
+            format!(
+                r#"{{"logical_processors":4,"load_avg_one_minute":{load},
+                "load_avg_five_minute":0.5,"load_avg_fifteen_minute":0.25,"system_memory":1024,
+                "allocated_memory":512,"model":"test","sensor_readings":null,"ethernet_stats":null}}"#
+            )
+        };
+        assert!(serde_json::from_str::<HardwareInfo>(&hardware_info("1.5")).is_ok());
+        assert!(serde_json::from_str::<HardwareInfo>(&hardware_info("-1.5")).is_err());
+        assert!(serde_json::from_str::<HardwareInfo>(&hardware_info("1e39")).is_err());
+
+        // json can't carry NaN or infinity, so those go straight to the validator
+        let load_avg = |load: f32| {
+            let deserializer: F32Deserializer<ValueError> = load.into_deserializer();
+            deserialize_load_avg(deserializer)
+        };
+        assert_eq!(load_avg(1.5).unwrap(), 1.5);
+        assert!(load_avg(f32::NAN).is_err());
+        assert!(load_avg(f32::INFINITY).is_err());
+        assert!(load_avg(f32::NEG_INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_payment_tx_validate() {
+        let id = |mesh_ip: &str, wg_key: &str| Identity {
+            mesh_ip: mesh_ip.parse().unwrap(),
+            eth_address: "0x5CC9aF89B1bf70565d75d0822027694Af38Ca017"
+                .parse()
+                .unwrap(),
+            wg_public_key: wg_key.parse().unwrap(),
+            nickname: None,
+        };
+        let us = id("fd00::1", "8BeCExnthLe5ou0EYec5jNqJ/PduZ1x2o7lpXJOpgXk=");
+        let peer = id("fd00::2", "QkzYfnCeTp1iYKUyMjAVsmwPiemx4Yyqc83G17cebyM=");
+        let stranger = id("fd00::3", "zgAlhyOQy8crB0ewrsWt3ES9SvFguwx5mq9i2KiknmA=");
+        let payment = |from: Identity, to: Identity, amount: u64| PaymentTx {
+            to,
+            from,
+            amount: amount.into(),
+            txid: 1u8.into(),
+        };
+
+        assert!(payment(us, peer, 10).validate(&us).is_ok());
+        assert!(payment(peer, us, 10).validate(&us).is_ok());
+        assert!(payment(us, peer, 0).validate(&us).is_err());
+        assert!(payment(us, us, 10).is_self_payment());
+        assert!(payment(us, us, 10).validate(&us).is_err());
+        assert!(payment(peer, stranger, 10).validate(&us).is_err());
+    }
+
+    #[test]
+    fn test_system_chain_id() {
+        assert_eq!(SystemChain::Ethereum.chain_id(), 1);
+        assert_eq!(SystemChain::Sepolia.chain_id(), 11155111);
+        assert_eq!(SystemChain::Xdai.chain_id(), 100);
+        assert_eq!(SystemChain::AltheaL1.chain_id(), 258432);
+    }
+
+    #[test]
+    fn test_release_status_feed_url() {
+        let current = "https://updates.altheamesh.com/beta18rc9/packages/mipsel_24kc/althea";
+        assert_eq!(
+            ReleaseStatus::GeneralAvailability
+                .feed_url(current)
+                .unwrap(),
+            "https://updates.altheamesh.com/ga/packages/mipsel_24kc/althea"
+        );
+        assert_eq!(
+            ReleaseStatus::ReleaseCandidate.feed_url(current).unwrap(),
+            "https://updates.altheamesh.com/rc/packages/mipsel_24kc/althea"
+        );
+        assert_eq!(
+            ReleaseStatus::PreRelease.feed_url(current).unwrap(),
+            "https://updates.altheamesh.com/pr/packages/mipsel_24kc/althea"
+        );
+        assert_eq!(
+            ReleaseStatus::Custom("https://example.com/feed/".to_string())
+                .feed_url(current)
+                .unwrap(),
+            "https://example.com/feed/"
+        );
+        assert!(ReleaseStatus::GeneralAvailability
+            .feed_url("https://updates.altheamesh.com/")
+            .is_none());
+        assert!(ReleaseStatus::GeneralAvailability
+            .feed_url("www.dummyurl.com")
+            .is_none());
+    }
+
+    #[test]
+    fn test_identity_mesh_ip_family() {
+        let json = r#"{"mesh_ip":"fd00::1","eth_address":"0x9CAFD25b8b5982F1edA0691DEF8997C55a4d8188","wg_public_key":"8BeCExnthLe5ou0EYec5jNqJ/PduZ1x2o7lpXJOpgXk=","nickname":null}"#;
+        let id: Identity = serde_json::from_str(json).unwrap();
+        assert_eq!(id.mesh_ip, "fd00::1".parse::<std::net::IpAddr>().unwrap());
+
+        let v4_json = json.replace("fd00::1", "10.0.0.1");
+        let res: Result<Identity, _> = serde_json::from_str(&v4_json);
+        assert!(res.unwrap_err().to_string().contains("fd00::/8"));
+
+        // ipv6 addresses outside of the mesh range are rejected as well
+        let global_json = json.replace("fd00::1", "2001:db8::1");
+        let res: Result<Identity, _> = serde_json::from_str(&global_json);
+        assert!(res.unwrap_err().to_string().contains("fd00::/8"));
+        let link_local_json = json.replace("fd00::1", "fe80::1");
+        assert!(serde_json::from_str::<Identity>(&link_local_json).is_err());
+        // the placeholder for payees that are not on the mesh
+        let placeholder_json = json.replace("fd00::1", "::1");
+        assert!(serde_json::from_str::<Identity>(&placeholder_json).is_ok());
+
+        assert!(Identity::try_new(
+            "10.0.0.1".parse().unwrap(),
+            id.eth_address,
+            id.wg_public_key,
+            None
+        )
+        .is_err());
+        assert!(Identity::try_new(id.mesh_ip, id.eth_address, id.wg_public_key, None).is_ok());
+    }
+
+    #[test]
+    fn test_heartbeat_without_exit_serialize() {
+        let id = Identity {
+            mesh_ip: "fd00::1".parse().unwrap(),
+            eth_address: "0x9CAFD25b8b5982F1edA0691DEF8997C55a4d8188"
+                .parse()
+                .unwrap(),
+            wg_public_key: "8BeCExnthLe5ou0EYec5jNqJ/PduZ1x2o7lpXJOpgXk="
+                .parse()
+                .unwrap(),
+            nickname: None,
+        };
+        let message = HeartbeatMessage {
+            id,
+            organizer_address: None,
+            balance: None,
+            exit_dest_price: 0,
+            upstream_id: None,
+            exit_route: None,
+            exit_neighbor: None,
+            notify_balance: false,
+            version: "0.0.0".to_string(),
+            sequence: 0,
+        };
+        let json = serde_json::to_string(&message).unwrap();
+        let parsed: HeartbeatMessage = serde_json::from_str(&json).unwrap();
+        assert!(parsed.exit_route.is_none());
+        assert!(parsed.exit_neighbor.is_none());
+        assert!(parsed.upstream_id.is_none());
+        assert_eq!(parsed.id, id);
+
+        // messages omitting the exit fields entirely must also parse
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let object = value.as_object_mut().unwrap();
+        object.remove("exit_route");
+        object.remove("exit_neighbor");
+        object.remove("upstream_id");
+        let parsed: HeartbeatMessage = serde_json::from_value(value).unwrap();
+        assert!(parsed.exit_route.is_none());
+    }
 }
@@ -73,16 +73,14 @@ impl FromStr for WgKey {
     type Err = AltheaTypesError;
 
     fn from_str(s: &str) -> Result<WgKey, Self::Err> {
-        let mut output = [0u8; 32];
-
-        if s.len() != 44 {
-            return Err(base64::DecodeError::InvalidLength.into());
-        }
-
-        match base64::decode_config_slice(s, base64::STANDARD, &mut output) {
-            Ok(_) => Ok(WgKey(output)),
-            Err(e) => Err(e.into()),
-        }
+        // decode into a vec rather than directly into the key, a string that decodes to
+        // more than 32 bytes must be rejected, not overflow the output
+        let decoded = base64::decode_config(s, base64::STANDARD)?;
+        let output: [u8; 32] = match decoded.try_into() {
+            Ok(output) => output,
+            Err(decoded) => return Err(AltheaTypesError::WgKeyLength(decoded.len())),
+        };
+        Ok(WgKey(output))
     }
 }
 
@@ -103,7 +101,7 @@ impl<'de> Visitor<'de> for WgKeyVisitor {
     fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             formatter,
-            "expects a valid base64-encoded string with length of 44"
+            "expects a valid base64-encoded string that decodes to 32 bytes"
         )
     }
 
@@ -147,6 +145,32 @@ mod tests {
         assert!(WgKey::from_str(bad_key2).is_err());
     }
 
+    #[test]
+    fn test_wgkey_decoded_length() {
+        // valid base64, but too short to be a key
+        match WgKey::from_str("AAAA") {
+            Err(AltheaTypesError::WgKeyLength(3)) => {}
+            res => panic!("Unexpected result {res:?}"),
+        }
+        // the right length as a string, but decodes to 33 bytes
+        let long_key = "A".repeat(44);
+        match WgKey::from_str(&long_key) {
+            Err(AltheaTypesError::WgKeyLength(33)) => {}
+            res => panic!("Unexpected result {res:?}"),
+        }
+        // invalid base64 characters
+        match WgKey::from_str("8BeCExnthLe5ou0EYec5jNqJ/PduZ1x2o7lpXJOp!Xk=") {
+            Err(AltheaTypesError::WgParseError(_)) => {}
+            res => panic!("Unexpected result {res:?}"),
+        }
+        // and malformed keys are rejected on deserialization too
+        assert!(serde_json::from_str::<WgKey>("\"AAAA\"").is_err());
+        assert!(
+            serde_json::from_str::<WgKey>("\"8BeCExnthLe5ou0EYec5jNqJ/PduZ1x2o7lpXJOpgXk=\"")
+                .is_ok()
+        );
+    }
+
     #[test]
     fn test_wgkey_to_string() {
         let key = WgKey::from_str("8BeCExnthLe5ou0EYec5jNqJ/PduZ1x2o7lpXJOpgXk=").unwrap();
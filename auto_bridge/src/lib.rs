@@ -23,6 +23,7 @@ pub use error::TokenBridgeError;
 pub static UNISWAP_GAS_LIMIT: u128 = 150_000;
 pub static ERC20_GAS_LIMIT: u128 = 40_000;
 pub static XDAI_FUNDS_UNLOCK_GAS: u128 = 180_000;
+pub static XDAI_RELAY_TOKENS_GAS: u128 = 80_000;
 /// Minimum transfer is $5 dai which has 18 decimal precision
 pub static MINIMUM_DAI_TO_SEND: u128 = 2_000_000_000_000_000_000;
 /// Minimum transfer is $15 USDC which has 6 decimal precision
@@ -77,6 +78,33 @@ pub struct WithdrawEvent {
     pub txid: Uint256,
 }
 
+/// An xDai -> Ethereum bridge withdraw, this is a transaction sent on the xDai chain to the
+/// bridge contract calling relayTokens(address) with the amount to withdraw as the tx value.
+/// The funds arrive on Ethereum as Dai once the validator signatures are submitted on the
+/// Eth side of the bridge
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct XdaiBridgeWithdraw {
+    /// The xDai bridge contract on the xDai chain, this is the tx destination
+    pub contract: Address,
+    /// The address the funds will arrive at on Ethereum
+    pub dest_address: Address,
+    /// The amount of xDai to withdraw, sent as the tx value
+    pub amount: Uint256,
+    /// The encoded relayTokens(address) call
+    pub payload: Vec<u8>,
+}
+
+impl XdaiBridgeWithdraw {
+    pub fn new(xdai_bridge_on_xdai: Address, dest_address: Address, amount: Uint256) -> Self {
+        XdaiBridgeWithdraw {
+            contract: xdai_bridge_on_xdai,
+            dest_address,
+            amount,
+            payload: get_payload_for_relay_tokens(dest_address),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct TokenBridge {
     pub xdai_web3: Web3,
@@ -314,6 +342,11 @@ pub fn get_payload_for_funds_unlock(data: &HelperWithdrawInfo) -> Vec<u8> {
     .unwrap()
 }
 
+/// Encodes the relayTokens(address) call used to withdraw from xDai to Ethereum
+pub fn get_payload_for_relay_tokens(dest_address: Address) -> Vec<u8> {
+    encode_call("relayTokens(address)", &[dest_address.into()]).unwrap()
+}
+
 pub const XDAI_BRIDGE_ON_ETH_ADDRESS: &str = "0x4aa42145Aa6Ebf72e164C9bBC74fbD3788045016";
 pub const HELPER_ON_XDAI_ADDRESS: &str = "0x6A92e97A568f5F58590E8b1f56484e6268CdDC51";
 pub const XDAI_BRIDGE_ON_XDAI_ADDRESS: &str = "0x7301CFA0e1756B71869E93d4e4Dca5c7d0eb0AA6";
@@ -368,15 +401,15 @@ pub async fn encode_relaytokens(
     amount: Uint256,
     timeout: Duration,
 ) -> Result<(), TokenBridgeError> {
-    let payload = encode_call("relayTokens(address)", &[dest_address.into()]).unwrap();
+    let withdraw = XdaiBridgeWithdraw::new(bridge.xdai_bridge_on_xdai, dest_address, amount);
     let options = Vec::new();
 
     let tx = bridge
         .xdai_web3
         .prepare_transaction(
-            bridge.xdai_bridge_on_xdai,
-            payload,
-            amount,
+            withdraw.contract,
+            withdraw.payload,
+            withdraw.amount,
             bridge.eth_privatekey,
             options,
         )
@@ -819,6 +852,32 @@ mod tests {
         });
     }
 
+    /// Checks the relayTokens(address) call used for xDai -> Eth withdraws against the known
+    /// function selector and abi encoding of the destination address
+    #[test]
+    fn test_relay_tokens_encoding() {
+        let dest_address: Address = "0xffcbadeb2a7cc87563e22a8fb4ee120eb73b2d82"
+            .parse()
+            .unwrap();
+        let amount: Uint256 = 580000000000000000000_u128.into();
+
+        let withdraw = XdaiBridgeWithdraw::new(
+            default_bridge_addresses().xdai_bridge_on_xdai,
+            dest_address,
+            amount,
+        );
+
+        assert_eq!(
+            withdraw.contract,
+            XDAI_BRIDGE_ON_XDAI_ADDRESS.parse::<Address>().unwrap()
+        );
+        assert_eq!(withdraw.amount, amount);
+        assert_eq!(
+            bytes_to_hex_str(&withdraw.payload),
+            "5d1e9307000000000000000000000000ffcbadeb2a7cc87563e22a8fb4ee120eb73b2d82"
+        );
+    }
+
     /// This tests that the function 'get_payload_for_funds_unlock' is working correctly and generating the correct bytes to use.
     /// To test this, we look at a successful transaction on etherscan.io, and compare the raw input to that generated by this function.
     #[test]
@@ -14,11 +14,19 @@ extern crate log;
 pub mod parsing;
 pub mod structs;
 
-use crate::parsing::{read_babel_sync, validate_preamble};
+use crate::parsing::{get_router_id_sync, read_babel_sync, validate_preamble};
 use crate::structs::{BabelMonitorError, Route};
-use parsing::{get_local_fee_sync, parse_interfaces_sync, parse_neighs_sync, parse_routes_sync};
+use ipnetwork::IpNetwork;
+use parsing::{
+    find_route_for_prefix_sync, get_local_fee_sync, parse_dump_lenient_sync, parse_interfaces_sync,
+    parse_neighs_sync, parse_routes_by_prefix_and_neighbor_sync, parse_routes_sync,
+};
+use std::collections::HashMap;
 use std::error::Error as ErrorTrait;
 use std::fmt::Debug;
+use std::fmt::Display;
+use std::io::BufRead;
+use std::io::BufReader;
 use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Write;
@@ -26,11 +34,18 @@ use std::iter::Iterator;
 use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
 use std::str::FromStr;
 use std::str::{self};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use structs::{BabeldInterfaceConfig, Interface, Neighbor};
+use std::time::Instant;
+use structs::{BabelState, BabeldInterfaceConfig, Interface, Neighbor};
 
 /// we want to ceed the cpu just long enough for Babel
 /// to finish what it's doing and warp up it's write
@@ -40,6 +55,46 @@ use structs::{BabeldInterfaceConfig, Interface, Neighbor};
 /// job
 const SLEEP_TIME: Duration = Duration::from_millis(10);
 
+/// The default for the largest response we will accumulate from babel before giving up on
+/// finding a terminator, a full dump on a large mesh is a few megabytes
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 16_000_000;
+
+/// see set_max_response_size
+static MAX_RESPONSE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_RESPONSE_SIZE);
+
+/// Sets the largest response, in bytes, that will be read from babel. A babeld that never sends a
+/// terminator, or another local process impersonating babel, could otherwise grow the response
+/// until we run out of memory
+pub fn set_max_response_size(bytes: usize) {
+    MAX_RESPONSE_SIZE.store(bytes, Ordering::Relaxed);
+}
+
+/// The default for the longest we will spend reading a single response from babel
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// see set_read_timeout, stored in milliseconds
+static READ_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_READ_TIMEOUT.as_millis() as u64);
+
+/// Sets the longest we will spend reading a single response. The socket timeout only bounds each
+/// individual read, a babeld that stalls partway through a response, or trickles it out, could
+/// otherwise hold up the calling loop far longer than that
+pub fn set_read_timeout(timeout: Duration) {
+    READ_TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// The default number of times writing a command is retried when the socket accepts only part of it
+/// or is not ready for writing
+pub const DEFAULT_WRITE_RETRIES: usize = 3;
+
+/// see set_write_retries
+static WRITE_RETRIES: AtomicUsize = AtomicUsize::new(DEFAULT_WRITE_RETRIES);
+
+/// Sets how many times writing a command is retried after a short or blocked write before the
+/// command is reported as failed
+pub fn set_write_retries(retries: usize) {
+    WRITE_RETRIES.store(retries, Ordering::Relaxed);
+}
+
 pub fn find_babel_val(val: &str, line: &str) -> Result<String, BabelMonitorError> {
     let mut iter = line.split(' ');
     while let Some(entry) = iter.next() {
@@ -74,6 +129,63 @@ where
     }
 }
 
+/// Finds a field and parses it with the given function, on failure the error names the field
+/// and the bad value so that a parse failure can be traced back to the part of the dump it came from
+fn find_and_parse_babel_field<T, E, F>(
+    val: &str,
+    line: &str,
+    parse: F,
+) -> Result<T, BabelMonitorError>
+where
+    E: Display,
+    F: FnOnce(&str) -> Result<T, E>,
+{
+    let string_val = find_babel_val(val, line)?;
+    match parse(&string_val) {
+        Ok(parsed_val) => Ok(parsed_val),
+        Err(e) => {
+            warn!("Error parsing {} from {} with {}", val, line, e);
+            Err(BabelMonitorError::FieldParseError(
+                val.to_string(),
+                string_val,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// Finds and parses a decimal u16 field such as a metric
+pub fn find_babel_val_u16(val: &str, line: &str) -> Result<u16, BabelMonitorError> {
+    find_and_parse_babel_field(val, line, |v| v.parse::<u16>())
+}
+
+/// Finds and parses a decimal u32 field such as a price or fee
+pub fn find_babel_val_u32(val: &str, line: &str) -> Result<u32, BabelMonitorError> {
+    find_and_parse_babel_field(val, line, |v| v.parse::<u32>())
+}
+
+/// Finds and parses a floating point field such as an rtt
+pub fn find_babel_val_f32(val: &str, line: &str) -> Result<f32, BabelMonitorError> {
+    find_and_parse_babel_field(val, line, |v| v.parse::<f32>())
+}
+
+/// Sums the prices along a path, for example a route price plus the exit price to get the end
+/// to end price of exit traffic. A hostile hop may advertise an absurd price such as u32::MAX so
+/// the sum saturates rather than wrapping, a warning is logged when that happens
+pub fn sum_path_price<I: IntoIterator<Item = u64>>(prices: I) -> u64 {
+    let mut total: u64 = 0;
+    for price in prices {
+        total = match total.checked_add(price) {
+            Some(v) => v,
+            None => {
+                warn!("Path price overflowed! saturating at {}", u64::MAX);
+                return u64::MAX;
+            }
+        };
+    }
+    total
+}
+
 /// Opens a tcpstream to the babel management socket using a standard timeout
 /// for both the open and read operations
 pub fn open_babel_stream(
@@ -82,117 +194,349 @@ pub fn open_babel_stream(
 ) -> Result<TcpStream, BabelMonitorError> {
     let socket_string = format!("[::1]:{babel_port}");
     trace!("About to open Babel socket using {}", socket_string);
-    let socket: SocketAddr = socket_string.parse().unwrap();
+    let socket: SocketAddr = socket_string.parse()?;
+    let mut stream = TcpStream::connect_timeout(&socket, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    start_connection(&mut stream)?;
+    Ok(stream)
+}
+
+/// Opens a babel stream like open_babel_stream and also returns babel's router id as
+/// reported in the preamble, for use in verify_router_id
+pub fn open_babel_stream_with_router_id(
+    babel_port: u16,
+    timeout: Duration,
+) -> Result<(TcpStream, String), BabelMonitorError> {
+    let socket: SocketAddr = format!("[::1]:{babel_port}").parse()?;
     let mut stream = TcpStream::connect_timeout(&socket, timeout)?;
     stream.set_read_timeout(Some(timeout))?;
     stream.set_write_timeout(Some(timeout))?;
+    let preamble = start_connection(&mut stream)?;
+    let router_id = get_router_id_sync(&preamble)?;
+    Ok((stream, router_id))
+}
 
-    // Consumes the automated Preamble and validates configuration api version
+/// Consumes the automated Preamble and validates configuration api version, then discards
+/// anything else waiting on the socket. Returns the preamble
+fn start_connection<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+) -> Result<String, BabelMonitorError> {
     info!("Starting babel connection");
-    let result = read_babel(&mut stream, String::new(), 0)?;
-    let preamble = result;
-    validate_preamble(preamble)?;
+    let preamble = read_babel(stream)?;
+    validate_preamble(preamble.clone())?;
+    flush_buffer(stream)?;
+    Ok(preamble)
+}
+
+/// Opens a unix domain socket to the babel management interface, this is preferable to
+/// open_babel_stream when babeld is configured with a local socket since it avoids exposing
+/// the management interface over tcp. Every function in this crate that takes a stream works
+/// with either stream type
+#[cfg(unix)]
+pub fn open_babel_unix_stream(
+    path: &Path,
+    timeout: Duration,
+) -> Result<UnixStream, BabelMonitorError> {
+    trace!("About to open Babel socket at {}", path.display());
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    start_connection(&mut stream)?;
     Ok(stream)
 }
 
-/// Read function, you should always pass an empty string to the previous contents field
-/// it's used when the function does not find a babel terminator and needs to recurse to get
-/// the full message
-fn read_babel(
-    stream: &mut TcpStream,
-    previous_contents: String,
-    depth: usize,
+/// A stream that can be temporarily switched to non blocking mode, this is what allows
+/// flush_buffer to read only the bytes that are already waiting without blocking for more
+pub trait NonBlockingStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()>;
+}
+
+impl NonBlockingStream for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl NonBlockingStream for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+/// Discards any bytes already waiting on the stream, returning how many were dropped. Residual
+/// output from a previous partial or timed out response would otherwise be stitched onto the
+/// next response and corrupt its parse. This is done on every new connection and before every
+/// command sent with run_command
+pub fn flush_buffer<S: Read + NonBlockingStream>(
+    stream: &mut S,
+) -> Result<usize, BabelMonitorError> {
+    stream.set_nonblocking(true)?;
+    let mut buffer = [0u8; 4096];
+    let mut discarded = 0;
+    let res = loop {
+        match stream.read(&mut buffer) {
+            // the other side has closed the connection, nothing more to drain
+            Ok(0) => break Ok(()),
+            Ok(bytes) => discarded += bytes,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => break Err(e),
+        }
+    };
+    stream.set_nonblocking(false)?;
+    res?;
+    if discarded > 0 {
+        warn!("Discarded {} stale bytes from the babel socket", discarded);
+    }
+    Ok(discarded)
+}
+
+/// Reads a full response from babel, up to and including the terminating ok, bad or no line
+fn read_babel<S: Read>(stream: &mut S) -> Result<String, BabelMonitorError> {
+    read_babel_limited(
+        stream,
+        MAX_RESPONSE_SIZE.load(Ordering::Relaxed),
+        Duration::from_millis(READ_TIMEOUT_MS.load(Ordering::Relaxed)),
+    )
+}
+
+/// Returns true if this line, with or without its newline, ends a babel response. Babel may pad
+/// its output with null bytes so those are ignored along with whitespace
+fn is_terminator(line: &[u8]) -> bool {
+    let is_padding = |b: &u8| b.is_ascii_whitespace() || *b == 0;
+    let start = line
+        .iter()
+        .position(|b| !is_padding(b))
+        .unwrap_or(line.len());
+    let end = line
+        .iter()
+        .rposition(|b| !is_padding(b))
+        .map_or(start, |i| i + 1);
+    matches!(&line[start..end], b"ok" | b"bad" | b"no")
+}
+
+/// read_babel with an explicit maximum response size and timeout, once the response grows past max_size
+/// or the timeout passes without a terminator the partial response is dropped and an error returned.
+/// The stream should be flushed with flush_buffer or dropped before it is used again.
+///
+/// The response is accumulated as raw bytes and only decoded once the terminator has been found, a
+/// multibyte codepoint may be split across two reads and decoding each read on its own would fail
+fn read_babel_limited<S: Read>(
+    stream: &mut S,
+    max_size: usize,
+    timeout: Duration,
 ) -> Result<String, BabelMonitorError> {
-    trace!(
-        "starting read babel with {} and {}",
-        previous_contents,
-        depth
-    );
+    let start = Instant::now();
     // 500kbytes / 0.5mbyte
     const BUFFER_SIZE: usize = 500_000;
-    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, stream);
+    let mut data: Vec<u8> = Vec::new();
+    // where the line currently being read starts, a read may stop partway through a line
+    let mut line_start = 0;
+    let mut retries = 0;
 
-    let result = stream.read(&mut buffer);
-
-    if let Err(e) = result {
-        if e.kind() == ErrorKind::WouldBlock {
+    loop {
+        let wait = match reader.read_until(b'\n', &mut data) {
+            // the stream has closed after a final terminator that had no newline
+            Ok(0) if data.len() > line_start && is_terminator(&data[line_start..]) => break,
+            // end of stream, babel may still be writing
+            Ok(0) => true,
+            Ok(_) => {
+                if data.ends_with(b"\n") {
+                    if is_terminator(&data[line_start..]) {
+                        break;
+                    }
+                    line_start = data.len();
+                }
+                false
+            }
             // response is not yet on the wire wait for it
+            Err(e) if e.kind() == ErrorKind::WouldBlock => true,
+            Err(e) => return Err(e.into()),
+        };
+
+        if data.len() > max_size {
+            error!(
+                "Babel response exceeded {} bytes without a terminator, dropping it",
+                max_size
+            );
+            return Err(BabelMonitorError::ResponseTooLarge(max_size));
+        }
+        if start.elapsed() > timeout {
+            warn!(
+                "Babel read timed out after {}ms! {}",
+                timeout.as_millis(),
+                String::from_utf8_lossy(&data)
+            );
+            return Err(BabelMonitorError::ReadTimeout(start.elapsed()));
+        }
+        if wait {
+            // It's possible we caught babel in the middle of writing to the socket
+            // if we don't see a terminator we either have an error in Babel or an error
+            // in our code for expecting one. So it's safe for us to keep trying and building
+            // a larger response until we see one, up to a limit to prevent waiting forever
+            if retries > 50 {
+                warn!("Babel read timed out! {}", String::from_utf8_lossy(&data));
+                return Err(BabelMonitorError::ReadTimeout(start.elapsed()));
+            }
+            retries += 1;
             thread::sleep(SLEEP_TIME);
-            return read_babel(stream, previous_contents, depth + 1);
-        } else {
-            return Err(e.into());
         }
     }
 
-    let bytes = result?;
-    let full_buffer = bytes == BUFFER_SIZE;
-
-    let output = String::from_utf8(buffer.to_vec());
-    if let Err(e) = output {
-        return Err(BabelMonitorError::TcpError(format!("{e:?}")));
-    }
-    let output = output?;
+    // a response that is not utf8 was still read in full, this is babel output we can't parse
+    let output = String::from_utf8(data)?;
     let output = output.trim_matches(char::from(0));
     trace!(
         "Babel monitor got {} bytes with the message {}",
-        bytes,
+        output.len(),
         output
     );
 
-    // It's possible we caught babel in the middle of writing to the socket
-    // if we don't see a terminator we either have an error in Babel or an error
-    // in our code for expecting one. So it's safe for us to keep trying and building
-    // a larger response until we see one. We terminate after 5 tries
-    // There is also the possible case that our buffer is full, in that case recurse
-    // with no retry limit immediately as we can simply go and read more
-    let full_message = previous_contents + output;
-    let babel_data = read_babel_sync(&full_message);
-    if depth > 50 {
-        // prevent infinite recursion in error cases
-        warn!("Babel read timed out! {}", output);
-        return Err(BabelMonitorError::ReadFailed(
-            "Babel read timed out!".to_string(),
+    match read_babel_sync(output) {
+        Ok(babel_data) => Ok(babel_data),
+        Err(e) => {
+            warn!("Babel read failed! {} {:?}", output, e);
+            Err(BabelMonitorError::ReadFailed(format!("{e:?}")))
+        }
+    }
+}
+
+/// Writes a single command to the babeld management interface and flushes it, so that the command is
+/// guaranteed to be on the wire before we start waiting for a response, a command left sitting in a
+/// buffer would otherwise deadlock the read
+fn write_command<W: Write>(stream: &mut W, cmd: &str) -> Result<(), BabelMonitorError> {
+    write_command_with_retries(stream, cmd, WRITE_RETRIES.load(Ordering::Relaxed))
+}
+
+/// write_command with an explicit retry count. A socket may accept only part of a command or report
+/// that it is not ready, in either case we wait a little longer each time and write the remainder,
+/// up to `retries` times before giving up
+fn write_command_with_retries<W: Write>(
+    stream: &mut W,
+    cmd: &str,
+    retries: usize,
+) -> Result<(), BabelMonitorError> {
+    let bytes = cmd.as_bytes();
+    let mut written = 0;
+    let mut attempts = 0;
+    while written < bytes.len() {
+        match stream.write(&bytes[written..]) {
+            Ok(n) => {
+                written += n;
+                if written == bytes.len() {
+                    break;
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) => {}
+            Err(e) => {
+                return Err(BabelMonitorError::CommandFailed(
+                    cmd.to_string(),
+                    format!("{e:?}"),
+                ))
+            }
+        }
+        if attempts >= retries {
+            return Err(BabelMonitorError::CommandFailed(
+                cmd.to_string(),
+                format!(
+                    "Only wrote {} of {} bytes after {} retries",
+                    written,
+                    bytes.len(),
+                    retries
+                ),
+            ));
+        }
+        attempts += 1;
+        thread::sleep(SLEEP_TIME * attempts as u32);
+    }
+    if let Err(e) = stream.flush() {
+        return Err(BabelMonitorError::CommandFailed(
+            cmd.to_string(),
+            format!("Flush failed {e:?}"),
         ));
-    } else if full_buffer {
-        // our buffer is full, we should recurse right away
-        warn!("Babel read larger than buffer! Consider increasing it's size");
-        return read_babel(stream, full_message, depth);
-    } else if let Err(BabelMonitorError::NoTerminator(_)) = babel_data {
-        // our buffer was not full but we also did not find a terminator,
-        // we must have caught babel while it was interrupted (only really happens
-        // in single cpu situations)
-        thread::sleep(SLEEP_TIME);
-        info!("we didn't get the whole message yet, trying again");
-        return read_babel(stream, full_message, depth + 1);
-    } else if let Err(e) = babel_data {
-        // some other error
-        warn!("Babel read failed! {} {:?}", output, e);
-        return Err(BabelMonitorError::ReadFailed(format!("{e:?}")));
-    }
-    let babel_data = babel_data?;
-
-    Ok(babel_data)
+    }
+    Ok(())
 }
 
 /// Runs a command on the babeld management interface, returns the full return string of the command
 /// this function will return an error if the command fails to write to the socket, but the command itself
-/// may still fail, you should check the output using read_babel_sync in addition to other parse functions
-pub fn run_command(stream: &mut TcpStream, cmd: &str) -> Result<String, BabelMonitorError> {
+/// may still fail, you should check the output using read_babel_sync in addition to other parse functions.
+///
+/// Every public function in this crate that takes a stream is a single request and response, it writes
+/// its command and reads until the terminator before returning. Babel answers commands strictly in order
+/// so a stream must not be used by two callers at once, see SharedBabelStream
+pub fn run_command<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+    cmd: &str,
+) -> Result<String, BabelMonitorError> {
     info!("Running babel command {}", cmd);
+    // anything left over from a previous timed out or failed read would be taken as our response
+    flush_buffer(stream)?;
     let cmd = format!("{cmd}\n");
-    let bytes = cmd.as_bytes().to_vec();
-    let out = stream.write_all(&bytes);
+    write_command(stream, &cmd)?;
+
+    info!("Command write succeeded, returning output");
+    read_babel(stream)
+}
 
-    match out {
-        Ok(_) => {
-            info!("Command write succeeded, returning output");
-            read_babel(stream, String::new(), 0)
+/// A babel management connection that can be shared between threads. If a second caller wrote its
+/// command while the first was still reading, the two responses would be crossed. Each call here holds
+/// the connection for the whole request and response so callers are served one at a time, each
+/// getting the response to its own command
+pub struct SharedBabelStream<S> {
+    stream: Arc<Mutex<S>>,
+}
+
+impl<S> Clone for SharedBabelStream<S> {
+    fn clone(&self) -> Self {
+        SharedBabelStream {
+            stream: self.stream.clone(),
+        }
+    }
+}
+
+impl<S: Read + Write + NonBlockingStream> SharedBabelStream<S> {
+    pub fn new(stream: S) -> Self {
+        SharedBabelStream {
+            stream: Arc::new(Mutex::new(stream)),
         }
-        Err(e) => Err(BabelMonitorError::CommandFailed(cmd, format!("{e:?}"))),
+    }
+
+    /// run_command with exclusive use of the connection
+    pub fn run_command(&self, cmd: &str) -> Result<String, BabelMonitorError> {
+        self.with(|stream| run_command(stream, cmd))
+    }
+
+    /// Runs any of the stream functions in this crate with exclusive use of the connection,
+    /// for example `shared.with(parse_routes)`
+    pub fn with<T, F>(&self, f: F) -> Result<T, BabelMonitorError>
+    where
+        F: FnOnce(&mut S) -> Result<T, BabelMonitorError>,
+    {
+        // a caller that panicked mid command leaves the stream in an unknown state, report
+        // that to everyone after it rather than panicking them too
+        let mut stream = match self.stream.lock() {
+            Ok(stream) => stream,
+            Err(_) => {
+                return Err(BabelMonitorError::TcpError(
+                    "Babel stream poisoned by a failed caller, reopen it".to_string(),
+                ))
+            }
+        };
+        f(&mut stream)
     }
 }
 
-pub fn parse_interfaces(stream: &mut TcpStream) -> Result<Vec<Interface>, BabelMonitorError> {
+pub fn parse_interfaces<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+) -> Result<Vec<Interface>, BabelMonitorError> {
     let output = run_command(stream, "dump")?;
 
     let babel_output = output;
@@ -200,7 +544,9 @@ pub fn parse_interfaces(stream: &mut TcpStream) -> Result<Vec<Interface>, BabelM
 }
 
 /// Gets this routers local fee, what the router charges for bandwidth. The unit is wei (1*10-18 of a dollar) per byte
-pub fn get_local_fee(stream: &mut TcpStream) -> Result<u32, BabelMonitorError> {
+pub fn get_local_fee<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+) -> Result<u32, BabelMonitorError> {
     let output = run_command(stream, "dump")?;
 
     let babel_output = output;
@@ -208,7 +554,10 @@ pub fn get_local_fee(stream: &mut TcpStream) -> Result<u32, BabelMonitorError> {
 }
 
 /// Sets this routers local fee, what the router charges for bandwidth. The unit is wei (1*10-18 of a dollar) per byte
-pub fn set_local_fee(stream: &mut TcpStream, new_fee: u32) -> Result<(), BabelMonitorError> {
+pub fn set_local_fee<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+    new_fee: u32,
+) -> Result<(), BabelMonitorError> {
     let result = run_command(stream, &format!("fee {new_fee}"))?;
 
     let _out = result;
@@ -218,7 +567,10 @@ pub fn set_local_fee(stream: &mut TcpStream, new_fee: u32) -> Result<(), BabelMo
 /// Sets the metric factor for babel. This is a weighting value used to decide if this router should select
 /// routes based on price or quality of service. A higher value will cause the router to prefer routes with
 /// higher quailty of service, a lower value will cause the router to prefer routes with lower price.
-pub fn set_metric_factor(stream: &mut TcpStream, new_factor: u32) -> Result<(), BabelMonitorError> {
+pub fn set_metric_factor<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+    new_factor: u32,
+) -> Result<(), BabelMonitorError> {
     let result = run_command(stream, &format!("metric-factor {new_factor}"))?;
 
     let _out = result;
@@ -227,8 +579,8 @@ pub fn set_metric_factor(stream: &mut TcpStream, new_factor: u32) -> Result<(),
 
 /// Sets the interval at which Babel will update it's routes from the kernel routing table. If set to zero Babel will only recieve
 /// updates from the kernel as changes are made and will never perform a full dump.
-pub fn set_kernel_check_interval(
-    stream: &mut TcpStream,
+pub fn set_kernel_check_interval<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
     kernel_check_interval: Option<Duration>,
 ) -> Result<(), BabelMonitorError> {
     let interval = match kernel_check_interval {
@@ -266,8 +618,8 @@ fn build_interface_config_string(config: BabeldInterfaceConfig) -> String {
 
 /// Adds an interface to babel to monitor, neighbors will be discovered on this interface and routes will be advertised
 /// optionally this interface can have it's own configuration parameters
-pub fn monitor(
-    stream: &mut TcpStream,
+pub fn monitor<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
     iface: &str,
     options: BabeldInterfaceConfig,
 ) -> Result<(), BabelMonitorError> {
@@ -282,8 +634,8 @@ pub fn monitor(
     Ok(())
 }
 
-pub fn redistribute_ip(
-    stream: &mut TcpStream,
+pub fn redistribute_ip<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
     ip: &IpAddr,
     allow: bool,
 ) -> Result<String, BabelMonitorError> {
@@ -295,10 +647,13 @@ pub fn redistribute_ip(
     let result = run_command(stream, &command)?;
 
     let _out = result;
-    read_babel(stream, String::new(), 0)
+    read_babel(stream)
 }
 
-pub fn unmonitor(stream: &mut TcpStream, iface: &str) -> Result<(), BabelMonitorError> {
+pub fn unmonitor<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+    iface: &str,
+) -> Result<(), BabelMonitorError> {
     let command = format!("flush interface {iface}");
     let iface = iface.to_string();
     let result = run_command(stream, &command)?;
@@ -308,23 +663,66 @@ pub fn unmonitor(stream: &mut TcpStream, iface: &str) -> Result<(), BabelMonitor
     Ok(())
 }
 
-pub fn parse_neighs(stream: &mut TcpStream) -> Result<Vec<Neighbor>, BabelMonitorError> {
+pub fn parse_neighs<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+) -> Result<Vec<Neighbor>, BabelMonitorError> {
     let result = run_command(stream, "dump")?;
 
     let output = result;
     parse_neighs_sync(output)
 }
 
-pub fn parse_routes(stream: &mut TcpStream) -> Result<Vec<Route>, BabelMonitorError> {
+pub fn parse_routes<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+) -> Result<Vec<Route>, BabelMonitorError> {
     let result = run_command(stream, "dump")?;
 
     let babel_out = result;
     parse_routes_sync(babel_out)
 }
 
+/// Dumps babel once and parses the interfaces, neighbors and routes from that dump, tolerating
+/// individual malformed lines. Per line parse errors are returned alongside everything that did
+/// parse, only a failure to talk to babel at all is an error
+pub fn dump_all_lenient<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+) -> Result<(BabelState, Vec<BabelMonitorError>), BabelMonitorError> {
+    let output = run_command(stream, "dump")?;
+    Ok(parse_dump_lenient_sync(&output))
+}
+
+/// Same as parse_routes but returns a map keyed by (prefix, neigh_ip), use this instead of keying
+/// on the route id which babel may reuse
+pub fn parse_routes_by_prefix_and_neighbor<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+) -> Result<HashMap<(IpNetwork, IpAddr), Route>, BabelMonitorError> {
+    let result = run_command(stream, "dump")?;
+
+    let babel_out = result;
+    parse_routes_by_prefix_and_neighbor_sync(babel_out)
+}
+
+/// Gets the route to a single prefix, see find_route_for_prefix_sync
+pub fn get_route_for_prefix<S: Read + Write + NonBlockingStream>(
+    stream: &mut S,
+    prefix: IpNetwork,
+) -> Result<Option<Route>, BabelMonitorError> {
+    let babel_out = run_command(stream, "dump")?;
+    find_route_for_prefix_sync(&babel_out, prefix)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parsing::get_interface_up_changes;
+    use crate::parsing::parse_event;
+    use crate::parsing::parse_interface_line;
+    use crate::parsing::parse_neigh_field;
+    use crate::parsing::parse_route_line;
+    use crate::parsing::verify_router_id;
+    use crate::structs::BabelEvent;
+    use crate::structs::LinkQuality;
+    use crate::structs::RouteMetric;
 
     static TABLE: &str =
 "local fee 1024\n\
@@ -381,6 +779,19 @@ ok\n";
 
     static PRICE_LINE: &str = "local price 1024";
 
+    static FEE_LINE: &str = "local fee 42";
+
+    #[test]
+    fn babel_val_whole_token() {
+        assert_eq!(find_babel_val("metric", ROUTE_LINE).unwrap(), "1306");
+        assert_eq!(find_babel_val("refmetric", ROUTE_LINE).unwrap(), "0");
+        // refmetric ahead of metric must not be matched as a substring of it
+        let reordered =
+            "add route 14f06d8 prefix 10.28.20.151/32 refmetric 0 metric 1306 price 4008";
+        assert_eq!(find_babel_val("metric", reordered).unwrap(), "1306");
+        assert!(find_babel_val("etric", reordered).is_err());
+    }
+
     #[test]
     fn line_parse() {
         assert_eq!(find_babel_val("metric", XROUTE_LINE).unwrap(), "0");
@@ -420,6 +831,37 @@ ok\n";
         assert_eq!(find_babel_val("price", PRICE_LINE).unwrap(), "1024");
     }
 
+    #[test]
+    fn router_id_verify() {
+        let router_id = get_router_id_sync(PREAMBLE).unwrap();
+        assert_eq!(router_id, "ba:27:eb:ff:fe:09:06:dd");
+        assert!(verify_router_id(&router_id, "ba:27:eb:ff:fe:09:06:dd"));
+        assert!(!verify_router_id(&router_id, "e6:95:6e:ff:fe:44:c4:12"));
+        assert!(get_router_id_sync("ALTHEA 0.1\nok\n").is_err());
+    }
+
+    #[test]
+    fn typed_line_parse() {
+        assert_eq!(find_babel_val_u16("metric", XROUTE_LINE).unwrap(), 0);
+        assert_eq!(
+            find_babel_val_u32("fee", PROBLEM_ROUTE_LINE).unwrap(),
+            354600
+        );
+        assert_eq!(find_babel_val_f32("rtt", NEIGH_LINE).unwrap(), 29.264);
+
+        // a malformed value is reported along with the field it came from
+        let bad_line = "add route 14f06d8 metric 1x9 refmetric 0";
+        match find_babel_val_u16("metric", bad_line) {
+            Err(BabelMonitorError::FieldParseError(field, val, _)) => {
+                assert_eq!(field, "metric");
+                assert_eq!(val, "1x9");
+            }
+            res => panic!("Unexpected result {res:?}"),
+        }
+        let e = find_babel_val_u16("metric", bad_line).unwrap_err();
+        assert!(e.to_string().contains("metric"));
+    }
+
     #[test]
     fn neigh_parse() {
         let neighs = parse_neighs_sync(TABLE.to_string()).unwrap();
@@ -430,6 +872,51 @@ ok\n";
         assert_eq!(neigh.id, "14f19a8");
     }
 
+    #[test]
+    fn neigh_link_quality() {
+        let neigh = parse_neighs_sync(NEIGH_LINE.to_string()).unwrap().remove(0);
+        // every hello heard but the rtt pushes the cost past excellent
+        assert_eq!(neigh.link_quality(), LinkQuality::Good);
+
+        let mut excellent = neigh.clone();
+        excellent.cost = 256;
+        assert_eq!(excellent.link_quality(), LinkQuality::Excellent);
+
+        let mut lossy = neigh.clone();
+        lossy.reach = 0x9007;
+        assert_eq!(lossy.link_quality(), LinkQuality::Poor);
+
+        let mut dead = neigh.clone();
+        dead.reach = 0;
+        assert_eq!(dead.link_quality(), LinkQuality::Dead);
+        dead.reach = 0xffff;
+        dead.cost = u16::MAX;
+        assert_eq!(dead.link_quality(), LinkQuality::Dead);
+    }
+
+    #[test]
+    fn neigh_parse_radix() {
+        assert_eq!(parse_neigh_field("reach", NEIGH_LINE).unwrap(), 0xffff);
+        assert_eq!(parse_neigh_field("rxcost", NEIGH_LINE).unwrap(), 256);
+        assert_eq!(parse_neigh_field("cost", NEIGH_LINE).unwrap(), 1306);
+
+        // a hex cost must be reported as a format change, not silently misread or skipped
+        let hex_cost_line = "add neighbour 14f05f0 address fe80::e9d0:498f:6c61:be29 if wlan0 \
+         reach ffff rxcost 1ff txcost 256 rtt 29.264 rttcost 1050 cost 1306";
+        let res = parse_neighs_sync(hex_cost_line.to_string());
+        assert!(matches!(
+            res,
+            Err(BabelMonitorError::RadixMismatch(field, val, 10)) if field == "rxcost" && val == "1ff"
+        ));
+        let prefixed_cost_line =
+            "add neighbour 14f05f0 address fe80::e9d0:498f:6c61:be29 if wlan0 \
+         reach ffff rxcost 256 txcost 0x100 rtt 29.264 rttcost 1050 cost 1306";
+        assert!(matches!(
+            parse_neighs_sync(prefixed_cost_line.to_string()),
+            Err(BabelMonitorError::RadixMismatch(..))
+        ));
+    }
+
     #[test]
     fn route_parse() {
         let routes = parse_routes_sync(TABLE.to_string()).unwrap();
@@ -437,6 +924,173 @@ ok\n";
 
         let route = routes.first().unwrap();
         assert_eq!(route.price, 3072);
+        assert_eq!(route.from_prefix.as_deref(), Some("0.0.0.0/0"));
+        let route = routes.last().unwrap();
+        assert_eq!(route.from_prefix.as_deref(), Some("::/0"));
+    }
+
+    #[test]
+    fn connection_errors_distinguished() {
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        assert!(BabelMonitorError::from(refused).is_connection_error());
+        assert!(BabelMonitorError::NoTerminator("add route".to_string()).is_connection_error());
+        assert!(!BabelMonitorError::ReadFailed("bad".to_string()).is_connection_error());
+        assert!(!parse_routes_sync("add route garbage\nok\n".to_string())
+            .unwrap_err()
+            .is_connection_error());
+    }
+
+    #[test]
+    fn route_cmp_by_metric() {
+        let routes = parse_routes_sync(TABLE.to_string()).unwrap();
+        let best = routes.iter().min_by(|a, b| a.cmp_by_metric(b)).unwrap();
+        assert!(routes.iter().all(|r| best.metric <= r.metric));
+
+        let mut a = routes[0].clone();
+        let mut b = routes[0].clone();
+        a.metric = RouteMetric::Finite(100);
+        b.metric = RouteMetric::Finite(200);
+        assert_eq!(a.cmp_by_metric(&b), std::cmp::Ordering::Less);
+        // equal metrics fall back to the cheaper route
+        b.metric = RouteMetric::Finite(100);
+        a.price = 20;
+        b.price = 10;
+        assert_eq!(a.cmp_by_metric(&b), std::cmp::Ordering::Greater);
+        // and then to the lower prefix
+        b.price = 20;
+        a.prefix = "10.0.0.2/32".parse().unwrap();
+        b.prefix = "10.0.0.1/32".parse().unwrap();
+        assert_eq!(a.cmp_by_metric(&b), std::cmp::Ordering::Greater);
+        assert_eq!(a.cmp_by_metric(&a), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn monitor_event_parse() {
+        assert!(matches!(
+            parse_event(PROBLEM_ROUTE_LINE),
+            Some(BabelEvent::AddRoute(route)) if route.id == "241fee0"
+        ));
+        assert!(matches!(
+            parse_event(NEIGH_LINE),
+            Some(BabelEvent::AddNeighbour(neigh)) if neigh.id == "14f05f0"
+        ));
+        let flush_route =
+            "flush route 14f06d8 prefix 10.28.20.151/32 from 0.0.0.0/0 installed yes \
+         id ba:27:eb:ff:fe:c1:2d:d5 metric 65535 price 4008 refmetric 0 via \
+         fe80::e9d0:498f:6c61:be29 if wlan0";
+        assert!(matches!(
+            parse_event(flush_route),
+            Some(BabelEvent::FlushRoute(id)) if id == "14f06d8"
+        ));
+        let flush_neigh = "flush neighbour 14f05f0 address fe80::e9d0:498f:6c61:be29 if wlan0";
+        assert!(matches!(
+            parse_event(flush_neigh),
+            Some(BabelEvent::FlushNeighbour(id)) if id == "14f05f0"
+        ));
+        assert!(parse_event(XROUTE_LINE).is_none());
+        assert!(parse_event(IFACE_LINE).is_none());
+        assert!(parse_event("ok").is_none());
+
+        // flushes in a dump must not be mistaken for entries
+        let dump =
+            format!("{PROBLEM_ROUTE_LINE}\n{flush_route}\n{NEIGH_LINE}\n{flush_neigh}\nok\n");
+        assert_eq!(parse_routes_sync(dump.clone()).unwrap().len(), 1);
+        assert_eq!(parse_neighs_sync(dump).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn route_reachability() {
+        let routes = parse_routes_sync(TABLE.to_string()).unwrap();
+        let mut route = routes.first().unwrap().clone();
+        assert!(route.is_reachable());
+
+        route.metric = u16::MAX.into();
+        assert!(!route.is_reachable());
+    }
+
+    #[test]
+    fn route_metric_sentinel() {
+        assert_eq!(RouteMetric::from(u16::MAX), RouteMetric::Infinite);
+        assert_eq!(
+            RouteMetric::from(u16::MAX - 1),
+            RouteMetric::Finite(u16::MAX - 1)
+        );
+        assert_eq!(u16::from(RouteMetric::Infinite), u16::MAX);
+        assert!(RouteMetric::Finite(u16::MAX - 1) < RouteMetric::Infinite);
+        assert!(RouteMetric::Finite(1) < RouteMetric::Finite(2));
+
+        // arithmetic never crosses between reachable and unreachable
+        let big = RouteMetric::Finite(u16::MAX - 10);
+        assert_eq!(
+            big.saturating_add(100),
+            RouteMetric::Finite(RouteMetric::MAX_FINITE)
+        );
+        assert_eq!(
+            RouteMetric::Infinite.saturating_sub(100),
+            RouteMetric::Infinite
+        );
+        assert_eq!(
+            RouteMetric::Finite(50).saturating_sub(100),
+            RouteMetric::Finite(0)
+        );
+
+        let route = parse_routes_sync(TABLE.to_string()).unwrap()[0].clone();
+        assert_eq!(route.metric, RouteMetric::Finite(1596));
+        let retracted = "add route 14f06d8 prefix 10.28.20.151/32 from 0.0.0.0/0 installed yes \
+                         id ba:27:eb:ff:fe:c1:2d:d5 metric 65535 price 4008 fee 4008 refmetric 0 \
+                         full-path-rtt 18.674 via fe80::e9d0:498f:6c61:be29 if wlan0";
+        assert_eq!(
+            parse_route_line(retracted).unwrap().metric,
+            RouteMetric::Infinite
+        );
+    }
+
+    #[test]
+    fn route_parse_by_prefix_and_neighbor() {
+        // both routes share an id but have differing prefixes, keying by id would lose one
+        let table = "add route 14f0820 prefix 10.28.7.7/32 from 0.0.0.0/0 installed yes id \
+ba:27:eb:ff:fe:5b:fe:c7 metric 1596 price 3072 fee 3072 refmetric 638 full-path-rtt 22.805 via \
+fe80::e914:2335:a76:bda3 if wlan0\n\
+add route 14f0820 prefix 10.28.20.151/32 from 0.0.0.0/0 installed yes id ba:27:eb:ff:fe:c1:2d:d5 \
+metric 817 price 4008 fee 4008 refmetric 0 full-path-rtt 18.674 via fe80::e914:2335:a76:bda3 if wlan0\n\
+ok\n";
+        let routes = parse_routes_by_prefix_and_neighbor_sync(table.to_string()).unwrap();
+        assert_eq!(routes.len(), 2);
+        let neigh: IpAddr = "fe80::e914:2335:a76:bda3".parse().unwrap();
+        let route = routes
+            .get(&("10.28.20.151/32".parse().unwrap(), neigh))
+            .unwrap();
+        assert_eq!(route.price, 4008);
+        let route = routes
+            .get(&("10.28.7.7/32".parse().unwrap(), neigh))
+            .unwrap();
+        assert_eq!(route.price, 3072);
+
+        let routes = parse_routes_by_prefix_and_neighbor_sync(TABLE.to_string()).unwrap();
+        assert_eq!(routes.len(), 5);
+    }
+
+    #[test]
+    fn route_for_prefix() {
+        // the installed route is preferred over the one listed after it
+        let route = find_route_for_prefix_sync(TABLE, "10.28.7.7/32".parse().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(route.id, "14f0820");
+        assert!(route.installed);
+
+        // with no installed route the first one listed is returned
+        let prefix = "fdc5:5bcb:24ac:b35a:4b7f:146a:a2a1:bdc4/128"
+            .parse()
+            .unwrap();
+        let route = find_route_for_prefix_sync(TABLE, prefix).unwrap().unwrap();
+        assert_eq!(route.id, "241fee0");
+
+        assert!(
+            find_route_for_prefix_sync(TABLE, "10.28.7.8/32".parse().unwrap())
+                .unwrap()
+                .is_none()
+        );
     }
 
     #[test]
@@ -455,11 +1109,99 @@ ok\n";
         let iface = interfaces.get(4).unwrap();
         assert!(iface.up);
         assert!(iface.ipv6.is_some());
+        assert!(iface.ipv4.is_none());
+
+        // a malformed address is not mistaken for a missing one
+        let bad_line = "add interface wlan0 up true ipv6 fe80::1a8b:ec1:8542:1bd8 ipv4 10.28.119";
+        assert!(parse_interface_line(bad_line).is_err());
+        assert_eq!(
+            parse_interface_line(IFACE_LINE).unwrap().ipv4,
+            Some("10.28.119.131".parse().unwrap())
+        );
+
+        // but it only drops that address, the interface is still listed
+        let interfaces = parse_interfaces_sync(format!("{bad_line}\nok\n")).unwrap();
+        assert_eq!(interfaces.len(), 1);
+        assert_eq!(interfaces[0].name, "wlan0");
+        assert!(interfaces[0].up);
+        assert!(interfaces[0].ipv6.is_some());
+        assert!(interfaces[0].ipv4.is_none());
+    }
+
+    #[test]
+    fn interface_up_changes() {
+        let previous = parse_interfaces_sync(TABLE.to_string()).unwrap();
+        assert!(get_interface_up_changes(&previous, &previous).is_empty());
+
+        let mut current = previous.clone();
+        current[4].up = false;
+        current.push(Interface {
+            name: "wg99".to_string(),
+            up: true,
+            ipv4: None,
+            ipv6: None,
+        });
+        let changes = get_interface_up_changes(&previous, &current);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].name, previous[4].name);
+        assert!(!changes[0].up);
+    }
+
+    #[test]
+    fn dump_lenient_parse() {
+        let bad_route = "add route 14f0999 prefix 10.28.9.9/32 from 0.0.0.0/0 installed yes id \
+                         ba:27:eb:ff:fe:5b:fe:c7 metric notanumber price 3072 fee 3072 refmetric 638 \
+                         full-path-rtt 22.805 via fe80::e914:2335:a76:bda3 if wlan0\n";
+        let dump = format!("{bad_route}{TABLE}");
+        let (state, errors) = parse_dump_lenient_sync(&dump);
+        assert_eq!(state.interfaces.len(), 5);
+        assert_eq!(state.neighbors.len(), 4);
+        assert_eq!(state.routes.len(), 5);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            BabelMonitorError::FieldParseError(field, _, _) if field == "metric"
+        ));
+
+        let (_, errors) = parse_dump_lenient_sync(TABLE);
+        assert!(errors.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_dump() {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("babel-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            conn.write_all(PREAMBLE.as_bytes()).unwrap();
+            let mut reader = BufReader::new(conn.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, "dump\n");
+            conn.write_all(TABLE.as_bytes()).unwrap();
+        });
+
+        let mut stream = open_babel_unix_stream(&path, Duration::from_secs(5)).unwrap();
+        let routes = parse_routes(&mut stream).unwrap();
+        assert_eq!(routes.len(), 5);
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
     }
 
     #[test]
     fn local_fee_parse() {
         assert_eq!(get_local_fee_sync(TABLE.to_string()).unwrap(), 1024);
+        let dump = format!("{PRICE_LINE}\n{FEE_LINE}\nok\n");
+        assert_eq!(get_local_fee_sync(dump).unwrap(), 42);
+        assert!(matches!(
+            get_local_fee_sync(format!("{PRICE_LINE}\nok\n")),
+            Err(BabelMonitorError::LocalFeeNotFound(_))
+        ));
     }
 
     #[test]
@@ -475,8 +1217,367 @@ ok\n";
         assert!(route.full_path_rtt - 22.805 < f32::EPSILON.abs());
     }
 
+    #[test]
+    fn route_without_full_path_rtt() {
+        let line = "add route 14f0640 prefix 10.28.7.7/32 from 0.0.0.0/0 installed yes id \
+                    ba:27:eb:ff:fe:5b:fe:c7 metric 1596 price 3072 fee 3072 refmetric 638 \
+                    via fe80::e914:2335:a76:bda3 if wlan0";
+        let route = parse_route_line(line).unwrap();
+        assert_eq!(route.metric, RouteMetric::Finite(1596));
+        assert_eq!(route.full_path_rtt, 0.0);
+
+        let bad = line.replace("refmetric 638", "refmetric 638 full-path-rtt fast");
+        assert!(matches!(
+            parse_route_line(&bad),
+            Err(BabelMonitorError::FieldParseError(field, _, _)) if field == "full-path-rtt"
+        ));
+    }
+
     #[test]
     fn only_ok_in_output() {
         read_babel_sync("ok\n").unwrap();
     }
+
+    #[test]
+    fn terminator_without_padding() {
+        use std::io::Cursor;
+
+        let read = |response: &[u8]| {
+            read_babel_limited(
+                &mut Cursor::new(response.to_vec()),
+                1000,
+                DEFAULT_READ_TIMEOUT,
+            )
+        };
+        assert_eq!(read(b"ok\n").unwrap(), "ok\n");
+        assert_eq!(
+            read(b"version babeld-1.8.0\nok").unwrap(),
+            "version babeld-1.8.0\nok\n"
+        );
+        // padding left over from a previous response before the terminator
+        assert_eq!(read(b"\0\0ok\n").unwrap(), "ok\n");
+        assert!(matches!(
+            read(b"version babeld-1.8.0\n\0\0no\n"),
+            Err(BabelMonitorError::ReadFailed(_))
+        ));
+        assert!(matches!(
+            read(b"bad"),
+            Err(BabelMonitorError::ReadFailed(_))
+        ));
+        assert!(is_terminator(b"\0ok\0\n"));
+        assert!(!is_terminator(b"nok\n"));
+        assert!(!is_terminator(b"\0\0\n"));
+    }
+
+    #[test]
+    fn path_price_saturates() {
+        assert_eq!(
+            sum_path_price(vec![100, u32::MAX as u64]),
+            100 + u32::MAX as u64
+        );
+        assert_eq!(
+            sum_path_price(vec![u64::MAX - 10, u32::MAX as u64, 5]),
+            u64::MAX
+        );
+        assert_eq!(sum_path_price(Vec::new()), 0);
+    }
+
+    #[test]
+    fn command_flushed_before_read() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (conn, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(conn.try_clone().unwrap());
+            let mut line = String::new();
+            // only respond once the full command has arrived, if it was left in
+            // a buffer the client read below would time out
+            reader.read_line(&mut line).unwrap();
+            let mut conn = conn;
+            conn.write_all(b"ok\n").unwrap();
+            line
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        run_command(&mut stream, "dump").unwrap();
+        assert_eq!(server.join().unwrap(), "dump\n");
+    }
+
+    #[test]
+    fn stale_bytes_flushed() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let stale = "add route 14f0999 prefix 10.28.9.9/32 from 0.0.0.0/0 installed yes id ba:27:eb:ff:fe:5b";
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            // the tail of a response the client never finished reading
+            conn.write_all(stale.as_bytes()).unwrap();
+            let mut reader = BufReader::new(conn.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            conn.write_all(TABLE.as_bytes()).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        // wait for the stale bytes to arrive
+        let mut peek = [0u8; 1];
+        stream.peek(&mut peek).unwrap();
+        let mut discarded = flush_buffer(&mut stream).unwrap();
+        while discarded < stale.len() {
+            thread::sleep(SLEEP_TIME);
+            discarded += flush_buffer(&mut stream).unwrap();
+        }
+        assert_eq!(discarded, stale.len());
+
+        let routes = parse_routes(&mut stream).unwrap();
+        assert_eq!(routes.len(), 5);
+        assert!(routes.iter().all(|r| r.id != "14f0999"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn stale_bytes_drained_before_command() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+        use std::sync::mpsc::channel;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (go, wait) = channel();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(conn.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            conn.write_all(TABLE.as_bytes()).unwrap();
+            // the late tail of a response the client already gave up on
+            wait.recv().unwrap();
+            conn.write_all(b"ok\n").unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            conn.write_all(TABLE.as_bytes()).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(parse_routes(&mut stream).unwrap().len(), 5);
+        go.send(()).unwrap();
+        // wait for the stale bytes to arrive
+        let mut peek = [0u8; 1];
+        stream.peek(&mut peek).unwrap();
+
+        // the stale terminator is not mistaken for the response to the next command
+        assert_eq!(parse_routes(&mut stream).unwrap().len(), 5);
+        server.join().unwrap();
+    }
+
+    /// A stream that never sends a terminator
+    struct EndlessStream;
+
+    impl Read for EndlessStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let line = b"add route 14f0999 prefix 10.28.9.9/32\n";
+            let len = line.len().min(buf.len());
+            buf[..len].copy_from_slice(&line[..len]);
+            Ok(len)
+        }
+    }
+
+    impl Write for EndlessStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Returns one chunk per read, in order
+    struct ChunkedStream(Vec<Vec<u8>>);
+
+    impl Read for ChunkedStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::Error::from(ErrorKind::WouldBlock));
+            }
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    /// Answers each command written to it with a response naming that command, after a delay
+    /// so that an unsynchronized second caller would have time to interleave
+    #[derive(Default)]
+    struct EchoStream {
+        pending: Vec<u8>,
+    }
+
+    impl Read for EchoStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.pending.is_empty() {
+                return Err(std::io::Error::from(ErrorKind::WouldBlock));
+            }
+            let len = self.pending.len().min(buf.len());
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    impl NonBlockingStream for EchoStream {
+        fn set_nonblocking(&self, _nonblocking: bool) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Write for EchoStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            thread::sleep(Duration::from_millis(20));
+            let cmd = String::from_utf8_lossy(buf);
+            self.pending
+                .extend_from_slice(format!("response to {}\nok\n", cmd.trim()).as_bytes());
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shared_stream_matches_responses() {
+        let shared = SharedBabelStream::new(EchoStream::default());
+        let handles: Vec<_> = ["dump", "interface wg0"]
+            .into_iter()
+            .map(|cmd| {
+                let shared = shared.clone();
+                thread::spawn(move || (cmd, shared.run_command(cmd).unwrap()))
+            })
+            .collect();
+        for handle in handles {
+            let (cmd, response) = handle.join().unwrap();
+            assert_eq!(response, format!("response to {cmd}\nok\n"));
+        }
+    }
+
+    #[test]
+    fn poisoned_shared_stream_errors() {
+        let shared = SharedBabelStream::new(EchoStream::default());
+        let poisoner = shared.clone();
+        let res =
+            thread::spawn(move || poisoner.with(|_| -> Result<(), _> { panic!("failed") })).join();
+        assert!(res.is_err());
+        assert!(matches!(
+            shared.run_command("dump"),
+            Err(BabelMonitorError::TcpError(_))
+        ));
+    }
+
+    /// Accepts at most `per_write` bytes per write and refuses every other write
+    struct SlowWriter {
+        per_write: usize,
+        refuse: bool,
+        written: Vec<u8>,
+    }
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.refuse = !self.refuse;
+            if self.refuse {
+                return Err(std::io::Error::from(ErrorKind::WouldBlock));
+            }
+            let len = buf.len().min(self.per_write);
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn short_writes_retried() {
+        let mut writer = SlowWriter {
+            per_write: 4,
+            refuse: false,
+            written: Vec::new(),
+        };
+        write_command_with_retries(&mut writer, "dump\n", 3).unwrap();
+        assert_eq!(writer.written, b"dump\n");
+
+        // a long command needs more retries than we allow
+        let mut writer = SlowWriter {
+            per_write: 1,
+            refuse: false,
+            written: Vec::new(),
+        };
+        let res = write_command_with_retries(&mut writer, "interface wg0\n", 3);
+        assert!(matches!(res, Err(BabelMonitorError::CommandFailed(..))));
+    }
+
+    #[test]
+    fn invalid_utf8_is_parse_error() {
+        let mut stream = ChunkedStream(vec![b"host caf\xc3\nok\n".to_vec()]);
+        let res = read_babel_limited(&mut stream, 1000, DEFAULT_READ_TIMEOUT);
+        match res {
+            Err(e @ BabelMonitorError::FromUtf8Error(_)) => assert!(!e.is_connection_error()),
+            other => panic!("Expected FromUtf8Error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn split_codepoint_read() {
+        let response = "version babeld-1.8.0\nhost café\nok\n".as_bytes();
+        // split the response in the middle of the two byte é
+        let split = response.iter().position(|b| *b == 0xc3).unwrap() + 1;
+        let mut stream =
+            ChunkedStream(vec![response[..split].to_vec(), response[split..].to_vec()]);
+        let res = read_babel_limited(&mut stream, 1000, DEFAULT_READ_TIMEOUT).unwrap();
+        assert_eq!(res, "version babeld-1.8.0\nhost café\nok\n");
+    }
+
+    /// Sends a line without a terminator on every read, slowly
+    struct TrickleStream;
+
+    impl Read for TrickleStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            thread::sleep(Duration::from_millis(5));
+            let line = b"add route 14f0999 prefix 10.28.9.9/32\n";
+            let len = line.len().min(buf.len());
+            buf[..len].copy_from_slice(&line[..len]);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn stalled_read_times_out() {
+        let start = Instant::now();
+        let res = read_babel_limited(&mut TrickleStream, usize::MAX, Duration::from_millis(50));
+        assert!(matches!(res, Err(BabelMonitorError::ReadTimeout(_))));
+        assert!(res.unwrap_err().is_connection_error());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn oversized_response_errors() {
+        let res = read_babel_limited(&mut EndlessStream, 1000, DEFAULT_READ_TIMEOUT);
+        match res {
+            Err(BabelMonitorError::ResponseTooLarge(limit)) => assert_eq!(limit, 1000),
+            other => panic!("Expected ResponseTooLarge, got {other:?}"),
+        }
+    }
 }
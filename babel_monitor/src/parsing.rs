@@ -3,10 +3,16 @@
 
 use crate::find_and_parse_babel_val;
 use crate::find_babel_val;
+use crate::find_babel_val_f32;
+use crate::find_babel_val_u16;
+use crate::find_babel_val_u32;
+use crate::structs::BabelEvent;
+use crate::structs::BabelState;
 use crate::structs::Interface;
 use crate::structs::Neighbor;
 use crate::structs::{BabelMonitorError, Route};
 use ipnetwork::IpNetwork;
+use std::collections::HashMap;
 use std::iter::Iterator;
 use std::net::IpAddr;
 use std::str::{self};
@@ -19,7 +25,8 @@ pub fn read_babel_sync(output: &str) -> Result<String, BabelMonitorError> {
     for line in output.lines() {
         ret.push_str(line);
         ret.push('\n');
-        match line.trim() {
+        // babel may pad its output with null bytes
+        match line.trim_matches(|c: char| c.is_whitespace() || c == '\0') {
             "ok" => {
                 trace!(
                     "Babel returned ok; full output:\n{}\nEND OF BABEL OUTPUT",
@@ -44,6 +51,56 @@ pub fn read_babel_sync(output: &str) -> Result<String, BabelMonitorError> {
     Err(BabelMonitorError::NoTerminator(ret))
 }
 
+/// The radix babel uses when printing each of the integer fields of an 'add neighbour' line,
+/// reach is a bitmask printed in hex while the costs are printed in decimal. All neighbour
+/// parsing should go through parse_neigh_field so that this table is the only place this is
+/// encoded
+pub const NEIGH_FIELD_RADIX: [(&str, u32); 5] = [
+    ("reach", 16),
+    ("txcost", 10),
+    ("rxcost", 10),
+    ("rttcost", 10),
+    ("cost", 10),
+];
+
+/// Finds and parses an integer field of an 'add neighbour' line using the radix listed for it in
+/// NEIGH_FIELD_RADIX. If the value is not valid in that radix but is a valid number in another
+/// radix babel might plausibly use a RadixMismatch error is returned, so that a change in babel's
+/// output format fails loudly instead of silently producing the wrong values
+pub fn parse_neigh_field(field: &str, line: &str) -> Result<u16, BabelMonitorError> {
+    let radix = match NEIGH_FIELD_RADIX.iter().find(|(name, _)| *name == field) {
+        Some((_, radix)) => *radix,
+        None => {
+            return Err(BabelMonitorError::MiscStringError(format!(
+                "No known radix for neighbour field {field}"
+            )))
+        }
+    };
+    let val = find_babel_val(field, line)?;
+    match u16::from_str_radix(&val, radix) {
+        Ok(parsed) => Ok(parsed),
+        Err(e) => {
+            let hex_digits = val.strip_prefix("0x").unwrap_or(&val);
+            let other_radix_ok = val.starts_with("0x")
+                || (radix == 10 && u16::from_str_radix(hex_digits, 16).is_ok());
+            if other_radix_ok {
+                error!(
+                    "Babel neighbour field {} is {} which is not base {} in {}",
+                    field, val, radix, line
+                );
+                Err(BabelMonitorError::RadixMismatch(
+                    field.to_string(),
+                    val,
+                    radix,
+                ))
+            } else {
+                warn!("Failed to convert {} {:?} {}", field, e, line);
+                Err(e.into())
+            }
+        }
+    }
+}
+
 pub fn validate_preamble(preamble: String) -> Result<(), BabelMonitorError> {
     // Note you have changed the config interface, bump to 1.1 in babel
     if preamble.contains("ALTHEA 0.1") {
@@ -54,31 +111,84 @@ pub fn validate_preamble(preamble: String) -> Result<(), BabelMonitorError> {
     }
 }
 
+/// Returns babel's router id, the 'my-id' line of the connection preamble
+pub fn get_router_id_sync(preamble: &str) -> Result<String, BabelMonitorError> {
+    for line in preamble.lines() {
+        if line.starts_with("my-id") {
+            return find_babel_val("my-id", line);
+        }
+    }
+    Err(BabelMonitorError::VariableNotFound(
+        "my-id".to_string(),
+        preamble.to_string(),
+    ))
+}
+
+/// Checks that babel is running with the router id we expect, a mismatch usually means
+/// a cloned image kept a stale babel id and two routers on the mesh are fighting over it
+pub fn verify_router_id(router_id: &str, expected: &str) -> bool {
+    if router_id.eq_ignore_ascii_case(expected) {
+        true
+    } else {
+        error!(
+            "BABEL ROUTER ID MISMATCH! babeld is running with my-id {} but {} was expected, routing will be unreliable",
+            router_id, expected
+        );
+        false
+    }
+}
+
+/// Parses a single 'add interface' line. Interfaces that are down, or that only have one address
+/// family, omit the ipv4 and ipv6 fields which leaves them as None, an address that is present but
+/// malformed is an error rather than being treated as missing
+pub fn parse_interface_line(entry: &str) -> Result<Interface, BabelMonitorError> {
+    Ok(Interface {
+        name: find_babel_val("interface", entry)?,
+        up: find_and_parse_babel_val("up", entry)?,
+        ipv4: find_optional_addr("ipv4", entry)?,
+        ipv6: find_optional_addr("ipv6", entry)?,
+    })
+}
+
+fn find_optional_addr(val: &str, line: &str) -> Result<Option<IpAddr>, BabelMonitorError> {
+    match find_and_parse_babel_val(val, line) {
+        Ok(addr) => Ok(Some(addr)),
+        Err(BabelMonitorError::VariableNotFound(..)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Like parse_interface_line but a malformed address is logged and left as None, the interface
+/// and its up state are still worth knowing about without it
+fn parse_interface_line_skip_bad_addrs(entry: &str) -> Result<Interface, BabelMonitorError> {
+    let addr = |val: &str| match find_optional_addr(val, entry) {
+        Ok(addr) => addr,
+        Err(e) => {
+            warn!(
+                "Ignoring malformed {} in babel interface {} with {}",
+                val, entry, e
+            );
+            None
+        }
+    };
+    Ok(Interface {
+        name: find_babel_val("interface", entry)?,
+        up: find_and_parse_babel_val("up", entry)?,
+        ipv4: addr("ipv4"),
+        ipv6: addr("ipv6"),
+    })
+}
+
 pub fn parse_interfaces_sync(output: String) -> Result<Vec<Interface>, BabelMonitorError> {
     let mut vector: Vec<Interface> = Vec::new();
     let mut found_interface = false;
     for entry in output.split('\n') {
         if entry.contains("add interface") {
             found_interface = true;
-            let interface = Interface {
-                name: match find_babel_val("interface", entry) {
-                    Ok(val) => val,
-                    Err(_) => continue,
-                },
-                up: match find_and_parse_babel_val("up", entry) {
-                    Ok(val) => val,
-                    Err(_) => continue,
-                },
-                ipv4: match find_and_parse_babel_val("ipv4", entry) {
-                    Ok(val) => Some(val),
-                    Err(_) => None,
-                },
-                ipv6: match find_and_parse_babel_val("ipv6", entry) {
-                    Ok(val) => Some(val),
-                    Err(_) => None,
-                },
-            };
-            vector.push(interface);
+            match parse_interface_line_skip_bad_addrs(entry) {
+                Ok(interface) => vector.push(interface),
+                Err(_) => continue,
+            }
         }
     }
     if vector.is_empty() && found_interface {
@@ -89,72 +199,101 @@ pub fn parse_interfaces_sync(output: String) -> Result<Vec<Interface>, BabelMoni
     Ok(vector)
 }
 
+/// Compares two interface dumps and returns the interfaces from the current dump whose up state
+/// differs from the previous dump. Interfaces that are new in the current dump are not changes
+pub fn get_interface_up_changes<'a>(
+    previous: &[Interface],
+    current: &'a [Interface],
+) -> Vec<&'a Interface> {
+    current
+        .iter()
+        .filter(|iface| {
+            previous
+                .iter()
+                .any(|prev| prev.name == iface.name && prev.up != iface.up)
+        })
+        .collect()
+}
+
+/// Finds the local fee line anywhere in a dump, babeld builds that emit other local values such as
+/// the local price do not always list the fee first
 pub fn get_local_fee_sync(babel_output: String) -> Result<u32, BabelMonitorError> {
-    let fee_entry = match babel_output.split('\n').next() {
-        Some(entry) => entry,
-        // Even an empty string wouldn't yield None
-        None => {
-            return Err(BabelMonitorError::LocalFeeNotFound(String::from(
-                "<Babel output is None>",
-            )))
+    for entry in babel_output.split('\n') {
+        if entry.trim_start().starts_with("local fee") {
+            let fee = find_babel_val_u32("fee", entry)?;
+            trace!("Retrieved a local fee of {}", fee);
+            return Ok(fee);
         }
-    };
+    }
 
-    if fee_entry.contains("local fee") {
-        let fee = find_babel_val("fee", fee_entry)?.parse()?;
-        trace!("Retrieved a local fee of {}", fee);
-        return Ok(fee);
+    let first_line = babel_output.split('\n').next().unwrap_or_default();
+    Err(BabelMonitorError::LocalFeeNotFound(String::from(
+        first_line,
+    )))
+}
+
+/// Parses a single 'add neighbour' line. The rtt fields are optional since the neighbor may not
+/// have rtt enabled
+pub fn parse_neigh_line(entry: &str) -> Result<Neighbor, BabelMonitorError> {
+    Ok(Neighbor {
+        id: find_babel_val("neighbour", entry)?,
+        address: find_and_parse_babel_val("address", entry)?,
+        iface: find_babel_val("if", entry)?,
+        reach: parse_neigh_field("reach", entry)?,
+        txcost: parse_neigh_field("txcost", entry)?,
+        rxcost: parse_neigh_field("rxcost", entry)?,
+        rtt: find_babel_val_f32("rtt", entry).unwrap_or(0.0),
+        rttcost: match parse_neigh_field("rttcost", entry) {
+            Ok(val) => val,
+            Err(e @ BabelMonitorError::RadixMismatch(..)) => return Err(e),
+            Err(_) => 0,
+        },
+        cost: parse_neigh_field("cost", entry)?,
+    })
+}
+
+/// Parses a single line of dump or monitor output into an event, returns None for lines that
+/// do not describe a route or neighbour as well as for lines that fail to parse
+pub fn parse_event(line: &str) -> Option<BabelEvent> {
+    match parse_event_line(line)? {
+        Ok(event) => Some(event),
+        Err(e) => {
+            warn!("Failed to parse babel event {} with {}", line, e);
+            None
+        }
     }
+}
 
-    Err(BabelMonitorError::LocalFeeNotFound(String::from(fee_entry)))
+/// Returns None if this line is not a route or neighbour event at all, otherwise the result of
+/// parsing it, the dump parsers need to tell those cases apart
+fn parse_event_line(line: &str) -> Option<Result<BabelEvent, BabelMonitorError>> {
+    let mut tokens = line.split_whitespace();
+    let event = match (tokens.next()?, tokens.next()?) {
+        ("add", "route") => parse_route_line(line).map(BabelEvent::AddRoute),
+        ("flush", "route") => find_babel_val("route", line).map(BabelEvent::FlushRoute),
+        ("add", "neighbour") => parse_neigh_line(line).map(BabelEvent::AddNeighbour),
+        ("flush", "neighbour") => find_babel_val("neighbour", line).map(BabelEvent::FlushNeighbour),
+        _ => return None,
+    };
+    Some(event)
 }
 
 pub fn parse_neighs_sync(output: String) -> Result<Vec<Neighbor>, BabelMonitorError> {
     let mut vector: Vec<Neighbor> = Vec::with_capacity(5);
     let mut found_neigh = false;
     for entry in output.split('\n') {
-        if entry.contains("add neighbour") {
-            found_neigh = true;
-            let neigh = Neighbor {
-                id: match find_babel_val("neighbour", entry) {
-                    Ok(val) => val,
-                    Err(_) => continue,
-                },
-                address: match find_and_parse_babel_val("address", entry) {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                },
-                iface: match find_babel_val("if", entry) {
-                    Ok(val) => val,
-                    Err(_) => continue,
-                },
-                reach: match find_babel_val("reach", entry) {
-                    Ok(val) => match u16::from_str_radix(&val, 16) {
-                        Ok(val) => val,
-                        Err(e) => {
-                            warn!("Failed to convert reach {:?} {}", e, entry);
-                            continue;
-                        }
-                    },
-                    Err(_) => continue,
-                },
-                txcost: match find_and_parse_babel_val("txcost", entry) {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                },
-                rxcost: match find_and_parse_babel_val("rxcost", entry) {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                },
-                // it's possible that the neighbor does not have rtt enabled
-                rtt: find_and_parse_babel_val("rtt", entry).unwrap_or(0.0),
-                rttcost: find_and_parse_babel_val("rttcost", entry).unwrap_or(0),
-                cost: match find_and_parse_babel_val("cost", entry) {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                },
-            };
-            vector.push(neigh);
+        match parse_event_line(entry) {
+            Some(Ok(BabelEvent::AddNeighbour(neigh))) => {
+                found_neigh = true;
+                vector.push(neigh)
+            }
+            Some(Err(e)) if entry.contains("add neighbour") => {
+                found_neigh = true;
+                if let BabelMonitorError::RadixMismatch(..) = e {
+                    return Err(e);
+                }
+            }
+            _ => continue,
         }
     }
     if vector.is_empty() && found_neigh {
@@ -165,60 +304,42 @@ pub fn parse_neighs_sync(output: String) -> Result<Vec<Neighbor>, BabelMonitorEr
     Ok(vector)
 }
 
+/// Parses a single 'add route' line
+pub fn parse_route_line(entry: &str) -> Result<Route, BabelMonitorError> {
+    Ok(Route {
+        id: find_babel_val("route", entry)?,
+        iface: find_babel_val("if", entry)?,
+        xroute: false,
+        installed: find_babel_val("installed", entry)?.contains("yes"),
+        neigh_ip: find_and_parse_babel_val("via", entry)?,
+        prefix: find_and_parse_babel_val("prefix", entry)?,
+        from_prefix: find_babel_val("from", entry).ok(),
+        metric: find_babel_val_u16("metric", entry)?.into(),
+        refmetric: find_babel_val_u16("refmetric", entry)?,
+        // older versions of babeld don't report the rtt, treat a missing value as zero but still
+        // fail on a value that is present and malformed
+        full_path_rtt: match find_babel_val_f32("full-path-rtt", entry) {
+            Err(BabelMonitorError::VariableNotFound(..)) => 0.0,
+            res => res?,
+        },
+        price: find_babel_val_u32("price", entry)?,
+        fee: find_babel_val_u32("fee", entry)?,
+    })
+}
+
 pub fn parse_routes_sync(babel_out: String) -> Result<Vec<Route>, BabelMonitorError> {
     let mut vector: Vec<Route> = Vec::with_capacity(20);
     let mut found_route = false;
     trace!("Got from babel dump: {}", babel_out);
 
     for entry in babel_out.split('\n') {
-        if entry.contains("add route") {
-            trace!("Parsing 'add route' entry: {}", entry);
-            found_route = true;
-            let route = Route {
-                id: match find_babel_val("route", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-                iface: match find_babel_val("if", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-                xroute: false,
-                installed: match find_babel_val("installed", entry) {
-                    Ok(value) => value.contains("yes"),
-                    Err(_) => continue,
-                },
-                neigh_ip: match find_and_parse_babel_val("via", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-                prefix: match find_and_parse_babel_val("prefix", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-                metric: match find_and_parse_babel_val("metric", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-                refmetric: match find_and_parse_babel_val("refmetric", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-                full_path_rtt: match find_and_parse_babel_val("full-path-rtt", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-                price: match find_and_parse_babel_val("price", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-                fee: match find_and_parse_babel_val("fee", entry) {
-                    Ok(value) => value,
-                    Err(_) => continue,
-                },
-            };
-
-            vector.push(route);
+        match parse_event_line(entry) {
+            Some(Ok(BabelEvent::AddRoute(route))) => {
+                found_route = true;
+                vector.push(route)
+            }
+            Some(Err(_)) if entry.contains("add route") => found_route = true,
+            _ => continue,
         }
     }
     if vector.is_empty() && found_route {
@@ -229,6 +350,76 @@ pub fn parse_routes_sync(babel_out: String) -> Result<Vec<Route>, BabelMonitorEr
     Ok(vector)
 }
 
+/// Parses the interfaces, neighbors and routes out of a single dump. Unlike the individual parse
+/// functions a line that fails to parse does not cause any other part of the dump to be discarded,
+/// every successfully parsed entry is returned along with an error for each line that failed
+pub fn parse_dump_lenient_sync(output: &str) -> (BabelState, Vec<BabelMonitorError>) {
+    let mut state = BabelState::default();
+    let mut errors = Vec::new();
+    for entry in output.split('\n') {
+        let res = if entry.contains("add interface") {
+            parse_interface_line(entry).map(|i| state.interfaces.push(i))
+        } else if entry.contains("add neighbour") {
+            parse_neigh_line(entry).map(|n| state.neighbors.push(n))
+        } else if entry.contains("add route") {
+            parse_route_line(entry).map(|r| state.routes.push(r))
+        } else {
+            Ok(())
+        };
+        if let Err(e) = res {
+            warn!("Failed to parse babel dump line {} with {}", entry, e);
+            errors.push(e);
+        }
+    }
+    (state, errors)
+}
+
+/// Parses the routes table into a map keyed by (prefix, neigh_ip). Babel is free to reuse or
+/// recycle route ids, so the id field is not a reliable key, the destination prefix combined with
+/// the neighbor the route goes through is unique for any given dump
+pub fn parse_routes_by_prefix_and_neighbor_sync(
+    babel_out: String,
+) -> Result<HashMap<(IpNetwork, IpAddr), Route>, BabelMonitorError> {
+    let routes = parse_routes_sync(babel_out)?;
+    let mut map = HashMap::with_capacity(routes.len());
+    for route in routes {
+        let key = (route.prefix, route.neigh_ip);
+        if let Some(old) = map.insert(key, route) {
+            warn!(
+                "Duplicate route for prefix {} via {}, replacing route {}",
+                key.0, key.1, old.id
+            );
+        }
+    }
+    Ok(map)
+}
+
+/// Finds the route to a single prefix in a dump without parsing the rest of the routing table, only
+/// lines for this prefix are parsed. The installed route is returned if there is one, otherwise the
+/// first route babel lists for the prefix
+pub fn find_route_for_prefix_sync(
+    babel_out: &str,
+    prefix: IpNetwork,
+) -> Result<Option<Route>, BabelMonitorError> {
+    let mut fallback = None;
+    for entry in babel_out.split('\n') {
+        if !entry.contains("add route") {
+            continue;
+        }
+        match find_and_parse_babel_val::<IpNetwork>("prefix", entry) {
+            Ok(route_prefix) if route_prefix == prefix => {}
+            _ => continue,
+        }
+        let route = parse_route_line(entry)?;
+        if route.installed {
+            return Ok(Some(route));
+        } else if fallback.is_none() {
+            fallback = Some(route);
+        }
+    }
+    Ok(fallback)
+}
+
 /// In this function we take a route snapshot then loop over the routes list twice
 /// to find the neighbor local address and then the route to the destination
 /// via that neighbor. This could be dramatically more efficient if we had the neighbors
@@ -1,4 +1,5 @@
 use ipnetwork::{IpNetwork, IpNetworkError};
+use std::cmp::Ordering;
 use std::f32;
 use std::fmt::Debug;
 use std::fmt::{Display, Formatter, Result as FmtResult};
@@ -29,6 +30,16 @@ pub enum BabelMonitorError {
     NoRoute(String),
     MiscStringError(String),
     FromUtf8Error(FromUtf8Error),
+    /// A numeric field was not formatted in the radix we expect babel to use for it,
+    /// contains the field name, the value and the expected radix
+    RadixMismatch(String, String, u32),
+    /// A field was found but its value could not be parsed, contains the field name,
+    /// the value and the parse error
+    FieldParseError(String, String, String),
+    /// Babel sent more than the maximum response size without a terminator, contains the limit
+    ResponseTooLarge(usize),
+    /// Babel did not finish its response in time, contains how long we waited
+    ReadTimeout(Duration),
 }
 
 impl From<std::io::Error> for BabelMonitorError {
@@ -101,10 +112,39 @@ impl Display for BabelMonitorError {
             }
             BabelMonitorError::MiscStringError(a) => write!(f, "{a}",),
             BabelMonitorError::FromUtf8Error(a) => write!(f, "{a}",),
+            BabelMonitorError::RadixMismatch(field, val, radix) => write!(
+                f,
+                "Babel field '{field}' has value '{val}' which is not base {radix}, has the babel output format changed?",
+            ),
+            BabelMonitorError::FieldParseError(field, val, e) => {
+                write!(f, "Failed to parse babel field '{field}' with value '{val}': {e}",)
+            }
+            BabelMonitorError::ResponseTooLarge(limit) => write!(
+                f,
+                "Babel response exceeded {limit} bytes without a terminator",
+            ),
+            BabelMonitorError::ReadTimeout(waited) => {
+                write!(f, "Babel did not finish its response after {waited:?}",)
+            }
         }
     }
 }
 
+impl BabelMonitorError {
+    /// True if this error means we could not talk to babeld at all, for example because it is
+    /// down or not responding, as opposed to babeld answering with something we could not parse
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            BabelMonitorError::ReadFunctionError(_)
+                | BabelMonitorError::TcpError(_)
+                | BabelMonitorError::TokioError(_)
+                | BabelMonitorError::NoTerminator(_)
+                | BabelMonitorError::ReadTimeout(_)
+        )
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Interface {
     pub name: String,
@@ -113,21 +153,121 @@ pub struct Interface {
     pub ipv4: Option<IpAddr>,
 }
 
+/// A babel route metric. Babel represents an unreachable (retracted) route with an infinite
+/// metric which it sends as u16::MAX, keeping that sentinel as its own variant means it can't
+/// be confused with a real, if very large, metric. On the wire this is still the plain u16 so
+/// serialized routes are unchanged
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(from = "u16", into = "u16")]
+pub enum RouteMetric {
+    Finite(u16),
+    /// Sorts after every finite metric
+    Infinite,
+}
+
+impl RouteMetric {
+    /// The largest metric that is still reachable, arithmetic on finite metrics saturates here
+    pub const MAX_FINITE: u16 = u16::MAX - 1;
+
+    pub fn is_reachable(&self) -> bool {
+        matches!(self, RouteMetric::Finite(_))
+    }
+
+    /// The metric value if the route is reachable
+    pub fn finite(&self) -> Option<u16> {
+        match self {
+            RouteMetric::Finite(v) => Some(*v),
+            RouteMetric::Infinite => None,
+        }
+    }
+
+    /// Adds to a finite metric, capping the result at MAX_FINITE so that a reachable route
+    /// is never made unreachable. An infinite metric stays infinite
+    pub fn saturating_add(self, rhs: u16) -> RouteMetric {
+        match self {
+            RouteMetric::Finite(v) => {
+                RouteMetric::Finite(v.saturating_add(rhs).min(Self::MAX_FINITE))
+            }
+            RouteMetric::Infinite => RouteMetric::Infinite,
+        }
+    }
+
+    /// Subtracts from a finite metric, an infinite metric stays infinite
+    pub fn saturating_sub(self, rhs: u16) -> RouteMetric {
+        match self {
+            RouteMetric::Finite(v) => RouteMetric::Finite(v.saturating_sub(rhs)),
+            RouteMetric::Infinite => RouteMetric::Infinite,
+        }
+    }
+}
+
+impl From<u16> for RouteMetric {
+    fn from(value: u16) -> Self {
+        if value == u16::MAX {
+            RouteMetric::Infinite
+        } else {
+            RouteMetric::Finite(value)
+        }
+    }
+}
+
+impl From<RouteMetric> for u16 {
+    fn from(value: RouteMetric) -> Self {
+        match value {
+            RouteMetric::Finite(v) => v,
+            RouteMetric::Infinite => u16::MAX,
+        }
+    }
+}
+
+impl Display for RouteMetric {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            RouteMetric::Finite(v) => write!(f, "{v}"),
+            RouteMetric::Infinite => write!(f, "infinity"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Route {
+    /// Babel's internal id for this route, this may be reused or recycled by babel and
+    /// is not a reliable unique key, use (prefix, neigh_ip) for that
     pub id: String,
     pub iface: String,
     pub xroute: bool,
     pub installed: bool,
     pub neigh_ip: IpAddr,
     pub prefix: IpNetwork,
-    pub metric: u16,
+    /// The source prefix of this route, babel lists this as the from field, routes out of older
+    /// babel versions may not have it
+    #[serde(default)]
+    pub from_prefix: Option<String>,
+    pub metric: RouteMetric,
     pub refmetric: u16,
     pub full_path_rtt: f32,
     pub price: u32,
     pub fee: u32,
 }
 
+impl Route {
+    /// Babel represents an unreachable (retracted) route with an infinite metric, a route
+    /// with any other metric can be used
+    pub fn is_reachable(&self) -> bool {
+        self.metric.is_reachable()
+    }
+
+    /// Orders routes from best to worst by metric. full_path_rtt is a float and is not used, ties
+    /// are broken by the lower price and then by the lower prefix address so that the choice between
+    /// otherwise equal routes is deterministic
+    pub fn cmp_by_metric(&self, other: &Route) -> Ordering {
+        self.metric
+            .cmp(&other.metric)
+            .then(self.price.cmp(&other.price))
+            .then(self.prefix.ip().cmp(&other.prefix.ip()))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Neighbor {
     pub id: String,
@@ -141,6 +281,65 @@ pub struct Neighbor {
     pub cost: u16,
 }
 
+/// The cost babel assigns to a neighbour it can no longer reach
+pub const INFINITE_COST: u16 = u16::MAX;
+/// Minimum number of the last 16 hellos that must have been received for an excellent link
+pub const EXCELLENT_LINK_MIN_REACH: u32 = 15;
+/// Maximum cost of an excellent link, a lossless link with a low rtt is costed at 256
+pub const EXCELLENT_LINK_MAX_COST: u16 = 512;
+/// Minimum number of the last 16 hellos that must have been received for a good link
+pub const GOOD_LINK_MIN_REACH: u32 = 12;
+/// Maximum cost of a good link
+pub const GOOD_LINK_MAX_COST: u16 = 2048;
+
+/// A coarse classification of a link to a neighbour, see Neighbor::link_quality
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum LinkQuality {
+    Excellent,
+    Good,
+    Poor,
+    /// No hellos have been heard recently or babel considers the neighbour unreachable
+    Dead,
+}
+
+impl Neighbor {
+    /// Classifies this link from the hello reachability bitmap and babel's computed cost
+    pub fn link_quality(&self) -> LinkQuality {
+        let hellos_received = self.reach.count_ones();
+        if hellos_received == 0 || self.cost == INFINITE_COST {
+            LinkQuality::Dead
+        } else if hellos_received >= EXCELLENT_LINK_MIN_REACH
+            && self.cost <= EXCELLENT_LINK_MAX_COST
+        {
+            LinkQuality::Excellent
+        } else if hellos_received >= GOOD_LINK_MIN_REACH && self.cost <= GOOD_LINK_MAX_COST {
+            LinkQuality::Good
+        } else {
+            LinkQuality::Poor
+        }
+    }
+}
+
+/// A single line of babel output describing a change to the route or neighbour tables, a dump
+/// only contains add events while the monitor command also streams flushes as entries are removed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BabelEvent {
+    AddRoute(Route),
+    /// A route was removed, contains babel's id for the route
+    FlushRoute(String),
+    AddNeighbour(Neighbor),
+    /// A neighbour was removed, contains babel's id for the neighbour
+    FlushNeighbour(String),
+}
+
+/// Everything parsed out of a single babel dump
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BabelState {
+    pub interfaces: Vec<Interface>,
+    pub neighbors: Vec<Neighbor>,
+    pub routes: Vec<Route>,
+}
+
 /// This struct lists config options for babeld, these are applied at startup
 /// it is not complete and only lists options that will probably be used
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -390,6 +390,7 @@ pub fn get_default_settings(
         workers: 2,
         remote_log: false,
         description: "Test environment exit instance".to_string(),
+        region: None,
         payment: PaymentSettings::default(),
         localization: LocalizationSettings::default(),
         network: NetworkSettings::default(),
@@ -134,14 +134,17 @@ fn main() {
     apply_babeld_settings_defaults(
         settings.network.babel_port,
         settings.network.babeld_settings,
+        settings.network.babel_router_id.clone(),
     );
 
     // On Linux static builds we need to probe ssl certs path to be able to
     // do TLS stuff.
     openssl_probe::init_ssl_cert_env_vars();
 
-    // we should remote log if there's an operator address or if logging is enabled
-    let should_remote_log = settings.log.enabled || settings.operator.operator_address.is_some();
+    // we should remote log if there's an operator address or if logging is enabled, unless
+    // the user has opted to always log locally
+    let should_remote_log = (settings.log.enabled || settings.operator.operator_address.is_some())
+        && !settings.log.force_local_logging;
     // if remote logging is disabled, or the NO_REMOTE_LOG env var is set we should use the
     // local logger and log to std-out. Note we don't care what is actually set in NO_REMOTE_LOG
     // just that it is set
@@ -100,6 +100,7 @@ async fn main() {
     apply_babeld_settings_defaults(
         settings.network.babel_port,
         settings.network.babeld_settings,
+        settings.network.babel_router_id.clone(),
     );
 
     // On Linux static builds we need to probe ssl certs path to be able to
@@ -215,7 +216,7 @@ async fn check_startup_balance_and_contract(
 async fn get_registered_users() -> Result<Vec<Identity>, Web3Error> {
     let payment_settings = settings::get_rita_common().payment;
     let our_address = payment_settings.eth_address.expect("No address!");
-    let full_node = get_web3_server();
+    let full_node = get_web3_server().map_err(|e| Web3Error::BadInput(format!("{e}")))?;
     let web3 = web30::client::Web3::new(&full_node, Duration::from_secs(5));
     let contract_address = settings::get_rita_exit()
         .exit_network
@@ -227,7 +228,7 @@ async fn check_balance(
     our_address: Address,
     startup_status: Arc<RwLock<Option<String>>>,
 ) -> Result<(), String> {
-    let full_node = get_web3_server();
+    let full_node = get_web3_server().map_err(|e| format!("{e}"))?;
     let web3 = web30::client::Web3::new(&full_node, Duration::from_secs(5));
     let res = web3.eth_get_balance(our_address).await;
     match res {
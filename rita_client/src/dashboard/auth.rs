@@ -1,26 +1,88 @@
-use actix_web_async::{http::StatusCode, web::Json, HttpResponse};
+use actix_web_async::{http::StatusCode, web::Json, HttpRequest, HttpResponse};
 use clarity::utils::bytes_to_hex_str;
+use rand::Rng;
 use rita_common::{RitaCommonError, KI};
 use settings::set_rita_client;
 use sha3::{Digest, Sha3_512};
 
+/// The salt every router used before salts were generated per router, a router with no salt in
+/// its settings has a password hashed with this
+pub const LEGACY_PASSWORD_SALT: &str = "RitaSalt";
+
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct RouterPassword {
     pub password: String,
+    /// Set by dashboards that fetch /router/password/salt before hashing the password to log in.
+    /// Older dashboards always hash with LEGACY_PASSWORD_SALT so a router with no salt yet keeps
+    /// using that one when they set the password
+    #[serde(default)]
+    pub use_router_salt: bool,
 }
 
-pub async fn set_pass(router_pass: Json<RouterPassword>) -> HttpResponse {
-    debug!("/router/password hit with {:?}", router_pass);
-    let router_pass = router_pass.into_inner();
-    let input_string = router_pass.password.clone() + "RitaSalt";
+/// Returns the salt the dashboard password is hashed with
+fn get_password_salt(salt: Option<String>) -> String {
+    salt.unwrap_or_else(|| LEGACY_PASSWORD_SALT.to_string())
+}
+
+/// Picks the salt a newly set password is stored under. A router keeps its salt once it has one,
+/// the legacy salt is only used when none is stored and the dashboard can't fetch a new one
+fn choose_password_salt(current: Option<String>, use_router_salt: bool) -> Option<String> {
+    match current {
+        Some(salt) => Some(salt),
+        None if use_router_salt => Some(generate_password_salt()),
+        None => None,
+    }
+}
 
-    debug!("Using {} as sha3 512 input", input_string);
+fn generate_password_salt() -> String {
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    bytes_to_hex_str(&salt)
+}
+
+fn hash_password(password: &str, salt: &str) -> String {
     let mut hasher = Sha3_512::new();
-    hasher.update(input_string.as_bytes());
-    let hashed_pass = bytes_to_hex_str(&hasher.finalize());
+    hasher.update(password.as_bytes());
+    hasher.update(salt.as_bytes());
+    bytes_to_hex_str(&hasher.finalize())
+}
+
+/// The dashboard hashes the password before sending it for authentication, so it needs this
+/// router's salt. The salt is not secret and this endpoint is exempt from authentication
+pub async fn get_pass_salt(_req: HttpRequest) -> HttpResponse {
+    let salt = settings::get_rita_client()
+        .network
+        .rita_dashboard_password_salt;
+    HttpResponse::Ok().json(get_password_salt(salt))
+}
+
+/// Rejects passwords that are too short to be used for both the dashboard and ssh login
+fn check_password_strength(password: &str, min_length: usize) -> Result<(), String> {
+    if password.chars().count() < min_length {
+        return Err(format!(
+            "Password must be at least {min_length} characters long"
+        ));
+    }
+    Ok(())
+}
 
+pub async fn set_pass(router_pass: Json<RouterPassword>) -> HttpResponse {
+    debug!("/router/password hit with {:?}", router_pass);
+    let router_pass = router_pass.into_inner();
+    let min_length = settings::get_rita_client()
+        .network
+        .min_dashboard_password_length;
+    if let Err(e) = check_password_strength(&router_pass.password, min_length) {
+        return HttpResponse::BadRequest().json(e);
+    }
     let mut rita_client = settings::get_rita_client();
+    let salt = choose_password_salt(
+        rita_client.network.rita_dashboard_password_salt.clone(),
+        router_pass.use_router_salt,
+    );
+    let hashed_pass = hash_password(&router_pass.password, &get_password_salt(salt.clone()));
+
     rita_client.network.rita_dashboard_password = Some(hashed_pass);
+    rita_client.network.rita_dashboard_password_salt = salt;
     set_rita_client(rita_client);
 
     if let Err(e) = settings::write_config() {
@@ -44,6 +106,10 @@ pub async fn set_pass(router_pass: Json<RouterPassword>) -> HttpResponse {
 
 #[cfg(test)]
 mod tests {
+    use super::{
+        check_password_strength, choose_password_salt, generate_password_salt, get_password_salt,
+        hash_password, LEGACY_PASSWORD_SALT,
+    };
     use clarity::utils::bytes_to_hex_str;
     use hex_literal::hex;
     use sha3::{Digest, Sha3_512};
@@ -60,6 +126,51 @@ mod tests {
         assert_eq!(result, sha3_output.to_vec());
     }
 
+    #[test]
+    fn test_password_strength() {
+        assert!(check_password_strength("", 8).is_err());
+        assert!(check_password_strength("abc", 8).is_err());
+        assert!(check_password_strength("abcdefghij", 8).is_ok());
+        assert!(check_password_strength("abc", 3).is_ok());
+    }
+
+    #[test]
+    fn test_password_salt() {
+        // a legacy install keeps validating against the fixed salt
+        let mut hasher = Sha3_512::new();
+        hasher.update(b"testing12RitaSalt");
+        let legacy_hash = bytes_to_hex_str(&hasher.finalize());
+        assert_eq!(get_password_salt(None), LEGACY_PASSWORD_SALT);
+        assert_eq!(
+            hash_password("testing12", &get_password_salt(None)),
+            legacy_hash
+        );
+
+        // generated salts differ between routers, and so do the hashes of the same password
+        let salt_a = generate_password_salt();
+        let salt_b = generate_password_salt();
+        assert_eq!(salt_a.len(), 32);
+        assert_ne!(salt_a, salt_b);
+        assert_eq!(get_password_salt(Some(salt_a.clone())), salt_a);
+        assert_ne!(
+            hash_password("testing12", &salt_a),
+            hash_password("testing12", &salt_b)
+        );
+
+        // a router with a salt keeps it whichever dashboard sets the password
+        assert_eq!(
+            choose_password_salt(Some(salt_a.clone()), true),
+            Some(salt_a.clone())
+        );
+        assert_eq!(
+            choose_password_salt(Some(salt_a.clone()), false),
+            Some(salt_a)
+        );
+        // without one the legacy salt is used unless the dashboard can fetch a new one
+        assert_eq!(choose_password_salt(None, false), None);
+        assert_eq!(choose_password_salt(None, true).unwrap().len(), 32);
+    }
+
     #[test]
     fn test_hash_to_string() {
         let sha3sum_output = "881c7d6ba98678bcd96e253086c4048c3ea15306d0d13ff48341c6285ee71102a47b6f16e20e4d65c0c3d677be689dfda6d326695609cbadfafa1800e9eb7fc1";
@@ -4,13 +4,62 @@
 use actix_web_async::http::StatusCode;
 use actix_web_async::HttpResponse;
 use actix_web_async::{web::Path, HttpRequest};
+use althea_kernel_interface::KernelInterfaceError;
 use rita_common::{RitaCommonError, KI};
+use std::collections::HashSet;
+
+/// The interface shaped when no lan networks are configured, this is the lan bridge on
+/// our standard firmware
+const DEFAULT_LAN_INTERFACE: &str = "br-lan";
+
+/// Returns the devices the user bandwidth limit should be applied to, in a stable order.
+/// lan_nics holds OpenWrt logical network names such as 'lan', not kernel devices, traffic
+/// for each of those networks passes through the bridge OpenWrt creates for it, br-<network>.
+/// If no lan networks are configured br-lan is shaped
+pub fn bandwidth_limit_interfaces(lan_nics: &HashSet<String>) -> Vec<String> {
+    let mut interfaces: Vec<String> = lan_nics
+        .iter()
+        .map(|network| format!("br-{network}"))
+        .collect();
+    if interfaces.is_empty() {
+        interfaces.push(DEFAULT_LAN_INTERFACE.to_string());
+    }
+    interfaces.sort();
+    interfaces
+}
+
+/// Applies the given bandwidth limit to every lan interface. A failure on one bridge does not
+/// stop the others from being shaped, every failure is reported in the returned error
+pub fn apply_bandwidth_limit(
+    lan_nics: &HashSet<String>,
+    limit: Option<usize>,
+) -> Result<(), KernelInterfaceError> {
+    let mut failures = Vec::new();
+    for iface in bandwidth_limit_interfaces(lan_nics) {
+        if let Err(e) = KI.set_codel_shaping(&iface, limit) {
+            failures.push(format!("{iface}: {e}"));
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(KernelInterfaceError::TrafficControlError(
+            failures.join(", "),
+        ))
+    }
+}
 
 pub async fn get_bandwidth_limit(_req: HttpRequest) -> HttpResponse {
     let val = settings::get_rita_client().network.user_bandwidth_limit;
     HttpResponse::Ok().json(val)
 }
 
+/// Returns the operator provided settings the automatic bandwidth shaper is operating within
+pub async fn get_shaper_settings(_req: HttpRequest) -> HttpResponse {
+    let val = settings::get_rita_client().network.shaper_settings;
+    HttpResponse::Ok().json(val)
+}
+
 pub async fn set_bandwidth_limit(path: Path<String>) -> HttpResponse {
     let value = path.into_inner();
     debug!("Set bandwidth limit!");
@@ -23,7 +72,16 @@ pub async fn set_bandwidth_limit(path: Path<String>) -> HttpResponse {
     } else {
         return HttpResponse::BadRequest().finish();
     }
-    let _res = KI.set_codel_shaping("br-lan", network.user_bandwidth_limit);
+    // don't save a limit that we failed to apply, the user would believe it is in effect
+    if let Err(e) = apply_bandwidth_limit(
+        &rita_client.exit_client.lan_nics,
+        network.user_bandwidth_limit,
+    ) {
+        error!("Failed to apply bandwidth limit with {:?}", e);
+        return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
+            .json(format!("Failed to apply bandwidth limit: {e}"));
+    }
+    let limit = network.user_bandwidth_limit;
     rita_client.network = network;
     settings::set_rita_client(rita_client);
 
@@ -31,5 +89,29 @@ pub async fn set_bandwidth_limit(path: Path<String>) -> HttpResponse {
         return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
             .json(format!("{}", RitaCommonError::SettingsError(e)));
     }
-    HttpResponse::Ok().json(())
+    HttpResponse::Ok().json(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bandwidth_limit_interfaces() {
+        let to_set =
+            |names: &[&str]| -> HashSet<String> { names.iter().map(|s| s.to_string()).collect() };
+        // the lan_nics shipped on our firmware
+        assert_eq!(
+            bandwidth_limit_interfaces(&to_set(&["lan"])),
+            vec!["br-lan".to_string()]
+        );
+        assert_eq!(
+            bandwidth_limit_interfaces(&to_set(&["lan", "guest"])),
+            vec!["br-guest".to_string(), "br-lan".to_string()]
+        );
+        assert_eq!(
+            bandwidth_limit_interfaces(&HashSet::new()),
+            vec!["br-lan".to_string()]
+        );
+    }
 }
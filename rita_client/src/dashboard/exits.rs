@@ -1,6 +1,9 @@
 //! The Exit info endpoint gathers infromation about exit status and presents it to the dashbaord.
 
-use crate::exit_manager::{exit_setup_request, set_selected_exit};
+use crate::exit_manager::exit_switcher::get_exit_selection_summary;
+use crate::exit_manager::{
+    exit_setup_request, get_current_exit, set_selected_exit, take_verification_code_submitted,
+};
 use crate::heartbeat::get_selected_exit_server;
 use crate::RitaClientError;
 use actix_web_async::http::StatusCode;
@@ -16,7 +19,9 @@ use settings::client::{ExitServer, SelectedExit};
 use settings::write_config;
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use std::time::Instant;
 
 #[derive(Serialize)]
 pub struct ExitInfo {
@@ -32,6 +37,14 @@ pub struct GetExitInfo;
 
 const EXIT_PING_TIMEOUT: Duration = Duration::from_millis(200);
 
+/// Minimum time between re-registration requests from the dashboard, each one
+/// sends a setup request to the exit so we don't want it spammed
+const REREGISTRATION_COOLDOWN: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref LAST_REREGISTRATION: Arc<RwLock<Option<Instant>>> = Arc::new(RwLock::new(None));
+}
+
 /// Checks if the provided exit is selected
 fn is_selected(exit: &ExitServer, current_exit: Option<ExitServer>) -> bool {
     match current_exit {
@@ -126,6 +139,13 @@ pub async fn add_exits(new_exits: Json<HashMap<IpAddr, ExitServer>>) -> HttpResp
     HttpResponse::Ok().json(copy)
 }
 
+/// Reports the exit switcher's current selection and the average metrics it is comparing, so that
+/// the dashboard can show why we are on a given exit
+pub async fn get_exit_selection(_req: HttpRequest) -> HttpResponse {
+    debug!("/exits/selection GET hit");
+    HttpResponse::Ok().json(get_exit_selection_summary())
+}
+
 pub async fn get_exit_info(_req: HttpRequest) -> HttpResponse {
     debug!("Exit endpoint hit!");
     match dashboard_get_exit_info() {
@@ -235,3 +255,126 @@ pub async fn verify_on_exit_with_code(path: Path<(String, String)>) -> HttpRespo
     }
     HttpResponse::Ok().json(ret)
 }
+
+/// Returns true if enough time has passed since the last re-registration request
+fn reregistration_allowed(last: Option<Instant>, now: Instant) -> bool {
+    match last {
+        Some(last) => now.saturating_duration_since(last) >= REREGISTRATION_COOLDOWN,
+        None => true,
+    }
+}
+
+/// Resets the state of the given exit to New so that registration starts over, returns
+/// false if the exit is not in the list
+fn reset_exit_state(exits: &mut HashMap<IpAddr, ExitServer>, exit: IpAddr) -> bool {
+    match exits.get_mut(&exit) {
+        Some(server) => {
+            server.info = ExitState::New;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Resets the currently selected exit to New and immediately attempts to register with it
+/// again, this gets routers out of a stale Pending or Denied state. Returns the resulting
+/// exit state
+pub async fn reregister_current_exit(_req: HttpRequest) -> HttpResponse {
+    debug!("/exits/reregister hit");
+    let mut ret = HashMap::new();
+
+    {
+        let mut last = LAST_REREGISTRATION.write().unwrap();
+        let now = Instant::now();
+        if !reregistration_allowed(*last, now) {
+            ret.insert(
+                "error".to_owned(),
+                "Re-registration requested too recently, try again later".to_owned(),
+            );
+            return HttpResponse::build(StatusCode::TOO_MANY_REQUESTS).json(ret);
+        }
+        *last = Some(now);
+    }
+
+    let exit = match get_current_exit() {
+        Some(exit) => exit,
+        None => {
+            ret.insert(
+                "error".to_owned(),
+                "No exit is currently selected".to_owned(),
+            );
+            return HttpResponse::build(StatusCode::BAD_REQUEST).json(ret);
+        }
+    };
+
+    let mut rita_client = settings::get_rita_client();
+    if !reset_exit_state(&mut rita_client.exit_client.exits, exit) {
+        error!("Selected exit {} is not in the exit list", exit);
+        ret.insert(
+            "error".to_owned(),
+            format!("Selected exit {exit} is not in the exit list"),
+        );
+        return HttpResponse::build(StatusCode::BAD_REQUEST).json(ret);
+    }
+    info!("Re-registering to exit {}", exit);
+    settings::set_rita_client(rita_client);
+    // any verification code belonged to the registration we just threw away
+    take_verification_code_submitted();
+
+    if let Err(e) = exit_setup_request(None).await {
+        error!("exit_setup_request() failed with: {:?}", e);
+    }
+
+    match settings::get_rita_client().exit_client.exits.get(&exit) {
+        Some(server) => HttpResponse::Ok().json(server.info.clone()),
+        None => HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
+            .json(format!("Exit {exit} was removed during re-registration")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use althea_types::Identity;
+
+    #[test]
+    fn test_reset_exit_state() {
+        let exit: IpAddr = "fd00::1337".parse().unwrap();
+        let mut exits = HashMap::new();
+        exits.insert(
+            exit,
+            ExitServer {
+                exit_id: Identity {
+                    mesh_ip: exit,
+                    eth_address: "0xd2C5b6dd6ca641BE4c90565b5d3DA34C14949A53"
+                        .parse()
+                        .unwrap(),
+                    wg_public_key: "V9I9yrxAqFqLV+9GeT5pnXPwk4Cxgfvl30Fv8khVGsM="
+                        .parse()
+                        .unwrap(),
+                    nickname: None,
+                },
+                registration_port: 3452,
+                wg_exit_listen_port: 59998,
+                info: ExitState::Denied {
+                    message: "stale".to_string(),
+                },
+            },
+        );
+
+        assert!(reset_exit_state(&mut exits, exit));
+        assert_eq!(exits[&exit].info, ExitState::New);
+        assert!(!reset_exit_state(&mut exits, "fd00::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_reregistration_cooldown() {
+        let now = Instant::now();
+        assert!(reregistration_allowed(None, now));
+        assert!(!reregistration_allowed(Some(now), now));
+        assert!(reregistration_allowed(
+            Some(now),
+            now + REREGISTRATION_COOLDOWN
+        ));
+    }
+}
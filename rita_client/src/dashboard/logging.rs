@@ -33,6 +33,38 @@ pub async fn remote_logging(path: Path<bool>) -> HttpResponse {
     HttpResponse::Ok().json(())
 }
 
+pub async fn get_force_local_logging(_req: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok().json(settings::get_rita_client().log.force_local_logging)
+}
+
+/// Lets the user permanently opt out of remote logging, this overrides the remote logging
+/// that is otherwise enabled by having an operator address
+pub async fn set_force_local_logging(path: Path<bool>) -> HttpResponse {
+    let enabled = path.into_inner();
+    debug!("/remote_logging/force_local/{} hit", enabled);
+
+    let mut rita_client = settings::get_rita_client();
+
+    rita_client.log.force_local_logging = enabled;
+
+    let service_path: String = format!("/etc/init.d/{}", rita_client.app_name);
+
+    settings::set_rita_client(rita_client);
+
+    if let Err(e) = settings::write_config() {
+        return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
+            .json(format!("Failed to write config {e:?}"));
+    }
+
+    // logging is configured at startup so a restart is required for this to take effect
+    if let Err(e) = KI.run_command(service_path.as_str(), &["restart"]) {
+        return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
+            .json(format!("Failed to restart service {e:?}"));
+    }
+
+    HttpResponse::Ok().json(())
+}
+
 pub async fn get_remote_logging_level(_req: HttpRequest) -> HttpResponse {
     let rita_client = settings::get_rita_client();
     let level = &rita_client.log.level;
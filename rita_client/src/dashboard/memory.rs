@@ -0,0 +1,55 @@
+//! Reports jemalloc memory statistics, this is useful for diagnosing memory issues on low ram
+//! devices. Only compiled when rita is built with the jemalloc feature
+
+use actix_web_async::{HttpRequest, HttpResponse};
+use jemalloc_ctl::{epoch, stats};
+
+/// Memory statistics as reported by jemalloc, all values are in bytes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct JemallocStats {
+    /// Bytes allocated by the application
+    pub allocated: usize,
+    /// Bytes in active pages, this is a multiple of the page size and at least allocated
+    pub active: usize,
+    /// Bytes in physically resident data pages mapped by jemalloc
+    pub resident: usize,
+}
+
+pub fn get_jemalloc_stats() -> Result<JemallocStats, jemalloc_ctl::Error> {
+    // jemalloc caches its statistics, advancing the epoch refreshes them
+    epoch::advance()?;
+    Ok(JemallocStats {
+        allocated: stats::allocated::read()?,
+        active: stats::active::read()?,
+        resident: stats::resident::read()?,
+    })
+}
+
+pub async fn get_memory_stats(_req: HttpRequest) -> HttpResponse {
+    debug!("/memory_stats GET hit");
+    match get_jemalloc_stats() {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => {
+            error!("Failed to read jemalloc stats with {}", e);
+            HttpResponse::InternalServerError().json(format!("{e}"))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "jemalloc"))]
+mod tests {
+    use super::*;
+    use jemallocator::Jemalloc;
+
+    // without this the stats describe an allocator nothing is using
+    #[global_allocator]
+    static GLOBAL: Jemalloc = Jemalloc;
+
+    #[test]
+    fn test_read_jemalloc_stats() {
+        let stats = get_jemalloc_stats().unwrap();
+        assert!(stats.allocated > 0);
+        assert!(stats.active >= stats.allocated);
+        assert!(stats.resident >= stats.active);
+    }
+}
@@ -16,6 +16,8 @@ pub mod installation_details;
 pub mod interfaces;
 pub mod localization;
 pub mod logging;
+#[cfg(feature = "jemalloc")]
+pub mod memory;
 pub mod mesh_ip;
 pub mod neighbors;
 pub mod notifications;
@@ -40,6 +42,8 @@ use crate::dashboard::installation_details::*;
 use crate::dashboard::interfaces::*;
 use crate::dashboard::localization::*;
 use crate::dashboard::logging::*;
+#[cfg(feature = "jemalloc")]
+use crate::dashboard::memory::*;
 use crate::dashboard::mesh_ip::*;
 use crate::dashboard::neighbors::*;
 use crate::dashboard::notifications::*;
@@ -55,7 +59,9 @@ use actix_web_async::{web, App, HttpServer};
 use rita_common::dashboard::babel::*;
 use rita_common::dashboard::debts::*;
 use rita_common::dashboard::development::*;
+use rita_common::dashboard::net_version::*;
 use rita_common::dashboard::nickname::*;
+use rita_common::dashboard::node_blacklist::*;
 use rita_common::dashboard::own_info::*;
 use rita_common::dashboard::settings::*;
 use rita_common::dashboard::token_bridge::*;
@@ -67,6 +73,15 @@ use rita_common::network_endpoints::*;
 
 use self::devices_on_lan::get_devices_lan_endpoint;
 
+/// Memory stats are only available when jemalloc is our allocator
+#[cfg(feature = "jemalloc")]
+fn memory_stats_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/memory_stats", web::get().to(get_memory_stats));
+}
+
+#[cfg(not(feature = "jemalloc"))]
+fn memory_stats_routes(_cfg: &mut web::ServiceConfig) {}
+
 pub fn start_client_dashboard(rita_dashboard_port: u16) {
     // dashboard
     thread::spawn(move || {
@@ -91,6 +106,8 @@ pub fn start_client_dashboard(rita_dashboard_port: u16) {
                     .route("/debts/reset", web::post().to(reset_debt))
                     .route("/exits", web::get().to(get_exit_info))
                     .route("/exits", web::post().to(add_exits))
+                    .route("/exits/reregister", web::post().to(reregister_current_exit))
+                    .route("/exits/selection", web::get().to(get_exit_selection))
                     .route("/exits/{name}/register", web::post().to(register_to_exit))
                     .route("/exits/{name}/reset", web::post().to(reset_exit))
                     .route("/exits/{name}/select", web::post().to(select_exit))
@@ -137,8 +154,17 @@ pub fn start_client_dashboard(rita_dashboard_port: u16) {
                         "/remote_logging/level/{level}",
                         web::post().to(remote_logging_level),
                     )
+                    .route(
+                        "/remote_logging/force_local",
+                        web::get().to(get_force_local_logging),
+                    )
+                    .route(
+                        "/remote_logging/force_local/{enabled}",
+                        web::post().to(set_force_local_logging),
+                    )
                     .route("/settings", web::get().to(get_settings))
                     .route("/settings", web::post().to(set_settings))
+                    .route("/settings/reload", web::post().to(reload_settings))
                     .route("/version", web::get().to(version))
                     .route("/wg_public_key", web::get().to(get_wg_public_key))
                     .route("/wifi_settings", web::post().to(set_wifi_multi))
@@ -166,6 +192,19 @@ pub fn start_client_dashboard(rita_dashboard_port: u16) {
                     .route("/blockchain/get", web::get().to(get_system_blockchain))
                     .route("/nickname/get", web::get().to(get_nickname))
                     .route("/nickname/set", web::post().to(set_nickname))
+                    .route("/full_nodes/blacklist", web::get().to(get_node_blacklist))
+                    .route(
+                        "/full_nodes/net_version/revalidate",
+                        web::post().to(revalidate_full_node_net_version),
+                    )
+                    .route(
+                        "/full_nodes/blacklist/clear",
+                        web::post().to(clear_node_blacklist),
+                    )
+                    .route(
+                        "/full_nodes/blacklist/remove",
+                        web::post().to(remove_from_node_blacklist),
+                    )
                     .route(
                         "/low_balance_notification",
                         web::get().to(get_low_balance_notification),
@@ -181,6 +220,7 @@ pub fn start_client_dashboard(rita_dashboard_port: u16) {
                     .route("/router/reboot", web::post().to(reboot_router))
                     .route("/router/update", web::post().to(update_router))
                     .route("/router/password", web::post().to(set_pass))
+                    .route("/router/password/salt", web::get().to(get_pass_salt))
                     .route("/remote_access", web::get().to(get_remote_access_status))
                     .route(
                         "/remote_access/{status}",
@@ -199,6 +239,7 @@ pub fn start_client_dashboard(rita_dashboard_port: u16) {
                     .route("/billing_details", web::get().to(get_billing_details))
                     .route("/billing_details", web::post().to(set_billing_details))
                     .route("/bandwidth_limit", web::get().to(get_bandwidth_limit))
+                    .route("/shaper_settings", web::get().to(get_shaper_settings))
                     .route(
                         "/bandwidth_limit/{limit}",
                         web::post().to(set_bandwidth_limit),
@@ -212,6 +253,7 @@ pub fn start_client_dashboard(rita_dashboard_port: u16) {
                     .route("/phone", web::post().to(set_phone_number))
                     .route("/email", web::get().to(get_email))
                     .route("/email", web::post().to(set_email))
+                    .configure(memory_stats_routes)
             })
             .workers(1)
             .bind(format!("[::0]:{rita_dashboard_port}"))
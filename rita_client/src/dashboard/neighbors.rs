@@ -120,7 +120,7 @@ fn generate_neighbors_list(
             if maybe_exit_route.is_err() {
                 output.push(nonviable_node_info(
                     nickname,
-                    neigh_route.metric,
+                    neigh_route.metric.into(),
                     identity.mesh_ip.to_string(),
                     *identity,
                     neigh.speed_limit,
@@ -134,8 +134,8 @@ fn generate_neighbors_list(
                 nickname: nickname.to_string(),
                 ip: identity.mesh_ip.to_string(),
                 id: *identity,
-                route_metric_to_exit: exit_route.metric,
-                route_metric: neigh_route.metric,
+                route_metric_to_exit: exit_route.metric.into(),
+                route_metric: neigh_route.metric.into(),
                 speed_limit: neigh.speed_limit,
                 total_payments: debt_info.total_payment_received,
                 debt: debt_info.debt,
@@ -146,7 +146,7 @@ fn generate_neighbors_list(
         } else {
             output.push(nonviable_node_info(
                 nickname,
-                neigh_route.metric,
+                neigh_route.metric.into(),
                 identity.mesh_ip.to_string(),
                 *identity,
                 neigh.speed_limit,
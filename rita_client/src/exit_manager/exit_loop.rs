@@ -5,12 +5,13 @@ use crate::exit_manager::{
     add_exits_to_exit_server_list, correct_default_route, exit_status_request, get_client_pub_ipv6,
     get_current_exit, get_exit_list, get_full_selected_exit, get_ready_to_switch_exits,
     get_routes_hashmap, has_exit_changed, linux_setup_exit_tunnel, remove_nat, restore_nat,
-    set_exit_list,
+    set_exit_list, take_verification_code_submitted,
 };
 use crate::traffic_watcher::{query_exit_debts, QueryExitDebts};
 use actix_async::System as AsyncSystem;
 use althea_types::ExitListV2;
 use althea_types::ExitState;
+use babel_monitor::structs::BabelMonitorError;
 use futures::future::join_all;
 use futures::join;
 use rita_common::blockchain_oracle::low_balance;
@@ -68,7 +69,8 @@ pub fn start_exit_manager_loop() {
                                 let babel_port = settings::get_rita_client().network.babel_port;
                                 let routes = match get_babel_routes(babel_port) {
                                     Ok(a) => a,
-                                    Err(_) => {
+                                    Err(e) => {
+                                        log_babel_routes_error(&e);
                                         warn!("No babel routes present to setup an exit");
                                         Vec::new()
                                     }
@@ -208,7 +210,8 @@ pub fn start_exit_manager_loop() {
                                     info!("We are signed up for the selected exit!");
                                     let routes = match get_babel_routes(babel_port) {
                                         Ok(a) => a,
-                                        Err(_) => {
+                                        Err(e) => {
+                                            log_babel_routes_error(&e);
                                             error!("No babel routes present to query exit debts");
                                             thread::sleep(EXIT_LOOP_SPEED);
                                             continue;
@@ -231,6 +234,14 @@ pub fn start_exit_manager_loop() {
                         let mut status_requests = Vec::new();
                         let mut exit_status_requested = false;
                         let servers = { settings::get_rita_client().exit_client.exits };
+                        // the user has entered a verification code, go back to polling pending exits quickly
+                        if take_verification_code_submitted() {
+                            em_state.pending_poll.clear();
+                        }
+                        // forget the backoff of any exit that is no longer pending
+                        em_state.pending_poll.retain(|k, _| {
+                            servers.get(k).map_or(false, |s| s.info.is_pending())
+                        });
                         for (k, s) in servers {
                             match s.info {
                                 // Once one exit is registered, this moves all exits from New -> Registered
@@ -241,9 +252,15 @@ pub fn start_exit_manager_loop() {
                                 },
                                 // For routers that register normally, (not through ops), New -> Pending. In this state, we 
                                 // continue to query until we reach Registered
+                                // while we wait for the user to enter a verification code we back off the polling
                                 ExitState::Pending { .. } => {
-                                    trace!("Exit {} is in state Pending, calling status request", k);
-                                    status_requests.push(exit_status_request(k));
+                                    let backoff = em_state.pending_poll.entry(k).or_default();
+                                    if backoff.should_poll(Instant::now()) {
+                                        trace!("Exit {} is in state Pending, calling status request", k);
+                                        status_requests.push(exit_status_request(k));
+                                    } else {
+                                        trace!("Exit {} is in state Pending, next poll in {:?}", k, backoff.interval());
+                                    }
                                 },
                                 ExitState::Registered { .. } => {
                                     trace!("Exit {} is in state Registered, calling status request", k);
@@ -307,3 +324,13 @@ pub fn start_exit_manager_loop() {
         }
     });
 }
+
+/// Logs why we failed to get babel routes, babeld being down is a different problem from babeld
+/// returning output we can't parse and is worth telling apart in the logs
+fn log_babel_routes_error(e: &BabelMonitorError) {
+    if e.is_connection_error() {
+        warn!("Failed to reach babeld, it may be down: {}", e);
+    } else {
+        error!("Babeld returned routes we could not parse: {}", e);
+    }
+}
@@ -15,45 +15,124 @@ use crate::exit_manager::{get_full_selected_exit, reset_exit_blacklist, set_sele
 use crate::rita_loop::CLIENT_LOOP_TIMEOUT;
 use crate::RitaClientError;
 use althea_types::Identity;
+use babel_monitor::structs::BabelMonitorError;
+use babel_monitor::structs::RouteMetric;
 use babel_monitor::{open_babel_stream, parse_routes, structs::Route};
+use ipnetwork::IpNetwork;
+use rita_common::payment_controller::{get_payment_health, PaymentHealth};
 use rita_common::FAST_LOOP_SPEED;
+use settings::client::ExitServer;
 use settings::client::ExitSwitchingCode;
 use settings::client::SelectedExit;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
 
 use super::get_exit_blacklist;
 
-/// This is the number of metric entries we collect for exit data. Since every tick is 5 sec, and the minimum time we
-/// use an exit without swtiching is 15 mins, this values is 15 * 60/5
+/// This is the default number of metric entries we collect for exit data. Since every tick is 5 sec, and the minimum time we
+/// use an exit without swtiching is 15 mins, this values is 15 * 60/5. Can be overridden with exit_switch_dwell_seconds
 const METRIC_ENTRIES: usize = (15 * 60) / (FAST_LOOP_SPEED.as_secs() as usize);
 
+/// The smallest switching window we will allocate METRIC_VALUES for. METRIC_VALUES is full when its length
+/// reaches its capacity, so a tiny window would treat nearly every tick as the end of a dwell period and let
+/// the exit switcher flap between exits
+const MIN_METRIC_ENTRIES: usize = 3;
+const _: () = assert!(METRIC_ENTRIES >= MIN_METRIC_ENTRIES);
+
+/// The largest switching window we will allocate METRIC_VALUES for, 4 hours of ticks. A longer window
+/// is clamped to this so that a misconfiguration can't balloon memory on constrained devices
+const MAX_METRIC_ENTRIES: usize = (4 * 60 * 60) / (FAST_LOOP_SPEED.as_secs() as usize);
+
 /// This is the threshold we use to ensure that a tracking exit is worth switching to. The average
 /// metric of a tracking exit of a period of 15 mins needs be atleast 50% better than our current exit
-/// to be considered as an exit to switch to
+/// to be considered as an exit to switch to. Can be overridden with tracking_switch_threshold
 const FLAPPING_THRESH: f64 = 0.5;
 
+/// The number of payments in a row that an exit must fail to accept before we treat it as degraded
+const PAYMENT_FAILURE_THRESHOLD: u32 = 3;
+
+/// Added to the metric of an exit that is not accepting our payments. This is large enough that any
+/// healthy exit with a usable route is preferred, while a degraded exit stays selectable if it is the
+/// only one we can reach
+const PAYMENT_FAILURE_PENALTY: u16 = 8000;
+
+/// exit_price_weight is given in millionths of a metric point per unit of price
+const PRICE_WEIGHT_SCALE: u64 = 1_000_000;
+
+/// Exit switches and tracking resets are counted over this window when reporting route flapping to the operator.
+/// Since a switch requires a full window of metrics there can be at most a few dozen switches a day on a healthy router
+pub const SWITCH_COUNT_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
 lazy_static! {
     /// This lazy static tracks metric values of the exit that we potentially consider switching to during every tick.
     /// To switch, this vector needs to be full of values from a single exit.
     pub static ref METRIC_VALUES: Arc<RwLock<Vec<u16>>> =
-        Arc::new(RwLock::new(Vec::with_capacity(METRIC_ENTRIES)));
+        Arc::new(RwLock::new(Vec::with_capacity(metric_entries_for_dwell(
+            settings::get_rita_client().exit_client.exit_switch_dwell_seconds
+        ))));
 
     pub static ref EXIT_TRACKER: Arc<RwLock<HashMap<IpAddr, ExitTracker>>> = Arc::new(RwLock::new(HashMap::new()));
 }
 
+/// Returns the number of METRIC_VALUES entries to allocate for a configured dwell time in seconds,
+/// falling back to METRIC_ENTRIES when none is configured
+fn metric_entries_for_dwell(dwell_seconds: Option<u64>) -> usize {
+    let requested = match dwell_seconds {
+        Some(secs) => (secs / FAST_LOOP_SPEED.as_secs()) as usize,
+        None => METRIC_ENTRIES,
+    };
+    clamp_metric_entries(requested)
+}
+
+/// Converts the configured tracking switch threshold percentage into the fraction used by
+/// worth_switching_tracking_exit, falling back to FLAPPING_THRESH when none is configured
+fn tracking_switch_threshold(percent: Option<u16>) -> f64 {
+    match percent {
+        Some(percent) => percent as f64 / 100.0,
+        None => FLAPPING_THRESH,
+    }
+}
+
+/// Returns the number of METRIC_VALUES entries to allocate for the requested switching window,
+/// clamped between MIN_METRIC_ENTRIES and MAX_METRIC_ENTRIES
+fn clamp_metric_entries(requested: usize) -> usize {
+    if requested < MIN_METRIC_ENTRIES {
+        warn!(
+            "Exit switching window of {} entries is too small, raising to {}",
+            requested, MIN_METRIC_ENTRIES
+        );
+        MIN_METRIC_ENTRIES
+    } else if requested > MAX_METRIC_ENTRIES {
+        error!(
+            "Exit switching window of {} entries is too large, clamping to {}",
+            requested, MAX_METRIC_ENTRIES
+        );
+        MAX_METRIC_ENTRIES
+    } else {
+        requested
+    }
+}
+
 /// This struct contains information about each exit in the cluster. It stores a running total of metric values. This is used to
 /// calculate the average metric, and this value wont overflow since we track metric values for no more than 15 mins.
 /// Since babel advertises several routes to a given exit, we need to find the route with the best metric and add it to this total. Last_added_metric
 /// helps with this by keeping track of what we previosly added to running_total, so that if we come across a better metric to the exit, we
 /// subtract this from the total and add the new better value.
+///
+/// Unlike the metric totals the switch history is not reset when tracking is reset, it records every time we switched to
+/// or started tracking this exit over the last SWITCH_COUNT_WINDOW so that an exit we keep flapping to can be reported to the operator
 #[derive(Default, Debug)]
 pub struct ExitTracker {
     last_added_metric: u16,
     running_total: u64,
     ticker_len: u16,
+    switch_times: VecDeque<Instant>,
 }
 
 impl ExitTracker {
@@ -62,8 +141,86 @@ impl ExitTracker {
             last_added_metric,
             running_total,
             ticker_len,
+            switch_times: VecDeque::new(),
         }
     }
+
+    fn record_switch(&mut self, now: Instant) {
+        while let Some(oldest) = self.switch_times.front() {
+            if now.saturating_duration_since(*oldest) < SWITCH_COUNT_WINDOW {
+                break;
+            }
+            self.switch_times.pop_front();
+        }
+        self.switch_times.push_back(now);
+    }
+
+    /// How many times we have switched to or started tracking this exit in the SWITCH_COUNT_WINDOW before now
+    fn switch_count(&self, now: Instant) -> u32 {
+        self.switch_times
+            .iter()
+            .filter(|time| now.saturating_duration_since(**time) < SWITCH_COUNT_WINDOW)
+            .count() as u32
+    }
+
+    /// The average babel metric observed for this exit since tracking was last reset, None if
+    /// we have not observed it yet
+    fn average_metric(&self) -> Option<u16> {
+        if self.ticker_len == 0 {
+            None
+        } else {
+            Some((self.running_total / self.ticker_len as u64) as u16)
+        }
+    }
+}
+
+/// A read only view of the exit switcher state, this lets the dashboard report which exit is selected,
+/// its metric and degradation, and the average metrics of the other exits we compared it against
+#[derive(Debug, Clone, Serialize)]
+pub struct ExitSelectionSummary {
+    pub selected_exit: SelectedExit,
+    pub exit_averages: Vec<(IpAddr, u16)>,
+}
+
+pub fn get_exit_selection_summary() -> ExitSelectionSummary {
+    ExitSelectionSummary {
+        selected_exit: get_full_selected_exit(),
+        exit_averages: get_exit_metric_averages(),
+    }
+}
+
+/// Returns the average babel metric of every exit in EXIT_TRACKER that we have observed since the
+/// last tracking reset
+pub fn get_exit_metric_averages() -> Vec<(IpAddr, u16)> {
+    summarize_exit_tracker(&EXIT_TRACKER.read().unwrap())
+}
+
+/// Returns how many times we have switched to or started tracking each exit over the last SWITCH_COUNT_WINDOW,
+/// a high count indicates the routes to that exit are flapping. Exits we have not switched to are omitted
+pub fn get_exit_switch_counts() -> Vec<(IpAddr, u32)> {
+    summarize_switch_counts(&EXIT_TRACKER.read().unwrap(), Instant::now())
+}
+
+fn summarize_switch_counts(
+    exit_map: &HashMap<IpAddr, ExitTracker>,
+    now: Instant,
+) -> Vec<(IpAddr, u32)> {
+    let mut ret: Vec<(IpAddr, u32)> = exit_map
+        .iter()
+        .map(|(ip, tracker)| (*ip, tracker.switch_count(now)))
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    ret.sort();
+    ret
+}
+
+fn summarize_exit_tracker(exit_map: &HashMap<IpAddr, ExitTracker>) -> Vec<(IpAddr, u16)> {
+    let mut ret: Vec<(IpAddr, u16)> = exit_map
+        .iter()
+        .filter_map(|(ip, tracker)| tracker.average_metric().map(|avg| (*ip, avg)))
+        .collect();
+    ret.sort();
+    ret
 }
 
 /// Simple struct that keep tracks of the following metrics during every tick:
@@ -160,7 +317,7 @@ impl From<ExitMetrics>
 /// This function helps decides whether we should switch to a better exit or not. It also helps with failover whenever the exit we
 /// are currently connected to goes down. The logic works as follows:
 ///
-/// We have a lazy static vector METRIC_VALUES which acts as a timer with 180 spots (1 added every tick ~ 15mins). This is the minimum time we need
+/// We have a lazy static vector METRIC_VALUES which acts as a timer with 180 spots by default (1 added every tick ~ 15mins, see exit_switch_dwell_seconds). This is the minimum time we need
 /// to wait before we decide whether we want to switch to another exit, given that our current exit is still up. This can also be thought of as a progress bar.
 /// To consdier switching our exit, we need to fill up this progress bar with metric values from a single route.
 ///
@@ -216,9 +373,28 @@ pub fn set_best_exit(
 
     let exit_map = &mut *EXIT_TRACKER.write().unwrap();
 
+    // The bonuses and penalties below only adjust the score used to pick the best exit, the route metrics
+    // themselves stay babel metrics so that the running averages and degradation are not skewed
+    let scores = get_selection_scores(&route_hashmap);
+    // Give exits in our current subnet a head start so that we only leave the subnet for a substantially better exit
+    let exit_client = settings::get_rita_client().exit_client;
+    let subnet_exits = get_subnet_exits(current_exit_ip, &exit_client.exits);
+    let scores = apply_metric_bonus(scores, &subnet_exits, exit_client.subnet_stickiness);
+    // Likewise give exits in our preferred region a bonus so we only leave the region for a substantially better exit
+    let region_exits =
+        get_region_exits(exit_client.preferred_region.as_deref(), &exit_client.exits);
+    let scores = apply_metric_bonus(scores, &region_exits, exit_client.region_tolerance);
+    // Exits that are refusing our payments are penalized so that we consider alternatives, payments that fail
+    // because of our own balance are ignored here since switching exits would not fix them
+    let degraded_exits = get_payment_degraded_exits(&exit_list, &get_payment_health());
+    let scores = apply_metric_penalty(scores, &degraded_exits, PAYMENT_FAILURE_PENALTY);
+    // Users on a budget may prefer a cheaper exit that is only marginally worse
+    let scores = apply_price_weight(scores, &route_hashmap, exit_client.exit_price_weight);
+
     // Parse all babel routes and find useful metrics
     let exit_metrics = get_exit_metrics(
         route_hashmap,
+        &scores,
         current_exit_ip,
         tracking_exit,
         current_exit_ip,
@@ -242,7 +418,8 @@ pub fn set_best_exit(
 
     // update lazy static metric and retrieve exit code
     let metric_vec = &mut *METRIC_VALUES.write().unwrap();
-    let exit_code = update_metric_value(exit_metrics, metric_vec, exit_map);
+    let threshold = tracking_switch_threshold(exit_client.tracking_switch_threshold);
+    let exit_code = update_metric_value(exit_metrics, metric_vec, exit_map, threshold)?;
 
     info!(
         "Exit_Switcher: exitCode: {:?}, vector len : {:?}, selected_metric: {:?}, current_exit_babel_met: {:?}, degradation: {:?}",
@@ -295,7 +472,10 @@ fn set_exit_state(
     let full_selected_exit = get_full_selected_exit();
     match exit_code {
         // we get this code when the exit is not setup, meaning it should not reach this else statement in the first place.
-        ExitSwitchingCode::InitialExitSetup => panic!("Should not reach this statement"),
+        ExitSwitchingCode::InitialExitSetup => Err(RitaClientError::MiscStringError(
+            "Got InitialExitSetup with an exit that is up, error with exit switching logic"
+                .to_string(),
+        )),
         ExitSwitchingCode::ContinueCurrentReset => {
             // We reach this when we continue with the same exit after 15mins of tracking.
             // Degradation is a measure of how much the route metric degrades after connecting to it
@@ -303,38 +483,33 @@ fn set_exit_state(
             set_selected_exit(SelectedExit {
                 selected_id: full_selected_exit.selected_id,
                 selected_id_metric: full_selected_exit.selected_id_metric,
-                selected_id_degradation: exit_metrics.cur_exit_babel_met.checked_sub(
-                    full_selected_exit
-                        .selected_id_metric
-                        .expect("No selected Ip metric where there should be one"),
-                ),
+                selected_id_degradation: exit_metrics
+                    .cur_exit_babel_met
+                    .checked_sub(expect_selected_metric(&full_selected_exit)?),
                 tracking_exit: full_selected_exit.tracking_exit,
             });
-            Ok(exit_metrics
-                .cur_exit
-                .expect("Ip value expected, none present"))
+            expect_exit_ip(exit_metrics.cur_exit)
         }
         ExitSwitchingCode::ContinueCurrent => {
             // set a degradation values if none, else update the current exit advertised values
             if full_selected_exit.selected_id_degradation.is_none() {
-                let average_metric = calculate_average(metric_vec.to_vec());
                 // We set degradation value = RelU(average_metric val - our_advertised_metric). Since we know tracking_exit == current_exit,
-                // We can use values in the vector.
-                set_selected_exit(SelectedExit {
-                    selected_id: full_selected_exit.selected_id,
-                    selected_id_metric: full_selected_exit.selected_id_metric,
-                    selected_id_degradation: average_metric.checked_sub(
-                        full_selected_exit
-                            .selected_id_metric
-                            .expect("No selected Ip metric where there should be one"),
-                    ),
-                    tracking_exit: full_selected_exit.tracking_exit,
-                });
+                // We can use values in the vector. If there are no values yet we try again next tick
+                if let Some(average_metric) = calculate_average(metric_vec.to_vec()) {
+                    set_selected_exit(SelectedExit {
+                        selected_id: full_selected_exit.selected_id,
+                        selected_id_metric: full_selected_exit.selected_id_metric,
+                        selected_id_degradation: average_metric
+                            .checked_sub(expect_selected_metric(&full_selected_exit)?),
+                        tracking_exit: full_selected_exit.tracking_exit,
+                    });
+                }
             } else {
                 // We have already set a degradation value, so we continue using the same value until the clock reset
-                let res = exit_metrics
-                    .cur_exit_babel_met
-                    .checked_sub(full_selected_exit.selected_id_degradation.unwrap());
+                let res = match full_selected_exit.selected_id_degradation {
+                    Some(degradation) => exit_metrics.cur_exit_babel_met.checked_sub(degradation),
+                    None => None,
+                };
 
                 // We should not be setting 'selected_id_metric' as None. If we do, that means degradation > current_metric, meaning an error with logic somewhere
                 if res.is_none() {
@@ -348,9 +523,7 @@ fn set_exit_state(
                     });
                 }
             }
-            Ok(exit_metrics
-                .cur_exit
-                .expect("Ip value expected, none present"))
+            expect_exit_ip(exit_metrics.cur_exit)
         }
         ExitSwitchingCode::SwitchExit => {
             // We swtich to the new exit
@@ -360,13 +533,9 @@ fn set_exit_state(
                 selected_id_degradation: None,
                 tracking_exit: exit_metrics.best_exit,
             });
-            Ok(exit_metrics
-                .best_exit
-                .expect("Ip value expected, none present"))
+            expect_exit_ip(exit_metrics.best_exit)
         }
-        ExitSwitchingCode::ContinueTracking => Ok(exit_metrics
-            .cur_exit
-            .expect("Ip value expected, none present")),
+        ExitSwitchingCode::ContinueTracking => expect_exit_ip(exit_metrics.cur_exit),
         ExitSwitchingCode::ResetTracking => {
             // selected id is still the same, we dont change exit, just change what we track
             set_selected_exit(SelectedExit {
@@ -375,20 +544,161 @@ fn set_exit_state(
                 selected_id_degradation: full_selected_exit.selected_id_degradation,
                 tracking_exit: exit_metrics.best_exit,
             });
-            Ok(exit_metrics
-                .cur_exit
-                .expect("Ip value expected, none present"))
+            expect_exit_ip(exit_metrics.cur_exit)
         }
     }
 }
 
+/// Unwraps an exit ip that the exit switching logic expects to be present, returning an error for this tick
+/// rather than crashing if it is missing
+fn expect_exit_ip(ip: Option<IpAddr>) -> Result<IpAddr, RitaClientError> {
+    ip.ok_or_else(|| {
+        RitaClientError::MiscStringError("Ip value expected, none present".to_string())
+    })
+}
+
+fn expect_selected_metric(selected_exit: &SelectedExit) -> Result<u16, RitaClientError> {
+    selected_exit.selected_id_metric.ok_or_else(|| {
+        RitaClientError::MiscStringError(
+            "No selected Ip metric where there should be one".to_string(),
+        )
+    })
+}
+
+/// Returns the mesh ips of all exits that are in the same internal subnet as our current exit, including
+/// the current exit itself. If we are not connected to an exit or lack its details this is empty
+fn get_subnet_exits(
+    current_exit_ip: Option<IpAddr>,
+    exits: &HashMap<IpAddr, ExitServer>,
+) -> HashSet<IpAddr> {
+    let mut ret = HashSet::new();
+    let current_subnet = match current_exit_ip
+        .and_then(|ip| exits.get(&ip))
+        .and_then(|exit| exit.info.general_details())
+        .and_then(|details| IpNetwork::new(details.server_internal_ip, details.netmask).ok())
+    {
+        Some(subnet) => subnet,
+        None => return ret,
+    };
+    for (ip, exit) in exits.iter() {
+        if let Some(details) = exit.info.general_details() {
+            if current_subnet.contains(details.server_internal_ip) {
+                ret.insert(*ip);
+            }
+        }
+    }
+    ret
+}
+
+/// Returns the mesh ips of all exits whose region tag matches our preferred region, if no region is
+/// preferred this is empty
+fn get_region_exits(
+    preferred_region: Option<&str>,
+    exits: &HashMap<IpAddr, ExitServer>,
+) -> HashSet<IpAddr> {
+    let preferred_region = match preferred_region {
+        Some(region) => region,
+        None => return HashSet::new(),
+    };
+    exits
+        .iter()
+        .filter(|(_, exit)| {
+            exit.info
+                .general_details()
+                .and_then(|details| details.region.as_deref())
+                .map(|region| region.eq_ignore_ascii_case(preferred_region))
+                .unwrap_or(false)
+        })
+        .map(|(ip, _)| *ip)
+        .collect()
+}
+
+/// Returns the score get_exit_metrics uses to pick the best exit for every route, this starts out as the babel
+/// metric and is then adjusted by the bonuses and penalties below
+fn get_selection_scores(route_hashmap: &HashMap<IpAddr, Route>) -> HashMap<IpAddr, RouteMetric> {
+    route_hashmap
+        .iter()
+        .map(|(ip, route)| (*ip, route.metric))
+        .collect()
+}
+
+/// Subtracts a bonus, such as the subnet stickiness, from the selection score of every route to one of the given
+/// exits. Unreachable routes are left untouched so that the bonus never makes a down exit look reachable
+fn apply_metric_bonus(
+    mut scores: HashMap<IpAddr, RouteMetric>,
+    exits: &HashSet<IpAddr>,
+    bonus: u16,
+) -> HashMap<IpAddr, RouteMetric> {
+    if bonus == 0 {
+        return scores;
+    }
+    for (ip, score) in scores.iter_mut() {
+        if exits.contains(ip) {
+            *score = score.saturating_sub(bonus);
+        }
+    }
+    scores
+}
+
+/// Returns the mesh ips of all exits that have failed to accept our last PAYMENT_FAILURE_THRESHOLD payments
+/// for reasons on their side
+fn get_payment_degraded_exits(
+    exit_list: &[Identity],
+    payment_health: &HashMap<Identity, PaymentHealth>,
+) -> HashSet<IpAddr> {
+    exit_list
+        .iter()
+        .filter(|exit| {
+            payment_health
+                .get(*exit)
+                .map(|health| health.peer_failures >= PAYMENT_FAILURE_THRESHOLD)
+                .unwrap_or(false)
+        })
+        .map(|exit| exit.mesh_ip)
+        .collect()
+}
+
+/// Adds the weighted price of every exit route to its selection score, so that the score becomes
+/// metric + price * weight / 1_000_000. Like apply_metric_penalty the result is capped at RouteMetric::MAX_FINITE
+fn apply_price_weight(
+    mut scores: HashMap<IpAddr, RouteMetric>,
+    route_hashmap: &HashMap<IpAddr, Route>,
+    weight: u32,
+) -> HashMap<IpAddr, RouteMetric> {
+    if weight == 0 {
+        return scores;
+    }
+    for (ip, score) in scores.iter_mut() {
+        if let Some(route) = route_hashmap.get(ip) {
+            let price_cost = route.price as u64 * weight as u64 / PRICE_WEIGHT_SCALE;
+            *score = score.saturating_add(u16::try_from(price_cost).unwrap_or(u16::MAX));
+        }
+    }
+    scores
+}
+
+/// Adds a penalty to the selection score of every route to one of the given exits, the opposite of apply_metric_bonus.
+/// The result is capped at RouteMetric::MAX_FINITE so that a penalized exit is never mistaken for an unreachable one
+fn apply_metric_penalty(
+    mut scores: HashMap<IpAddr, RouteMetric>,
+    exits: &HashSet<IpAddr>,
+    penalty: u16,
+) -> HashMap<IpAddr, RouteMetric> {
+    for (ip, score) in scores.iter_mut() {
+        if exits.contains(ip) {
+            *score = score.saturating_add(penalty);
+        }
+    }
+    scores
+}
+
 /// This function loops through all the routes advertised through babel and searches for 3 particular exits:
 ///
 /// 1.) Current Exit we are connected to, if there is one
 ///
 /// 2.) The Tracking exit that we keep track of in lazy static
 ///
-/// 3.) The best exit with lowest metric, according to babel metrics during this tick
+/// 3.) The best exit with lowest selection score during this tick, routes without a score are compared by their babel metric
 ///
 /// These values will help us determine the course of action to take, and wheter to switch or not.
 /// Once it finds this 3 exits, its returns an ExitMetric struct with the following information:
@@ -405,9 +715,10 @@ fn set_exit_state(
 ///
 /// 6.) Option<IpAddr> of the best exit during this tick
 ///
-/// 7.) Metric of the best exit during this tick
+/// 7.) Babel metric of the best exit during this tick
 fn get_exit_metrics(
     route_hashmap: HashMap<IpAddr, Route>,
+    scores: &HashMap<IpAddr, RouteMetric>,
     current_exit_ip: Option<IpAddr>,
     tracking_exit: Option<IpAddr>,
     initial_best_exit: Option<IpAddr>,
@@ -415,13 +726,14 @@ fn get_exit_metrics(
     exit_list: Vec<Identity>,
     exit_map: &mut HashMap<IpAddr, ExitTracker>,
 ) -> ExitMetrics {
+    let initial_best_metric = RouteMetric::from(initial_best_metric);
     let mut best_exit = None;
-    let mut best_metric = u16::MAX;
-    //By default we say our exit is down. If we find a route to it that is not u16::MAX, we can change this
+    let mut best_metric = RouteMetric::Infinite;
+    //By default we say our exit is down. If we find a reachable route to it, we can change this
     let mut current_exit_down = true;
 
-    let mut current_exit_metric = u16::MAX;
-    let mut tracking_metric = u16::MAX;
+    let mut current_exit_metric = RouteMetric::Infinite;
+    let mut tracking_metric = RouteMetric::Infinite;
 
     // When all exits are blacklisted, reset the blacklist. Normally all exits in the subnet should not be blacklisted, however a false
     // positive is possible when a working exit is unresponsive for a period of time and we blacklist it. When it comes back up, we are unable to
@@ -429,6 +741,15 @@ fn get_exit_metrics(
     // the rogue ip addrs to be added back into the blacklist
     let mut all_exits_blacklisted = true;
     let blacklisted = get_exit_blacklist();
+    // Routes to exits that are eligible to be the best exit this tick
+    let mut candidates: Vec<&Route> = Vec::new();
+    let score = |route: &Route| {
+        scores
+            .get(&route.prefix.ip())
+            .copied()
+            .unwrap_or(route.metric)
+    };
+    let mut current_exit_score = RouteMetric::Infinite;
 
     for ip in exit_list.clone() {
         // All babel routes are advertised as /128, so we check if each 'single' ip is part of exit subnet
@@ -456,42 +777,43 @@ fn get_exit_metrics(
             // 2.) Our exit ip doesnt exist in babel's routes
             // 3.) Exit's route metric has gone to inf
             if let Some(exit_ip) = current_exit_ip {
-                if exit_ip == ip && route.metric != u16::MAX {
+                if exit_ip == ip && route.is_reachable() {
                     // Current exit metric is not inf and we have a path to exit, so current exit is up. The time intial_best_metric is
-                    // u16::MAX is on rita startup, meaning we have not setup the initial exit yet
-                    if initial_best_metric != u16::MAX {
+                    // infinite is on rita startup, meaning we have not setup the initial exit yet
+                    if initial_best_metric.is_reachable() {
                         current_exit_down = false;
-                        current_exit_metric = if current_exit_metric > route.metric {
-                            route.metric
-                        } else {
-                            current_exit_metric
-                        };
+                        current_exit_metric = current_exit_metric.min(route.metric);
+                        current_exit_score = current_exit_score.min(score(route));
                     }
                 }
             }
             if let Some(tracking_ip) = tracking_exit {
-                if tracking_ip == ip && route.metric != u16::MAX {
+                if tracking_ip == ip && route.is_reachable() {
                     // We are currently tracking an exit, we set its metric. Since babel advertises several routes to an exit, we choose best one
-                    tracking_metric = if tracking_metric > route.metric {
-                        route.metric
-                    } else {
-                        tracking_metric
-                    };
+                    tracking_metric = tracking_metric.min(route.metric);
                 }
             }
 
             info!("Metric for the IP: {} is {}", ip, route.metric);
             // Set details for additional exits in the server
-            observe_cluster_metrics(exit_map, ip, route.metric);
+            observe_cluster_metrics(exit_map, ip, route.metric.into());
 
-            // Every loop iteration, update the best exit
-            if route.metric < best_metric {
-                best_metric = route.metric;
-                best_exit = Some(ip);
+            if route.is_reachable() {
+                candidates.push(route);
             }
         }
     }
 
+    let mut best_score = RouteMetric::Infinite;
+    if let Some(best) = candidates
+        .into_iter()
+        .min_by(|a, b| score(*a).cmp(&score(*b)).then(a.cmp_by_metric(b)))
+    {
+        best_metric = best.metric;
+        best_score = score(best);
+        best_exit = Some(best.prefix.ip());
+    }
+
     //If all exits blacklist, reset blacklist
     if all_exits_blacklisted {
         error!("All exits in subnet have been blacklisted, clearing blacklist");
@@ -499,8 +821,11 @@ fn get_exit_metrics(
         reset_exit_blacklist();
     }
 
-    //If current exit is still up, we reset best exit with current exit, using our advertised metric values given that our current exit better
-    if !current_exit_down && initial_best_metric < best_metric {
+    //If current exit is still up, we reset best exit with current exit, using our advertised metric values given that our current exit better.
+    // This is compared on selection scores so that penalties, price and region apply to our current exit as well
+    let current_exit_score =
+        advertised_score(current_exit_score, current_exit_metric, initial_best_metric);
+    if !current_exit_down && current_exit_score < best_score {
         best_metric = initial_best_metric;
         best_exit = initial_best_exit;
     }
@@ -511,12 +836,27 @@ fn get_exit_metrics(
     ExitMetrics {
         is_exit_down: current_exit_down,
         cur_exit: current_exit_ip,
-        cur_exit_babel_met: current_exit_metric,
+        cur_exit_babel_met: current_exit_metric.into(),
         tracking_exit,
-        tracking_met: tracking_metric,
+        tracking_met: tracking_metric.into(),
         best_exit,
-        best_exit_met: best_metric,
+        best_exit_met: best_metric.into(),
+    }
+}
+
+/// Applies our advertised metric to the selection score of our current exit, the score is shifted by however much
+/// the advertised metric differs from the babel metric so that the bonuses and penalties in the score are kept
+fn advertised_score(
+    score: RouteMetric,
+    babel_metric: RouteMetric,
+    advertised_metric: RouteMetric,
+) -> RouteMetric {
+    if !score.is_reachable() || !babel_metric.is_reachable() || !advertised_metric.is_reachable() {
+        return RouteMetric::Infinite;
     }
+    let shifted = i64::from(u16::from(score)) + i64::from(u16::from(advertised_metric))
+        - i64::from(u16::from(babel_metric));
+    RouteMetric::Finite(shifted.clamp(0, i64::from(RouteMetric::MAX_FINITE)) as u16)
 }
 
 /// This function is called to update the running averages of babel metrics for every exit in the cluster. These average can then
@@ -579,7 +919,8 @@ fn update_metric_value(
     exit_metrics: ExitMetrics,
     metric_vec: &mut Vec<u16>,
     exit_map: &mut HashMap<IpAddr, ExitTracker>,
-) -> ExitSwitchingCode {
+    switch_threshold: f64,
+) -> Result<ExitSwitchingCode, RitaClientError> {
     let is_full = metric_vec.len() == metric_vec.capacity();
     let current_exit = exit_metrics.cur_exit;
     let current_metric = exit_metrics.cur_exit_babel_met;
@@ -588,62 +929,70 @@ fn update_metric_value(
     let tracking_exit = exit_metrics.tracking_exit;
     let tracking_metric = exit_metrics.tracking_met;
 
-    if current_exit.is_none() {
-        //setting up exit for first time, vec should be empty
-        if !metric_vec.is_empty() {
-            panic!("Error with METRIC VALUES update logic");
+    let current_exit = match current_exit {
+        Some(a) => a,
+        None => {
+            //setting up exit for first time, vec should be empty
+            if !metric_vec.is_empty() {
+                return Err(RitaClientError::MiscStringError(
+                    "Error with METRIC VALUES update logic".to_string(),
+                ));
+            }
+            return Ok(ExitSwitchingCode::InitialExitSetup);
         }
-        return ExitSwitchingCode::InitialExitSetup;
-    }
+    };
 
     // if best exit is none, routing table is empty, and thus we still are initial setup
-    if best_exit.is_none() {
-        panic!("Best exit should not be set to none. If it is it should not be reaching this statement");
-    }
-
-    let tracking_exit = match tracking_exit {
+    let best_exit = match best_exit {
         Some(a) => a,
-        None => best_exit.unwrap(),
+        None => {
+            return Err(RitaClientError::MiscStringError(
+                "Best exit should not be set to none during exit metric update".to_string(),
+            ))
+        }
     };
 
-    // We have checked that there are values in best_exit, tracking_exit and current_exit, so we can unwrap safely
-    if best_exit.unwrap() == tracking_exit {
-        if current_exit.unwrap() == tracking_exit {
+    let tracking_exit = tracking_exit.unwrap_or(best_exit);
+
+    if best_exit == tracking_exit {
+        if current_exit == tracking_exit {
             // All three exits are the same
             if is_full {
                 metric_vec.clear();
                 reset_exit_tracking(exit_map);
                 metric_vec.push(best_metric);
-                ExitSwitchingCode::ContinueCurrentReset
+                Ok(ExitSwitchingCode::ContinueCurrentReset)
             } else {
                 metric_vec.push(best_metric);
-                ExitSwitchingCode::ContinueCurrent
+                Ok(ExitSwitchingCode::ContinueCurrent)
             }
         } else {
             //our current exit is different from the best exit
             if is_full {
                 metric_vec.clear();
                 reset_exit_tracking(exit_map);
+                record_exit_switch(exit_map, best_exit);
                 metric_vec.push(best_metric);
-                ExitSwitchingCode::SwitchExit
+                Ok(ExitSwitchingCode::SwitchExit)
             } else {
                 metric_vec.push(best_metric);
-                ExitSwitchingCode::ContinueTracking
+                Ok(ExitSwitchingCode::ContinueTracking)
             }
         }
     } else {
         // best exit is different from tracking, so we change tracking to be best if we see that the best metric >>> tracking metric
-        if worth_switching_tracking_exit(metric_vec, best_exit.unwrap(), exit_map) {
+        if worth_switching_tracking_exit(metric_vec, best_exit, exit_map, switch_threshold) {
             metric_vec.clear();
             reset_exit_tracking(exit_map);
+            record_exit_switch(exit_map, best_exit);
             metric_vec.push(best_metric);
-            ExitSwitchingCode::ResetTracking
+            Ok(ExitSwitchingCode::ResetTracking)
         } else {
             // Since we want to continue with current tracking exit, we just make recursive call to best exit == tracking exit
             update_metric_value(
                 ExitMetrics::new(
                     exit_metrics.is_exit_down,
-                    current_exit,
+                    Some(current_exit),
                     current_metric,
                     Some(tracking_exit),
                     tracking_metric,
@@ -652,11 +1001,20 @@ fn update_metric_value(
                 ),
                 metric_vec,
                 exit_map,
+                switch_threshold,
             )
         }
     }
 }
 
+/// Records that we switched to or started tracking this exit, see get_exit_switch_counts
+fn record_exit_switch(exit_map: &mut HashMap<IpAddr, ExitTracker>, ip: IpAddr) {
+    exit_map
+        .entry(ip)
+        .or_default()
+        .record_switch(Instant::now());
+}
+
 fn reset_exit_tracking(exit_map: &mut HashMap<IpAddr, ExitTracker>) {
     for (_, v) in exit_map.iter_mut() {
         v.last_added_metric = 0;
@@ -665,7 +1023,7 @@ fn reset_exit_tracking(exit_map: &mut HashMap<IpAddr, ExitTracker>) {
     }
 }
 
-/// It is worth tracking a new better exit only if its values is more than 10% better than our current tracking exit values, else there is no point
+/// It is worth tracking a new better exit only if its values is more than switch_threshold better than our current tracking exit values, else there is no point
 /// throwing away all our progress for our current tracking exit. This helps solve the following edge case:
 ///
 /// We are connected to exit A, exit B and C are consistently better than A, but they flucuate between being the best exit every tick. Instead of
@@ -675,68 +1033,60 @@ fn worth_switching_tracking_exit(
     metric_vec: &mut [u16],
     best_ip: IpAddr,
     exit_map: &mut HashMap<IpAddr, ExitTracker>,
+    switch_threshold: f64,
 ) -> bool {
-    if metric_vec.is_empty() {
-        return false;
-    }
-    let avg_tracking_metric = calculate_average(metric_vec.to_owned());
+    let avg_tracking_metric = match calculate_average(metric_vec.to_owned()) {
+        Some(avg) => avg,
+        None => return false,
+    };
 
-    let exit_tracker = exit_map
-        .get(&best_ip)
-        .expect("There should be an ExitTracker entry here");
-    if exit_tracker.ticker_len == 0 {
-        return false;
-    }
-    let avg_best_metric = (exit_tracker.running_total / exit_tracker.ticker_len as u64) as u16;
+    let exit_tracker = match exit_map.get(&best_ip) {
+        Some(tracker) => tracker,
+        None => {
+            warn!("No ExitTracker entry for best exit {}", best_ip);
+            return false;
+        }
+    };
+    let avg_best_metric = match exit_tracker.average_metric() {
+        Some(avg) => avg,
+        None => return false,
+    };
 
     if avg_tracking_metric < avg_best_metric || avg_best_metric == 0 {
         false
     } else {
         (((avg_tracking_metric - avg_best_metric) as f64) / (avg_tracking_metric as f64))
-            > FLAPPING_THRESH
+            > switch_threshold
     }
 }
 
-/// Given a vector of u16, calculates the average. Panics if given a vector with no entries
-fn calculate_average(vals: Vec<u16>) -> u16 {
+/// Given a vector of u16, calculates the average. Returns None if given a vector with no entries
+fn calculate_average(vals: Vec<u16>) -> Option<u16> {
     if vals.is_empty() {
-        panic!("received list of values with no elements");
+        return None;
     }
     let mut sum: u64 = 0;
     for entry in vals.iter() {
         sum += *entry as u64;
     }
 
-    (sum / vals.len() as u64) as u16
+    Some((sum / vals.len() as u64) as u16)
 }
 
 /// Simple helper function that opens a babel stream to get all routes related to us. We can use these routes to
-/// check which ips are exits and thereby register or setup exits
-pub fn get_babel_routes(babel_port: u16) -> Result<Vec<Route>, RitaClientError> {
-    let mut stream = match open_babel_stream(babel_port, CLIENT_LOOP_TIMEOUT) {
-        Ok(a) => a,
-        Err(_) => {
-            return Err(RitaClientError::MiscStringError(
-                "open babel stream error in exit manager tick".to_string(),
-            ))
-        }
-    };
-    let routes = match parse_routes(&mut stream) {
-        Ok(a) => a,
-        Err(_) => {
-            return Err(RitaClientError::MiscStringError(
-                "Parse routes error in exit manager tick".to_string(),
-            ))
-        }
-    };
-
-    Ok(routes)
+/// check which ips are exits and thereby register or setup exits. The error is passed through so that callers can tell
+/// babeld being down, see BabelMonitorError::is_connection_error, apart from babeld returning output we can't parse
+pub fn get_babel_routes(babel_port: u16) -> Result<Vec<Route>, BabelMonitorError> {
+    let mut stream = open_babel_stream(babel_port, CLIENT_LOOP_TIMEOUT)?;
+    parse_routes(&mut stream)
 }
 
 #[cfg(test)]
 mod tests {
 
-    use althea_types::{FromStr, Identity, WgKey};
+    use althea_types::{
+        ExitDetails, ExitState, ExitVerifMode, FromStr, Identity, SystemChain, WgKey,
+    };
     use clarity::Address;
     use ipnetwork::IpNetwork;
 
@@ -744,18 +1094,66 @@ mod tests {
     use crate::exit_manager::{
         reset_blacklist_warnings, ExitBlacklist, MAX_BLACKLIST_STRIKES, SELECTED_EXIT_DETAILS,
     };
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_clamp_metric_entries() {
+        assert_eq!(clamp_metric_entries(METRIC_ENTRIES), METRIC_ENTRIES);
+        assert_eq!(clamp_metric_entries(usize::MAX), MAX_METRIC_ENTRIES);
+        assert_eq!(
+            clamp_metric_entries(MAX_METRIC_ENTRIES + 1),
+            MAX_METRIC_ENTRIES
+        );
+        assert_eq!(clamp_metric_entries(0), MIN_METRIC_ENTRIES);
+        assert_eq!(clamp_metric_entries(MIN_METRIC_ENTRIES), MIN_METRIC_ENTRIES);
+    }
+
+    #[test]
+    fn test_metric_entries_for_dwell() {
+        assert_eq!(metric_entries_for_dwell(None), METRIC_ENTRIES);
+        assert_eq!(metric_entries_for_dwell(Some(15 * 60)), METRIC_ENTRIES);
+        assert_eq!(
+            metric_entries_for_dwell(Some(60 * 60)),
+            (60 * 60) / FAST_LOOP_SPEED.as_secs() as usize
+        );
+        // a near zero dwell is raised to the minimum window
+        assert_eq!(metric_entries_for_dwell(Some(0)), MIN_METRIC_ENTRIES);
+        assert_eq!(metric_entries_for_dwell(Some(u64::MAX)), MAX_METRIC_ENTRIES);
+
+        // the configured capacity is what marks the vector as full
+        let metric_vec: Vec<u16> = Vec::with_capacity(metric_entries_for_dwell(Some(0)));
+        assert_eq!(metric_vec.capacity(), MIN_METRIC_ENTRIES);
+    }
 
     #[test]
     fn test_calculate_average() {
+        assert_eq!(calculate_average(vec![]), None);
+
         let vec = vec![10];
 
-        assert_eq!(calculate_average(vec), 10);
+        assert_eq!(calculate_average(vec), Some(10));
 
         let vec = vec![10, 10, 12, 16, 20];
 
         // we map 13.6 -> u16
-        assert_eq!(calculate_average(vec), 13);
+        assert_eq!(calculate_average(vec), Some(13));
+    }
+
+    #[test]
+    fn test_summarize_exit_tracker() {
+        let ip1 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+        let ip2 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 2));
+        let ip3 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 3));
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        exit_map.insert(ip2, ExitTracker::new(0, 500 * 3, 3));
+        exit_map.insert(ip1, ExitTracker::new(0, 13 * 5 + 3, 5));
+        // not yet observed since the last reset
+        exit_map.insert(ip3, ExitTracker::new(0, 0, 0));
+
+        assert_eq!(
+            summarize_exit_tracker(&exit_map),
+            vec![(ip1, 13), (ip2, 500)]
+        );
     }
 
     #[test]
@@ -765,25 +1163,200 @@ mod tests {
         let ip = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
 
         exit_map.insert(ip, ExitTracker::new(110, 110, 1));
-        assert!(!worth_switching_tracking_exit(&mut vec, ip, &mut exit_map));
+        assert!(!worth_switching_tracking_exit(
+            &mut vec,
+            ip,
+            &mut exit_map,
+            FLAPPING_THRESH
+        ));
 
         exit_map.insert(ip, ExitTracker::new(111, 111, 1));
-        assert!(!worth_switching_tracking_exit(&mut vec, ip, &mut exit_map));
+        assert!(!worth_switching_tracking_exit(
+            &mut vec,
+            ip,
+            &mut exit_map,
+            FLAPPING_THRESH
+        ));
 
         exit_map.insert(ip, ExitTracker::new(90, 90, 1));
-        assert!(!worth_switching_tracking_exit(&mut vec, ip, &mut exit_map));
+        assert!(!worth_switching_tracking_exit(
+            &mut vec,
+            ip,
+            &mut exit_map,
+            FLAPPING_THRESH
+        ));
 
         exit_map.insert(ip, ExitTracker::new(89, 89, 1));
-        assert!(!worth_switching_tracking_exit(&mut vec, ip, &mut exit_map));
+        assert!(!worth_switching_tracking_exit(
+            &mut vec,
+            ip,
+            &mut exit_map,
+            FLAPPING_THRESH
+        ));
 
         //avg is 13.6 -> to u16 -> 13
         let mut vec = vec![10, 10, 12, 16, 20];
 
         exit_map.insert(ip, ExitTracker::new(12, 12 * 5, 5));
-        assert!(!worth_switching_tracking_exit(&mut vec, ip, &mut exit_map));
+        assert!(!worth_switching_tracking_exit(
+            &mut vec,
+            ip,
+            &mut exit_map,
+            FLAPPING_THRESH
+        ));
 
         exit_map.insert(ip, ExitTracker::new(11, 11 * 5, 5));
-        assert!(!worth_switching_tracking_exit(&mut vec, ip, &mut exit_map));
+        assert!(!worth_switching_tracking_exit(
+            &mut vec,
+            ip,
+            &mut exit_map,
+            FLAPPING_THRESH
+        ));
+    }
+
+    #[test]
+    fn test_tracking_switch_threshold() {
+        assert_eq!(tracking_switch_threshold(None), FLAPPING_THRESH);
+        assert_eq!(tracking_switch_threshold(Some(10)), 0.1);
+
+        // an exit 20% better than the tracked exit is only worth switching to with a low threshold
+        let mut vec: Vec<u16> = vec![100];
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let ip = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+        exit_map.insert(ip, ExitTracker::new(80, 80, 1));
+        assert!(!worth_switching_tracking_exit(
+            &mut vec,
+            ip,
+            &mut exit_map,
+            FLAPPING_THRESH
+        ));
+        assert!(worth_switching_tracking_exit(
+            &mut vec,
+            ip,
+            &mut exit_map,
+            tracking_switch_threshold(Some(10))
+        ));
+    }
+
+    #[test]
+    fn test_update_metric_value_errors() {
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let current_exit = Some(IpAddr::V4(Ipv4Addr::new(1, 12, 12, 12)));
+
+        // a missing best exit is an error for this tick, not a crash
+        let mut vec: Vec<u16> = Vec::with_capacity(10);
+        assert!(update_metric_value(
+            ExitMetrics::new(false, current_exit, 400, None, u16::MAX, None, u16::MAX),
+            &mut vec,
+            &mut exit_map,
+            FLAPPING_THRESH
+        )
+        .is_err());
+
+        // so is a partially filled vector without a current exit
+        let mut vec: Vec<u16> = vec![400];
+        assert!(update_metric_value(
+            ExitMetrics::new(true, None, u16::MAX, None, u16::MAX, current_exit, 400),
+            &mut vec,
+            &mut exit_map,
+            FLAPPING_THRESH
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_update_metric_values_min_capacity() {
+        // even the smallest window we allocate should alternate between filling and resetting,
+        // rather than growing the vector or switching every tick
+        let mut vec: Vec<u16> = Vec::with_capacity(MIN_METRIC_ENTRIES);
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let ip = IpAddr::V4(Ipv4Addr::new(1, 12, 12, 12));
+        exit_map.insert(ip, ExitTracker::new(400, 400, 1));
+        let metrics = || ExitMetrics::new(false, Some(ip), 400, Some(ip), 400, Some(ip), 400);
+
+        for len in 1..=MIN_METRIC_ENTRIES {
+            assert_eq!(
+                update_metric_value(metrics(), &mut vec, &mut exit_map, FLAPPING_THRESH).unwrap(),
+                ExitSwitchingCode::ContinueCurrent
+            );
+            assert_eq!(vec.len(), len);
+        }
+        for _ in 0..3 {
+            assert_eq!(
+                update_metric_value(metrics(), &mut vec, &mut exit_map, FLAPPING_THRESH).unwrap(),
+                ExitSwitchingCode::ContinueCurrentReset
+            );
+            assert_eq!(vec.len(), 1);
+            for len in 2..=MIN_METRIC_ENTRIES {
+                assert_eq!(
+                    update_metric_value(metrics(), &mut vec, &mut exit_map, FLAPPING_THRESH)
+                        .unwrap(),
+                    ExitSwitchingCode::ContinueCurrent
+                );
+                assert_eq!(vec.len(), len);
+            }
+            assert_eq!(vec.capacity(), MIN_METRIC_ENTRIES);
+        }
+    }
+
+    #[test]
+    fn test_exit_switch_count() {
+        // with the smallest window we switch to the best exit once every full window
+        let mut vec: Vec<u16> = Vec::with_capacity(MIN_METRIC_ENTRIES);
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let current = IpAddr::V4(Ipv4Addr::new(1, 12, 12, 12));
+        let best = IpAddr::V4(Ipv4Addr::new(1, 13, 13, 13));
+        let metrics =
+            || ExitMetrics::new(false, Some(current), 400, Some(best), 200, Some(best), 200);
+
+        for _ in 0..MIN_METRIC_ENTRIES {
+            assert_eq!(
+                update_metric_value(metrics(), &mut vec, &mut exit_map, FLAPPING_THRESH).unwrap(),
+                ExitSwitchingCode::ContinueTracking
+            );
+        }
+        assert!(summarize_switch_counts(&exit_map, Instant::now()).is_empty());
+        for expected in 1..=3 {
+            assert_eq!(
+                update_metric_value(metrics(), &mut vec, &mut exit_map, FLAPPING_THRESH).unwrap(),
+                ExitSwitchingCode::SwitchExit
+            );
+            assert_eq!(
+                summarize_switch_counts(&exit_map, Instant::now()),
+                vec![(best, expected)]
+            );
+            for _ in 1..MIN_METRIC_ENTRIES {
+                assert_eq!(
+                    update_metric_value(metrics(), &mut vec, &mut exit_map, FLAPPING_THRESH)
+                        .unwrap(),
+                    ExitSwitchingCode::ContinueTracking
+                );
+            }
+        }
+
+        // resetting tracking to a much better exit also counts
+        let better = IpAddr::V4(Ipv4Addr::new(1, 14, 14, 14));
+        exit_map.insert(better, ExitTracker::new(0, 50, 1));
+        let mut vec: Vec<u16> = Vec::with_capacity(10);
+        vec.push(200);
+        assert_eq!(
+            update_metric_value(
+                ExitMetrics::new(false, Some(current), 400, Some(best), 200, Some(better), 50),
+                &mut vec,
+                &mut exit_map,
+                FLAPPING_THRESH
+            )
+            .unwrap(),
+            ExitSwitchingCode::ResetTracking
+        );
+        assert_eq!(
+            summarize_switch_counts(&exit_map, Instant::now()),
+            vec![(best, 3), (better, 1)]
+        );
+
+        // switches older than the window are no longer reported
+        let later = Instant::now() + SWITCH_COUNT_WINDOW;
+        assert!(summarize_switch_counts(&exit_map, later).is_empty());
     }
 
     #[test]
@@ -814,8 +1387,10 @@ mod tests {
                     400
                 ),
                 &mut vec,
-                &mut exit_map
+                &mut exit_map,
+                FLAPPING_THRESH
             )
+            .unwrap()
         );
         assert_eq!(vec.len(), 0);
 
@@ -828,8 +1403,10 @@ mod tests {
             update_metric_value(
                 ExitMetrics::new(false, current_exit, 450, tracking_exit, 450, best_exit, 450),
                 &mut vec,
-                &mut exit_map
+                &mut exit_map,
+                FLAPPING_THRESH
             )
+            .unwrap()
         );
         assert_eq!(vec.len(), 1);
         assert_eq!(vec[0], 450);
@@ -844,8 +1421,10 @@ mod tests {
             update_metric_value(
                 ExitMetrics::new(false, current_exit, 415, tracking_exit, 415, best_exit, 415),
                 &mut vec,
-                &mut exit_map
+                &mut exit_map,
+                FLAPPING_THRESH
             )
+            .unwrap()
         );
         assert_eq!(vec.capacity(), 10);
         assert_eq!(vec.len(), 1);
@@ -871,8 +1450,10 @@ mod tests {
                     413
                 ),
                 &mut vec,
-                &mut exit_map
+                &mut exit_map,
+                FLAPPING_THRESH
             )
+            .unwrap()
         );
         assert_eq!(vec.len(), 2);
         assert_eq!(vec[1], 5000);
@@ -885,8 +1466,10 @@ mod tests {
             update_metric_value(
                 ExitMetrics::new(false, current_exit, 500, tracking_exit, 410, best_exit, 410),
                 &mut vec,
-                &mut exit_map
+                &mut exit_map,
+                FLAPPING_THRESH
             )
+            .unwrap()
         );
         assert_eq!(vec.len(), 3);
         assert_eq!(vec[2], 410);
@@ -901,8 +1484,10 @@ mod tests {
             update_metric_value(
                 ExitMetrics::new(false, current_exit, 500, tracking_exit, 410, best_exit, 410),
                 &mut vec,
-                &mut exit_map
+                &mut exit_map,
+                FLAPPING_THRESH
             )
+            .unwrap()
         );
         assert_eq!(vec.len(), 1);
         assert_eq!(vec.capacity(), 10);
@@ -920,8 +1505,10 @@ mod tests {
             update_metric_value(
                 ExitMetrics::new(false, current_exit, 500, tracking_exit, 450, best_exit, 440),
                 &mut vec,
-                &mut exit_map
+                &mut exit_map,
+                FLAPPING_THRESH
             )
+            .unwrap()
         );
         assert_eq!(vec.len(), 1);
         assert_eq!(vec.capacity(), 10);
@@ -938,13 +1525,37 @@ mod tests {
             update_metric_value(
                 ExitMetrics::new(false, current_exit, 500, tracking_exit, 450, best_exit, 200),
                 &mut vec,
-                &mut exit_map
+                &mut exit_map,
+                FLAPPING_THRESH
             )
+            .unwrap()
         );
         assert_eq!(vec.len(), 1);
         assert_eq!(vec.capacity(), 10);
     }
 
+    /// A route to an exit at `ip` as babel would report it, with a host prefix for its family
+    fn test_route(ip: IpAddr, metric: u16) -> Route {
+        let (neigh_ip, prefix_len) = match ip {
+            IpAddr::V4(_) => (IpAddr::V4(Ipv4Addr::new(2, 1, 1, 5)), 32),
+            IpAddr::V6(_) => (IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 5)), 128),
+        };
+        Route {
+            id: "a".to_string(),
+            iface: "a".to_string(),
+            xroute: false,
+            installed: false,
+            neigh_ip,
+            prefix: IpNetwork::new(ip, prefix_len).unwrap(),
+            from_prefix: None,
+            metric: metric.into(),
+            refmetric: 400,
+            full_path_rtt: 10.0,
+            price: 10,
+            fee: 10,
+        }
+    }
+
     fn test_identity(ip: IpAddr) -> Identity {
         Identity {
             mesh_ip: ip,
@@ -968,7 +1579,8 @@ mod tests {
             installed: false,
             neigh_ip: random_ip,
             prefix: IpNetwork::new(ip1, 32).unwrap(),
-            metric: 400,
+            from_prefix: None,
+            metric: RouteMetric::Finite(400),
             refmetric: 400,
             full_path_rtt: 10.0,
             price: 10,
@@ -982,7 +1594,8 @@ mod tests {
             installed: false,
             neigh_ip: random_ip,
             prefix: IpNetwork::new(ip2, 32).unwrap(),
-            metric: 500,
+            from_prefix: None,
+            metric: RouteMetric::Finite(500),
             refmetric: 400,
             full_path_rtt: 10.0,
             price: 10,
@@ -996,7 +1609,8 @@ mod tests {
             installed: false,
             neigh_ip: random_ip,
             prefix: IpNetwork::new(ip3, 32).unwrap(),
-            metric: 200,
+            from_prefix: None,
+            metric: RouteMetric::Finite(200),
             refmetric: 400,
             full_path_rtt: 10.0,
             price: 10,
@@ -1010,7 +1624,8 @@ mod tests {
             installed: false,
             neigh_ip: random_ip,
             prefix: IpNetwork::new(random_ip, 32).unwrap(),
-            metric: 100,
+            from_prefix: None,
+            metric: RouteMetric::Finite(100),
             refmetric: 400,
             full_path_rtt: 10.0,
             price: 10,
@@ -1029,6 +1644,7 @@ mod tests {
         // Nothing is setup yet
         let (exit_down, _, c_e_met, _, t_e_m, b_exit, b_e_m) = get_exit_metrics(
             route_hashmap.clone(),
+            &HashMap::new(),
             None,
             None,
             None,
@@ -1046,6 +1662,7 @@ mod tests {
         // Only current exit is setup, not tracking yet
         let (exit_down, _, c_e_met, _, t_e_m, b_exit, b_e_m) = get_exit_metrics(
             route_hashmap.clone(),
+            &HashMap::new(),
             Some(ip1),
             None,
             Some(ip1),
@@ -1063,6 +1680,7 @@ mod tests {
         // current and tracking at setup and different from each other and best exit
         let (exit_down, _, c_e_met, _, t_e_m, b_exit, b_e_m) = get_exit_metrics(
             route_hashmap.clone(),
+            &HashMap::new(),
             Some(ip1),
             Some(ip2),
             Some(ip1),
@@ -1080,6 +1698,7 @@ mod tests {
         // Current and tracking are same but different from best exit
         let (exit_down, _, c_e_met, _, t_e_m, b_exit, b_e_m) = get_exit_metrics(
             route_hashmap.clone(),
+            &HashMap::new(),
             Some(ip2),
             Some(ip2),
             Some(ip2),
@@ -1097,6 +1716,7 @@ mod tests {
         // All three exits are the same
         let (exit_down, _, c_e_met, _, t_e_m, b_exit, b_e_m) = get_exit_metrics(
             route_hashmap,
+            &HashMap::new(),
             Some(ip3),
             Some(ip3),
             Some(ip3),
@@ -1112,6 +1732,378 @@ mod tests {
         assert_eq!(b_e_m, 200);
     }
 
+    #[test]
+    fn test_ipv6_exit_metrics() {
+        let ip1 = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+        let ip2 = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+        let ip3 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 3));
+        // a mixed family cluster, the ipv4 exit is unreachable
+        let exit_list = vec![test_identity(ip1), test_identity(ip2), test_identity(ip3)];
+        let mut route_hashmap = HashMap::new();
+        route_hashmap.insert(ip1, test_route(ip1, 500));
+        route_hashmap.insert(ip2, test_route(ip2, 400));
+        route_hashmap.insert(ip3, test_route(ip3, u16::MAX));
+
+        // initial setup, no current exit
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (exit_down, _, c_e_met, _, t_e_m, b_exit, b_e_m) = get_exit_metrics(
+            route_hashmap.clone(),
+            &HashMap::new(),
+            None,
+            None,
+            None,
+            u16::MAX,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert!(exit_down);
+        assert_eq!(c_e_met, u16::MAX);
+        assert_eq!(t_e_m, u16::MAX);
+        assert_eq!(b_exit.unwrap(), ip2);
+        assert_eq!(b_e_m, 400);
+
+        // connected to and tracking ip1, which is still up
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (exit_down, _, c_e_met, _, t_e_m, b_exit, b_e_m) = get_exit_metrics(
+            route_hashmap.clone(),
+            &HashMap::new(),
+            Some(ip1),
+            Some(ip1),
+            Some(ip1),
+            450,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert!(!exit_down);
+        assert_eq!(c_e_met, 500);
+        assert_eq!(t_e_m, 500);
+        assert_eq!(b_exit.unwrap(), ip2);
+        assert_eq!(b_e_m, 400);
+
+        // connected to the unreachable ipv4 exit, it is down and we pick the best ipv6 exit
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (exit_down, _, c_e_met, _, _, b_exit, _) = get_exit_metrics(
+            route_hashmap,
+            &HashMap::new(),
+            Some(ip3),
+            None,
+            Some(ip3),
+            300,
+            exit_list,
+            &mut exit_map,
+        )
+        .into();
+        assert!(exit_down);
+        assert_eq!(c_e_met, u16::MAX);
+        assert_eq!(b_exit.unwrap(), ip2);
+    }
+
+    #[test]
+    fn test_ipv6_set_best_exit() {
+        use settings::client::RitaClientSettings;
+        let ip1 = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0x11));
+        let ip2 = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0x12));
+        let random_ip = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 5));
+        RitaClientSettings::setup_test(test_identity(random_ip));
+        let mut route_hashmap = HashMap::new();
+        route_hashmap.insert(ip1, test_route(ip1, 500));
+        route_hashmap.insert(ip2, test_route(ip2, 400));
+
+        // with no exit selected yet we set up the best ipv6 exit
+        let selected =
+            set_best_exit(vec![test_identity(ip1), test_identity(ip2)], route_hashmap).unwrap();
+        assert_eq!(selected, ip2);
+        let selected_exit = get_full_selected_exit();
+        assert_eq!(selected_exit.selected_id, Some(ip2));
+        assert_eq!(selected_exit.selected_id_metric, Some(400));
+        assert_eq!(selected_exit.tracking_exit, Some(ip2));
+    }
+
+    #[test]
+    fn test_subnet_stickiness() {
+        let ip1 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+        let ip2 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 2));
+        // ip1 is our current exit, ip2 is a marginally better exit in another subnet
+        let mut route_hashmap = HashMap::new();
+        route_hashmap.insert(ip1, test_route(ip1, 400));
+        route_hashmap.insert(ip2, test_route(ip2, 380));
+        let subnet_exits: HashSet<IpAddr> = vec![ip1].into_iter().collect();
+        let exit_list = vec![test_identity(ip1), test_identity(ip2)];
+
+        // no stickiness, we pick the marginally better exit
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (_, _, _, _, _, b_exit, b_e_m) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_metric_bonus(get_selection_scores(&route_hashmap), &subnet_exits, 0),
+            Some(ip1),
+            None,
+            Some(ip1),
+            400,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert_eq!(b_exit.unwrap(), ip2);
+        assert_eq!(b_e_m, 380);
+
+        // with stickiness we stay within our subnet
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (_, _, _, _, _, b_exit, b_e_m) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_metric_bonus(get_selection_scores(&route_hashmap), &subnet_exits, 50),
+            Some(ip1),
+            None,
+            Some(ip1),
+            400,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert_eq!(b_exit.unwrap(), ip1);
+        // the bonus only affects selection, the reported metric is still babel's
+        assert_eq!(b_e_m, 400);
+
+        // a substantially better exit in another subnet still wins
+        route_hashmap.insert(ip2, test_route(ip2, 300));
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (_, _, _, _, _, b_exit, _) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_metric_bonus(get_selection_scores(&route_hashmap), &subnet_exits, 50),
+            Some(ip1),
+            None,
+            Some(ip1),
+            400,
+            exit_list,
+            &mut exit_map,
+        )
+        .into();
+        assert_eq!(b_exit.unwrap(), ip2);
+
+        // unreachable routes are never made to look reachable
+        route_hashmap.insert(ip1, test_route(ip1, u16::MAX));
+        let adjusted = apply_metric_bonus(get_selection_scores(&route_hashmap), &subnet_exits, 50);
+        assert!(!adjusted.get(&ip1).unwrap().is_reachable());
+    }
+
+    #[test]
+    fn test_region_preference() {
+        let ip1 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+        let ip2 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 2));
+        let exit_server = |ip: IpAddr, region: &str| ExitServer {
+            exit_id: test_identity(ip),
+            registration_port: 3452,
+            wg_exit_listen_port: 59998,
+            info: ExitState::GotInfo {
+                general_details: ExitDetails {
+                    server_internal_ip: ip,
+                    netmask: 24,
+                    wg_exit_port: 59998,
+                    exit_price: 10,
+                    exit_currency: SystemChain::Xdai,
+                    description: "".to_string(),
+                    verif_mode: ExitVerifMode::Off,
+                    region: Some(region.to_string()),
+                },
+                message: "".to_string(),
+            },
+        };
+        let mut exits = HashMap::new();
+        exits.insert(ip1, exit_server(ip1, "us-west"));
+        exits.insert(ip2, exit_server(ip2, "eu-central"));
+
+        // no preferred region, nothing gets a bonus
+        assert!(get_region_exits(None, &exits).is_empty());
+        let region_exits = get_region_exits(Some("US-West"), &exits);
+        assert_eq!(region_exits, vec![ip1].into_iter().collect());
+
+        // ip2 is comparable but marginally better, the region preference picks ip1
+        let mut route_hashmap = HashMap::new();
+        route_hashmap.insert(ip1, test_route(ip1, 400));
+        route_hashmap.insert(ip2, test_route(ip2, 380));
+        let exit_list = vec![test_identity(ip1), test_identity(ip2)];
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (_, _, _, _, _, b_exit, _) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_metric_bonus(get_selection_scores(&route_hashmap), &region_exits, 0),
+            None,
+            None,
+            None,
+            u16::MAX,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert_eq!(b_exit.unwrap(), ip2);
+
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (_, _, _, _, _, b_exit, _) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_metric_bonus(get_selection_scores(&route_hashmap), &region_exits, 50),
+            None,
+            None,
+            None,
+            u16::MAX,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert_eq!(b_exit.unwrap(), ip1);
+
+        // a client already on the out of region exit moves to the preferred region
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (exit_down, _, _, _, _, b_exit, b_e_m) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_metric_bonus(get_selection_scores(&route_hashmap), &region_exits, 50),
+            Some(ip2),
+            Some(ip2),
+            Some(ip2),
+            380,
+            exit_list,
+            &mut exit_map,
+        )
+        .into();
+        assert!(!exit_down);
+        assert_eq!(b_exit.unwrap(), ip1);
+        assert_eq!(b_e_m, 400);
+    }
+
+    #[test]
+    fn test_payment_degraded_exit() {
+        let ip1 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+        let ip2 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 2));
+        let exit_list = vec![test_identity(ip1), test_identity(ip2)];
+        let mut route_hashmap = HashMap::new();
+        route_hashmap.insert(ip1, test_route(ip1, 400));
+        route_hashmap.insert(ip2, test_route(ip2, 900));
+
+        // failures caused by our own balance don't count against the exit
+        let mut payment_health = HashMap::new();
+        payment_health.insert(
+            test_identity(ip1),
+            PaymentHealth {
+                our_failures: 10,
+                peer_failures: 0,
+            },
+        );
+        assert!(get_payment_degraded_exits(&exit_list, &payment_health).is_empty());
+
+        // ip1 has the better route but keeps refusing our payments
+        payment_health.insert(
+            test_identity(ip1),
+            PaymentHealth {
+                our_failures: 0,
+                peer_failures: PAYMENT_FAILURE_THRESHOLD,
+            },
+        );
+        let degraded = get_payment_degraded_exits(&exit_list, &payment_health);
+        assert_eq!(degraded, vec![ip1].into_iter().collect());
+
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (_, _, _, _, _, b_exit, _) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_metric_penalty(
+                get_selection_scores(&route_hashmap),
+                &degraded,
+                PAYMENT_FAILURE_PENALTY,
+            ),
+            None,
+            None,
+            None,
+            u16::MAX,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert_eq!(b_exit.unwrap(), ip2);
+
+        // we are connected to ip1 and it is up, the penalty still moves us off of it
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (exit_down, _, _, _, _, b_exit, b_e_m) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_metric_penalty(
+                get_selection_scores(&route_hashmap),
+                &degraded,
+                PAYMENT_FAILURE_PENALTY,
+            ),
+            Some(ip1),
+            Some(ip1),
+            Some(ip1),
+            400,
+            exit_list,
+            &mut exit_map,
+        )
+        .into();
+        assert!(!exit_down);
+        assert_eq!(b_exit.unwrap(), ip2);
+        assert_eq!(b_e_m, 900);
+    }
+
+    #[test]
+    fn test_exit_price_weight() {
+        let ip1 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+        let ip2 = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 2));
+        let route = |ip: IpAddr, metric: u16, price: u32| Route {
+            price,
+            ..test_route(ip, metric)
+        };
+        let exit_list = vec![test_identity(ip1), test_identity(ip2)];
+        // ip1 is slightly better but much more expensive
+        let mut route_hashmap = HashMap::new();
+        route_hashmap.insert(ip1, route(ip1, 400, 10_000_000));
+        route_hashmap.insert(ip2, route(ip2, 450, 1_000_000));
+
+        // without a price weight we select on metric alone
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (_, _, _, _, _, b_exit, b_e_m) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_price_weight(get_selection_scores(&route_hashmap), &route_hashmap, 0),
+            None,
+            None,
+            None,
+            u16::MAX,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert_eq!(b_exit.unwrap(), ip1);
+        assert_eq!(b_e_m, 400);
+
+        // 10 metric points per million units of price tips the decision to the cheaper exit
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (_, _, _, _, _, b_exit, b_e_m) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_price_weight(get_selection_scores(&route_hashmap), &route_hashmap, 10),
+            None,
+            None,
+            None,
+            u16::MAX,
+            exit_list.clone(),
+            &mut exit_map,
+        )
+        .into();
+        assert_eq!(b_exit.unwrap(), ip2);
+        assert_eq!(b_e_m, 450);
+        assert_eq!(exit_map.get(&ip2).unwrap().running_total, 450);
+
+        // the same holds when we are already connected to the expensive exit with the better metric
+        let mut exit_map: HashMap<IpAddr, ExitTracker> = HashMap::new();
+        let (exit_down, _, _, _, _, b_exit, _) = get_exit_metrics(
+            route_hashmap.clone(),
+            &apply_price_weight(get_selection_scores(&route_hashmap), &route_hashmap, 10),
+            Some(ip1),
+            Some(ip1),
+            Some(ip1),
+            400,
+            exit_list,
+            &mut exit_map,
+        )
+        .into();
+        assert!(!exit_down);
+        assert_eq!(b_exit.unwrap(), ip2);
+    }
+
     #[ignore]
     #[test]
     fn test_config_update() {
@@ -20,6 +20,7 @@ pub mod exit_loop;
 pub mod exit_switcher;
 pub mod time_sync;
 
+use crate::dashboard::bandwidth_limit::bandwidth_limit_interfaces;
 use crate::heartbeat::get_selected_exit_server;
 use crate::rita_loop::CLIENT_LOOP_TIMEOUT;
 use crate::RitaClientError;
@@ -46,14 +47,26 @@ use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::Nonce;
 use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
 use std::collections::{HashMap, HashSet};
 use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Duration;
 use std::time::Instant;
 
 /// The number of times ExitSwitcher will try to connect to an unresponsive exit before blacklisting its ip
 const MAX_BLACKLIST_STRIKES: u16 = 100;
 
+/// The initial interval at which we poll an exit that has us in the Pending state, this is the exit loop speed
+const PENDING_POLL_MIN: Duration = Duration::from_secs(5);
+/// The longest we will wait between polls of an exit that has us in the Pending state, since the user has to
+/// enter a verification code by hand there's little point in asking the exit more often than this
+const PENDING_POLL_MAX: Duration = Duration::from_secs(60);
+
 lazy_static! {
+    /// Set when the user submits a verification code so the exit loop can go back to polling
+    /// pending exits quickly
+    static ref VERIFICATION_CODE_SUBMITTED: AtomicBool = AtomicBool::new(false);
     pub static ref SELECTED_EXIT_DETAILS: Arc<RwLock<SelectedExitDetails>> =
         Arc::new(RwLock::new(SelectedExitDetails::default()));
 }
@@ -100,6 +113,53 @@ pub struct ExitManager {
     /// Store last exit here, when we see an exit change, we reset wg tunnels
     pub last_exit_state: LastExitStates,
     pub last_status_request: Option<Instant>,
+    /// Poll backoff for exits that have us in the Pending state, keyed by exit mesh ip
+    pub pending_poll: HashMap<IpAddr, PendingPollBackoff>,
+}
+
+/// While an exit has us in the Pending state we are waiting on the user to enter a verification code,
+/// which takes minutes, so we back off polling the exit for our state until a code is submitted
+#[derive(Debug, Clone, Copy)]
+pub struct PendingPollBackoff {
+    interval: Duration,
+    last_poll: Option<Instant>,
+}
+
+impl Default for PendingPollBackoff {
+    fn default() -> Self {
+        PendingPollBackoff {
+            interval: PENDING_POLL_MIN,
+            last_poll: None,
+        }
+    }
+}
+
+impl PendingPollBackoff {
+    /// Returns true if it's time to poll the exit again, every poll doubles the interval
+    /// until the next one up to PENDING_POLL_MAX
+    pub fn should_poll(&mut self, now: Instant) -> bool {
+        match self.last_poll {
+            Some(last_poll) if now.saturating_duration_since(last_poll) < self.interval => false,
+            Some(_) => {
+                self.interval = std::cmp::min(self.interval * 2, PENDING_POLL_MAX);
+                self.last_poll = Some(now);
+                true
+            }
+            None => {
+                self.last_poll = Some(now);
+                true
+            }
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+/// Returns true if a verification code has been submitted since the last call
+pub fn take_verification_code_submitted() -> bool {
+    VERIFICATION_CODE_SUBMITTED.swap(false, Ordering::Relaxed)
 }
 
 /// This functions sets the exit list ONLY IF the list arguments provived is not empty. This is need for the following edge case:
@@ -167,6 +227,7 @@ fn linux_setup_exit_tunnel(
         netmask: general_details.netmask,
         rita_hello_port: network.rita_hello_port,
         user_specified_speed: network.user_bandwidth_limit,
+        lan_interfaces: bandwidth_limit_interfaces(&rita_client.exit_client.lan_nics),
     };
 
     info!("Args while setting up wg_exit on client are: {:?}", args);
@@ -434,6 +495,9 @@ pub async fn exit_setup_request(code: Option<String>) -> Result<(), RitaClientEr
                     };
 
                 // Send a verification code if we have one
+                if code.is_some() {
+                    VERIFICATION_CODE_SUBMITTED.store(true, Ordering::Relaxed);
+                }
                 reg_details.phone_code = code;
 
                 let ident = ExitClientIdentity {
@@ -681,7 +745,7 @@ pub fn get_ready_to_switch_exits(exit_list: ExitListV2) -> Vec<Identity> {
     for exit in exit_list.exit_list {
         match exits.get(&exit.mesh_ip) {
             Some(server) => {
-                if let ExitState::Registered { .. } = server.info {
+                if server.info.is_registered() {
                     ret.push(exit_identity_to_id(exit));
                 }
             }
@@ -736,6 +800,32 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_pending_poll_backoff() {
+        let mut backoff = PendingPollBackoff::default();
+        let start = Instant::now();
+        assert!(backoff.should_poll(start));
+        assert_eq!(backoff.interval(), PENDING_POLL_MIN);
+        // too soon
+        assert!(!backoff.should_poll(start + Duration::from_secs(1)));
+
+        let mut now = start;
+        let mut last_interval = backoff.interval();
+        for _ in 0..3 {
+            now += last_interval;
+            assert!(backoff.should_poll(now));
+            assert!(backoff.interval() > last_interval);
+            last_interval = backoff.interval();
+        }
+
+        // the interval is capped
+        for _ in 0..10 {
+            now += backoff.interval();
+            assert!(backoff.should_poll(now));
+        }
+        assert_eq!(backoff.interval(), PENDING_POLL_MAX);
+    }
+
     #[test]
     fn test_exit_has_changed() {
         let mut exit_server = ExitServer {
@@ -763,6 +853,7 @@ mod tests {
             exit_currency: SystemChain::Xdai,
             description: "".to_string(),
             verif_mode: ExitVerifMode::Off,
+            region: None,
         };
         let mut last_states = LastExitStates::default();
 
@@ -7,6 +7,7 @@ use althea_types::LocalIdentity;
 use althea_types::WgKey;
 use babel_monitor::structs::Neighbor;
 use babel_monitor::structs::Route;
+use babel_monitor::structs::RouteMetric;
 use clarity::Address;
 use ipnetwork::IpNetwork;
 use rita_common::tunnel_manager::Neighbor as RitaNeighbor;
@@ -23,7 +24,8 @@ pub fn dummy_route() -> Route {
         installed: true,
         neigh_ip: IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
         prefix: IpNetwork::new(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)), 16).unwrap(),
-        metric: 100,
+        from_prefix: None,
+        metric: RouteMetric::Finite(100),
         refmetric: 200,
         full_path_rtt: 200.0,
         price: 100,
@@ -55,7 +57,7 @@ pub fn dummy_neigh_tunnel() -> RitaNeighbor {
             wg_port: 10,
             have_tunnel: None,
             global: Identity {
-                mesh_ip: IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+                mesh_ip: "fd00::1".parse().unwrap(),
                 eth_address: Address::parse_and_validate(
                     "0x9CAFD25b8b5982F1edA0691DEF8997C55a4d8188",
                 )
@@ -82,5 +84,6 @@ pub fn dummy_selected_exit_details() -> ExitDetails {
         exit_currency: althea_types::SystemChain::Ethereum,
         description: "".to_string(),
         verif_mode: althea_types::ExitVerifMode::Off,
+        region: None,
     }
 }
@@ -1,7 +1,10 @@
 //! Manages the Heartbeat from the router. This is a piece of metric data that is controlled by the system logging setting
 //! if logging is enabled the system will send a heartbeat in the Rita client loop every CLIENT_LOOP_SPEED seconds. This
 //! heartbeat contains data about routing, balance, and implicit in it's sending data that the router is up and functioning.
-//! This data is sent as a udp fire and forget packet. Take note that if this packet is larger than the MTU you may run into
+//! This data is sent as a udp packet, the operator server may optionally acknowledge it. Once a server has acked a heartbeat
+//! we wait up to HEARTBEAT_ACK_TIMEOUT for an ack of every heartbeat sent to it and resend once if none arrives. Servers that
+//! have never acked are sent each heartbeat once and only probed for an ack every HEARTBEAT_ACK_PROBE_INTERVAL heartbeats,
+//! so servers that do not ack still work without paying for the wait. Take note that if this packet is larger than the MTU you may run into
 //! issues, so be careful expanding it. It's usually about 1kbyte at the moment.
 //!
 //! Note that if an Operator address is configured it has the effect of forcing heartbeats on as the operator interface is
@@ -15,10 +18,12 @@
 
 use althea_kernel_interface::KI;
 use althea_types::ExitDetails;
+use althea_types::HeartbeatAck;
 
 use babel_monitor::parsing::get_installed_route;
 use babel_monitor::parsing::get_neigh_given_route;
 use babel_monitor::structs::BabelMonitorError;
+use babel_monitor::sum_path_price;
 
 use dummy::dummy_selected_exit_details;
 
@@ -34,6 +39,10 @@ use babel_monitor::structs::Neighbor;
 use babel_monitor::structs::Route;
 use settings::client::ExitServer;
 use sodiumoxide::crypto::box_;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::Nonce;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::SecretKey;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::iter::FromIterator;
 use std::net::ToSocketAddrs;
@@ -49,6 +58,8 @@ use dummy::dummy_neigh_tunnel;
 use dummy::dummy_route;
 
 use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::time::Duration;
@@ -56,13 +67,20 @@ use std::time::Duration;
 use crate::exit_manager::get_current_exit;
 
 pub const HEARTBEAT_LOOP_SPEED: u64 = 5;
+/// How long we wait for the operator server to acknowledge a heartbeat before resending it
+pub const HEARTBEAT_ACK_TIMEOUT: Duration = Duration::from_millis(500);
+/// The total number of times a heartbeat is sent if it is never acknowledged
+const HEARTBEAT_SEND_ATTEMPTS: u8 = 2;
+/// How often, in heartbeats, we wait to see if a server that has never acked has started to
+const HEARTBEAT_ACK_PROBE_INTERVAL: u64 = 60;
 
 mod dummy;
+#[derive(Clone)]
 pub struct HeartbeatCache {
     dns: VecDeque<SocketAddr>,
-    exit_route: Route,
-    exit_neighbor_babel: Neighbor,
-    exit_neighbor_rita: RitaNeighbor,
+    exit_route: Option<Route>,
+    exit_neighbor_babel: Option<Neighbor>,
+    exit_neighbor_rita: Option<RitaNeighbor>,
 }
 
 #[cfg(not(any(feature = "operator_debug", feature = "dev_env")))]
@@ -80,6 +98,9 @@ lazy_static! {
 lazy_static! {
     pub static ref HEARTBEAT_CACHE: Arc<RwLock<Option<HeartbeatCache>>> =
         Arc::new(RwLock::new(None));
+    static ref HEARTBEAT_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    /// Heartbeat servers that have acknowledged a heartbeat, only these are waited on and resent to
+    static ref HEARTBEAT_ACKING_SERVERS: RwLock<HashSet<SocketAddr>> = RwLock::new(HashSet::new());
 }
 
 pub fn send_heartbeat_loop() {
@@ -139,39 +160,37 @@ fn send_udp_heartbeat() {
     let dns_request = heartbeat_url.to_socket_addrs();
 
     // Check for the basics first, before doing any of the hard futures work
-    let mut our_id: Identity = if settings::get_rita_client().get_identity().is_some() {
-        trace!(
-            "Got identity: {} ",
-            settings::get_rita_client().get_identity().unwrap()
-        );
-        settings::get_rita_client().get_identity().unwrap()
-    } else {
-        trace!("Could not get identity!");
-        return;
+    let our_id: Identity = match settings::get_rita_client().get_identity() {
+        Some(id) => {
+            trace!("Got identity: {} ", id);
+            id
+        }
+        None => {
+            trace!("Could not get identity!");
+            return;
+        }
     };
-    let mut selected_exit_details: ExitDetails = dummy_selected_exit_details();
-
-    if !cfg!(feature = "operator_debug") {
-        if let (Some(id), Some(exit)) = (
-            settings::get_rita_client().get_identity(),
-            get_selected_exit_server(),
-        ) {
-            let exit_info = exit.info;
-            match exit_info.general_details() {
+
+    // a client that has not yet connected to an exit still heartbeats, just without
+    // any of the exit specific information, so that the operator server can tell
+    // the difference between a router that is offline and one that is up but has no exit
+    let selected_exit_details: Option<ExitDetails> = if cfg!(feature = "operator_debug") {
+        Some(dummy_selected_exit_details())
+    } else {
+        match get_selected_exit_server() {
+            Some(exit) => match exit.info.general_details() {
                 Some(details) => {
-                    our_id = id;
-                    selected_exit_details = details.clone();
-                    trace!("got exit details for id: {}", id);
+                    trace!("got exit details for id: {}", our_id);
+                    Some(details.clone())
                 }
                 None => {
                     trace!("got no exit details!");
-                    return;
+                    None
                 }
-            }
-        } else {
-            return;
-        };
-    }
+            },
+            None => None,
+        }
+    };
 
     trace!("we have heartbeat basic info");
 
@@ -186,9 +205,40 @@ fn send_udp_heartbeat() {
     // In this block we handle gathering all the info and the many ways gathering it could fail
     // once we have succeeded even if only once we have a cached value that is updated regularly
     // if for some reason the cache update fails, we can still progress with the heartbeat
+    let mut hb_cache = HEARTBEAT_CACHE.write().unwrap();
     match dns_request {
         Ok(dnsres) => {
             let dnsresult = VecDeque::from_iter(dnsres);
+            if let Some(ref mut hb_cache) = &mut *hb_cache {
+                trace!("we have heartbeat dns");
+                // having successfully talked to the DNS server does not mean we have any dns records
+                // this is where we disambiguate that. If we have seen records before we reject and refuse
+                // to update if the server tells us there are no longer any records. Yes this does actually
+                // happen very rarely, even on the worlds most reliable DNS servers
+                if !dnsresult.is_empty() {
+                    hb_cache.dns = dnsresult;
+                }
+            } else {
+                *hb_cache = Some(HeartbeatCache {
+                    dns: dnsresult,
+                    exit_route: None,
+                    exit_neighbor_babel: None,
+                    exit_neighbor_rita: None,
+                });
+            }
+        }
+        Err(e) => {
+            warn!("DNS resolution failed with {:?}", e);
+        }
+    }
+
+    if let Some(ref mut hb_cache) = &mut *hb_cache {
+        if selected_exit_details.is_none() {
+            // we are not connected to an exit, don't report stale exit info
+            hb_cache.exit_route = None;
+            hb_cache.exit_neighbor_babel = None;
+            hb_cache.exit_neighbor_rita = None;
+        } else {
             let selected_exit_route =
                 if cfg!(feature = "operator_debug") || cfg!(feature = "dev_env") {
                     Ok(dummy_route())
@@ -209,29 +259,9 @@ fn send_udp_heartbeat() {
                         };
 
                     if let Some((neigh, rita_neigh)) = neigh_option {
-                        // Now that we have all the info we can stop and try to update the
-                        // heartbeat cache
-                        let mut hb_cache = HEARTBEAT_CACHE.write().unwrap();
-                        if let Some(ref mut hb_cache) = &mut *hb_cache {
-                            trace!("we have heartbeat dns");
-                            // having successfully talked to the DNS server does not mean we have any dns records
-                            // this is where we disambiguate that. If we have seen records before we reject and refuse
-                            // to update if the server tells us there are no longer any records. Yes this does actually
-                            // happen very rarely, even on the worlds most reliable DNS servers
-                            if !dnsresult.is_empty() {
-                                hb_cache.dns = dnsresult;
-                            }
-                            hb_cache.exit_route = route;
-                            hb_cache.exit_neighbor_babel = neigh;
-                            hb_cache.exit_neighbor_rita = rita_neigh;
-                        } else {
-                            *hb_cache = Some(HeartbeatCache {
-                                dns: dnsresult,
-                                exit_route: route,
-                                exit_neighbor_babel: neigh,
-                                exit_neighbor_rita: rita_neigh,
-                            });
-                        }
+                        hb_cache.exit_route = Some(route);
+                        hb_cache.exit_neighbor_babel = Some(neigh);
+                        hb_cache.exit_neighbor_rita = Some(rita_neigh);
                     } else {
                         warn!("Failed to find neigh for heartbeat!");
                     }
@@ -239,25 +269,30 @@ fn send_udp_heartbeat() {
                 Err(e) => warn!("Failed to get heartbeat route with {:?}", e),
             }
         }
-        Err(e) => {
-            warn!("DNS resolution failed with {:?}", e);
-        }
     }
+    drop(hb_cache);
+
     // Now we actually send the heartbeat, using the cached data if it is
     // available. We should only ever see it not be available for short periods
-    // on startup
-    let hb_cache = &*HEARTBEAT_CACHE.read().unwrap();
+    // on startup. The cache is copied out so that the lock is not held while
+    // we wait on acks from the heartbeat servers
+    let hb_cache = HEARTBEAT_CACHE.read().unwrap().clone();
     if let Some(hb_cache) = hb_cache {
+        let sequence = HEARTBEAT_SEQUENCE.fetch_add(1, Ordering::Relaxed);
         // this is intentional behavior, if we have multiple DNS records we should
         // send heartbeats to all of them
         for dns_socket in hb_cache.dns.iter() {
             send_udp_heartbeat_packet(
                 dns_socket,
                 our_id,
-                selected_exit_details.exit_price,
+                selected_exit_details.as_ref().map(|d| d.exit_price),
                 hb_cache.exit_route.clone(),
                 hb_cache.exit_neighbor_babel.clone(),
-                hb_cache.exit_neighbor_rita.identity.global,
+                hb_cache
+                    .exit_neighbor_rita
+                    .as_ref()
+                    .map(|n| n.identity.global),
+                sequence,
             );
         }
     } else {
@@ -309,10 +344,11 @@ fn get_rita_neighbor(neigh: &Neighbor, rita_neighbors: &[RitaNeighbor]) -> Optio
 fn send_udp_heartbeat_packet(
     dns_socket: &SocketAddr,
     our_id: Identity,
-    exit_price: u64,
-    exit_route: Route,
-    exit_neighbor: Neighbor,
-    exit_neighbor_id: Identity,
+    exit_price: Option<u64>,
+    exit_route: Option<Route>,
+    exit_neighbor: Option<Neighbor>,
+    exit_neighbor_id: Option<Identity>,
+    sequence: u64,
 ) {
     trace!("building heartbeat packet");
     let rita_client = settings::get_rita_client();
@@ -321,12 +357,12 @@ fn send_udp_heartbeat_packet(
         .exit_client
         .low_balance_notification;
     let our_publickey = network_settings.wg_public_key.expect("No public key?");
-    let our_secretkey = network_settings
+    let our_secretkey: SecretKey = network_settings
         .wg_private_key
         .expect("No private key?")
         .into();
     let their_publickey: WgKey = *HEARTBEAT_SERVER_KEY;
-    let their_publickey = their_publickey.into();
+    let their_publickey: PublicKey = their_publickey.into();
     drop(network_settings);
 
     let remote_port = dns_socket.port();
@@ -350,18 +386,20 @@ fn send_udp_heartbeat_packet(
         "Sending heartbeat to {:?} from {:?}",
         remote, local_socketaddr
     );
-    let mut rita_client = settings::get_rita_client();
-    let payment = rita_client.payment;
     let message = HeartbeatMessage {
         id: our_id,
         organizer_address: settings::get_rita_client().operator.operator_address,
         balance: get_oracle_balance(),
-        exit_dest_price: exit_price + exit_route.price as u64,
+        exit_dest_price: sum_path_price([
+            exit_price.unwrap_or(0),
+            exit_route.as_ref().map(|r| r.price as u64).unwrap_or(0),
+        ]),
         upstream_id: exit_neighbor_id,
         exit_route,
         exit_neighbor,
         notify_balance: low_balance_notification,
         version: env!("CARGO_PKG_VERSION").to_string(),
+        sequence,
     };
     // serde will only fail under specific circumstances with specific structs
     // given the fixed nature of our application here I think this is safe
@@ -380,18 +418,193 @@ fn send_udp_heartbeat_packet(
         return;
     }
 
-    match local_socket.send_to(&packet_contents, remote) {
-        Ok(bytes) => info!("Sent {} heartbeat bytes", bytes),
-        Err(e) => error!("Failed to send heartbeat with {:?}", e),
+    let known_to_ack = HEARTBEAT_ACKING_SERVERS.read().unwrap().contains(remote);
+    let probe = sequence % HEARTBEAT_ACK_PROBE_INTERVAL == 0;
+    let (sends, acked) = send_until_acked(
+        || match local_socket.send_to(&packet_contents, remote) {
+            Ok(bytes) => {
+                info!("Sent {} heartbeat bytes", bytes);
+                true
+            }
+            Err(e) => {
+                error!("Failed to send heartbeat with {:?}", e);
+                false
+            }
+        },
+        || wait_for_heartbeat_ack(&local_socket, sequence, &their_publickey, &our_secretkey),
+        known_to_ack,
+        probe,
+    );
+    if acked && !known_to_ack {
+        info!("Heartbeat server {} acks heartbeats", remote);
+        HEARTBEAT_ACKING_SERVERS.write().unwrap().insert(*remote);
+    } else if !acked && known_to_ack {
+        info!(
+            "Heartbeat {} was not acknowledged by {}, sent {} times",
+            sequence, remote, sends
+        );
+        HEARTBEAT_ACKING_SERVERS.write().unwrap().remove(remote);
+    }
+}
+
+/// Sends a heartbeat, if the server is known to ack heartbeats we wait for the ack and resend once
+/// if it does not arrive. Servers not known to ack are sent the heartbeat once, and only waited on
+/// when probing to see if they have started acking. Returns the number of times the heartbeat was
+/// sent and if it was acked
+fn send_until_acked<S, A>(
+    mut send: S,
+    mut wait_for_ack: A,
+    known_to_ack: bool,
+    probe: bool,
+) -> (u8, bool)
+where
+    S: FnMut() -> bool,
+    A: FnMut() -> bool,
+{
+    if !known_to_ack {
+        let acked = send() && probe && wait_for_ack();
+        return (1, acked);
+    }
+    let mut sends = 0;
+    while sends < HEARTBEAT_SEND_ATTEMPTS {
+        sends += 1;
+        if send() && wait_for_ack() {
+            return (sends, true);
+        }
+    }
+    (sends, false)
+}
+
+/// Waits up to HEARTBEAT_ACK_TIMEOUT for an ack of the heartbeat with the given sequence number,
+/// the ack is sent back to the socket the heartbeat was sent from in the format Nonce, Ciphertext
+fn wait_for_heartbeat_ack(
+    socket: &UdpSocket,
+    sequence: u64,
+    their_publickey: &PublicKey,
+    our_secretkey: &SecretKey,
+) -> bool {
+    if let Err(e) = socket.set_read_timeout(Some(HEARTBEAT_ACK_TIMEOUT)) {
+        trace!("Failed to set heartbeat ack timeout {:?}", e);
+        return false;
+    }
+    let mut buf = [0u8; 1024];
+    let bytes = match socket.recv_from(&mut buf) {
+        Ok((bytes, _)) => bytes,
+        Err(e) => {
+            trace!("No heartbeat ack received {:?}", e);
+            return false;
+        }
+    };
+    if bytes < box_::NONCEBYTES {
+        return false;
+    }
+    let nonce = match Nonce::from_slice(&buf[..box_::NONCEBYTES]) {
+        Some(nonce) => nonce,
+        None => return false,
+    };
+    let plaintext = match box_::open(
+        &buf[box_::NONCEBYTES..bytes],
+        &nonce,
+        their_publickey,
+        our_secretkey,
+    ) {
+        Ok(plaintext) => plaintext,
+        Err(_) => {
+            warn!("Could not decrypt heartbeat ack");
+            return false;
+        }
+    };
+    match serde_json::from_slice::<HeartbeatAck>(&plaintext) {
+        Ok(ack) => ack.sequence == sequence,
+        Err(e) => {
+            warn!("Could not deserialize heartbeat ack with {:?}", e);
+            false
+        }
     }
-    rita_client.payment = payment;
-    settings::set_rita_client(rita_client);
 }
 
 #[cfg(test)]
 mod tests {
+    use super::send_until_acked;
     use std::net::ToSocketAddrs;
 
+    #[test]
+    fn test_unacked_heartbeat_resent_once() {
+        let mut sends = 0;
+        let mut waits = 0;
+        let res = send_until_acked(
+            || {
+                sends += 1;
+                true
+            },
+            || {
+                waits += 1;
+                false
+            },
+            true,
+            false,
+        );
+        assert_eq!(res, (2, false));
+        assert_eq!(sends, 2);
+        assert_eq!(waits, 2);
+
+        // an acked heartbeat is only sent once
+        let mut sends = 0;
+        let res = send_until_acked(
+            || {
+                sends += 1;
+                true
+            },
+            || true,
+            true,
+            false,
+        );
+        assert_eq!(res, (1, true));
+        assert_eq!(sends, 1);
+    }
+
+    #[test]
+    fn test_heartbeat_not_resent_to_unknown_server() {
+        // a server that has never acked is sent the heartbeat once without waiting
+        let mut sends = 0;
+        let mut waits = 0;
+        let res = send_until_acked(
+            || {
+                sends += 1;
+                true
+            },
+            || {
+                waits += 1;
+                false
+            },
+            false,
+            false,
+        );
+        assert_eq!(res, (1, false));
+        assert_eq!(sends, 1);
+        assert_eq!(waits, 0);
+
+        // when probing we wait once to learn if it acks, but never resend
+        let mut sends = 0;
+        let mut waits = 0;
+        let res = send_until_acked(
+            || {
+                sends += 1;
+                true
+            },
+            || {
+                waits += 1;
+                false
+            },
+            false,
+            true,
+        );
+        assert_eq!(res, (1, false));
+        assert_eq!(sends, 1);
+        assert_eq!(waits, 1);
+        assert_eq!(send_until_acked(|| true, || true, false, true), (1, true));
+    }
+
     #[test]
     fn check_resolver() {
         let heartbeat_url = "dai.althea.net:33333";
@@ -15,17 +15,26 @@
 //! querying an API for an individual bill. As this is not designed to be a trustless payment
 
 use althea_types::Identity;
-use althea_types::PaymentTx;
+use althea_types::UnpublishedPaymentTx;
+use clarity::Address;
+use clarity::PrivateKey;
 use num256::Uint256;
+use num_traits::{CheckedAdd, CheckedMul};
+use rita_common::blockchain_oracle::get_gas_limit;
 use rita_common::blockchain_oracle::get_oracle_balance;
 use rita_common::blockchain_oracle::get_pay_thresh;
+use rita_common::blockchain_oracle::TransactionType;
+use rita_common::payment_controller::PaymentRetry;
+use rita_common::payment_controller::RetryDecision;
 use rita_common::payment_controller::TRANSACTION_SUBMISSION_TIMEOUT;
 use rita_common::rita_loop::get_web3_server;
 use rita_common::simulated_txfee_manager::add_tx_to_total;
 use rita_common::usage_tracker::update_payments;
 use std::sync::{Arc, RwLock};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use web30::client::Web3;
+use web30::jsonrpc::error::Web3Error;
+use web30::types::SendTxOption;
 
 lazy_static! {
     static ref OPERATOR_FEE_DATA: Arc<RwLock<OperatorFeeManager>> =
@@ -47,6 +56,8 @@ struct OperatorFeeManager {
     /// by just computing off of the last updated time, if the operator fee is changed while
     /// the node is live it will result in a large back-payment
     operator_debt: Uint256,
+    /// Failed attempts to pay the operator, limited by the payment retry settings
+    retry: PaymentRetry,
 }
 
 impl OperatorFeeManager {
@@ -54,6 +65,7 @@ impl OperatorFeeManager {
         OperatorFeeManager {
             last_updated: Instant::now(),
             operator_debt: 0u8.into(),
+            retry: PaymentRetry::default(),
         }
     }
 }
@@ -95,15 +107,36 @@ pub async fn tick_operator_payments() {
     set_operator_fee_data(state.clone());
 
     // reassign to an immutable variable to prevent mistakes
-    let amount_to_pay = state.operator_debt;
+    let amount_to_pay = cap_operator_payment(
+        state.operator_debt,
+        operator_settings.operator_fee_max_payment,
+    );
 
-    // we should pay if the amount is greater than the pay threshold and if we have the
+    // we should pay if the debt is greater than the pay threshold and if we have the
     // balance to do so.
-    let should_pay = amount_to_pay.to_int256().unwrap_or_else(|| 0u64.into()) > pay_threshold
+    let should_pay = state
+        .operator_debt
+        .to_int256()
+        .unwrap_or_else(|| 0u64.into())
+        > pay_threshold
         && amount_to_pay <= our_balance.unwrap_or_else(|| 0u64.into());
     trace!("We should pay our operator {}", should_pay);
 
     if should_pay {
+        state.retry.reset_for_payee(operator_address);
+        match state.retry.decide(
+            payment_settings.max_payment_retries,
+            Duration::from_secs(payment_settings.payment_retry_backoff_seconds),
+        ) {
+            RetryDecision::Attempt => {}
+            RetryDecision::Wait => return,
+            RetryDecision::GiveUp => {
+                // the debt is retained, nothing more is paid until the operator changes
+                trace!("Gave up on paying the operator fee");
+                set_operator_fee_data(state);
+                return;
+            }
+        }
         trace!("Paying subnet operator fee to {}", operator_address);
 
         let operator_identity = Identity {
@@ -116,43 +149,222 @@ pub async fn tick_operator_payments() {
             mesh_ip: "::1".parse().unwrap(),
             nickname: None,
         };
+        let payment = UnpublishedPaymentTx {
+            to: operator_identity,
+            from: our_id,
+            amount: amount_to_pay,
+        };
+        if let Err(e) = payment.validate(&our_id) {
+            error!("Not paying the operator fee: {}", e);
+            return;
+        }
 
-        let full_node = get_web3_server();
+        let full_node = match get_web3_server() {
+            Ok(node) => node,
+            Err(e) => {
+                warn!("Unable to pay the operator fee with {}", e);
+                return;
+            }
+        };
         let web3 = Web3::new(&full_node, TRANSACTION_SUBMISSION_TIMEOUT);
+        let gas_limit = get_gas_limit(payment_settings.system_chain, TransactionType::Transfer);
 
-        let tx = web3
-            .prepare_transaction(
-                operator_address,
-                Vec::new(),
-                amount_to_pay,
-                eth_private_key,
-                vec![],
-            )
-            .await;
-        match tx {
-            Ok(tx) => match web3.send_prepared_transaction(tx).await {
-                Ok(txid) => {
-                    info!(
-                        "Successfully paid the operator {} wei with txid: {:#066x}!",
-                        amount_to_pay, txid
-                    );
-                    update_payments(PaymentTx {
-                        to: operator_identity,
-                        from: our_id,
-                        amount: amount_to_pay,
-                        txid,
-                    });
-                    add_tx_to_total(amount_to_pay);
-                    state.operator_debt -= amount_to_pay;
-                    set_operator_fee_data(state);
-                }
-                Err(e) => {
-                    warn!("Failed to pay the operator! {:?}", e);
-                }
-            },
+        // a transaction we can not cover would only fail on broadcast, so this is checked before
+        // signing and nothing is advanced, the payment is attempted again next tick
+        let gas_price = match web3.eth_gas_price().await {
+            Ok(gas_price) => gas_price,
             Err(e) => {
+                warn!(
+                    "Unable to get the gas price for the operator fee with {:?}",
+                    e
+                );
+                return;
+            }
+        };
+        if !can_afford_payment(our_balance, amount_to_pay, gas_price, gas_limit) {
+            warn!(
+                "Balance {:?} can not cover the operator fee of {} plus gas, skipping",
+                our_balance, amount_to_pay
+            );
+            return;
+        }
+
+        let mut res = send_operator_payment(
+            &web3,
+            operator_address,
+            amount_to_pay,
+            eth_private_key,
+            gas_limit,
+        )
+        .await;
+        if let Err(e) = &res {
+            if is_nonce_too_low(e) {
+                // another payment from this address got the nonce first, the next attempt
+                // fetches a fresh nonce so it is safe to try once more right away
+                warn!("Operator payment nonce was too low, retrying once");
+                res = send_operator_payment(
+                    &web3,
+                    operator_address,
+                    amount_to_pay,
+                    eth_private_key,
+                    gas_limit,
+                )
+                .await;
+            }
+        }
+        match res {
+            Ok(txid) => {
+                info!(
+                    "Successfully paid the operator {} wei with txid: {:#066x}!",
+                    amount_to_pay, txid
+                );
+                update_payments(payment.publish(txid));
+                add_tx_to_total(amount_to_pay);
+                state.operator_debt -= amount_to_pay;
+                state.retry = PaymentRetry::default();
+                set_operator_fee_data(state);
+            }
+            Err(e) => {
+                // the debt is only reduced by a payment that was sent, so a failure leaves the
+                // full amount owed to be paid by the next attempt rather than underpaying
                 warn!("Failed to pay the operator! {:?}", e);
+                state.retry.failed();
+                if state.retry.failures() >= payment_settings.max_payment_retries {
+                    error!(
+                        "Failed to pay the operator after {} attempts, giving up until the operator changes",
+                        state.retry.failures()
+                    );
+                }
+                set_operator_fee_data(state);
             }
         }
     }
 }
+
+/// Limits a single operator payment to the configured maximum, the remaining debt stays on the
+/// books and is paid off by later payments
+fn cap_operator_payment(debt: Uint256, max_payment: Option<Uint256>) -> Uint256 {
+    match max_payment {
+        Some(max) if debt > max => {
+            info!(
+                "Operator debt {} is over the max payment of {}, paying the max",
+                debt, max
+            );
+            max
+        }
+        _ => debt,
+    }
+}
+
+/// Returns true if the balance covers the payment along with the gas to send it, a cost too
+/// large to represent is never affordable
+fn can_afford_payment(
+    balance: Option<Uint256>,
+    amount: Uint256,
+    gas_price: Uint256,
+    gas_limit: Uint256,
+) -> bool {
+    let cost = gas_price
+        .checked_mul(&gas_limit)
+        .and_then(|gas| amount.checked_add(&gas));
+    match (balance, cost) {
+        (Some(balance), Some(cost)) => balance >= cost,
+        _ => false,
+    }
+}
+
+/// Builds and sends a single operator payment, a fresh nonce is fetched for every call
+async fn send_operator_payment(
+    web3: &Web3,
+    operator_address: Address,
+    amount: Uint256,
+    eth_private_key: PrivateKey,
+    gas_limit: Uint256,
+) -> Result<Uint256, Web3Error> {
+    let tx = web3
+        .prepare_transaction(
+            operator_address,
+            Vec::new(),
+            amount,
+            eth_private_key,
+            vec![SendTxOption::GasLimit(gas_limit)],
+        )
+        .await?;
+    web3.send_prepared_transaction(tx).await
+}
+
+/// Returns true if the full node rejected a transaction because its nonce was already used
+fn is_nonce_too_low(e: &Web3Error) -> bool {
+    match e {
+        Web3Error::JsonRpcError { message, .. } => message.to_lowercase().contains("nonce too low"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::Bounded;
+
+    #[test]
+    fn test_operator_payment_cap() {
+        let debt: Uint256 = 1000u32.into();
+        assert_eq!(cap_operator_payment(debt, None), debt);
+        assert_eq!(cap_operator_payment(debt, Some(2000u32.into())), debt);
+        assert_eq!(
+            cap_operator_payment(debt, Some(300u32.into())),
+            300u32.into()
+        );
+    }
+
+    #[test]
+    fn test_operator_payment_affordable() {
+        let amount: Uint256 = 1000u32.into();
+        let gas_price: Uint256 = 2u32.into();
+        let gas_limit: Uint256 = 21000u32.into();
+        assert!(can_afford_payment(
+            Some(43000u32.into()),
+            amount,
+            gas_price,
+            gas_limit
+        ));
+        // enough for the payment but not the gas
+        assert!(!can_afford_payment(
+            Some(42999u32.into()),
+            amount,
+            gas_price,
+            gas_limit
+        ));
+        assert!(!can_afford_payment(None, amount, gas_price, gas_limit));
+        // a cost that overflows is not affordable, even with the largest possible balance
+        assert!(!can_afford_payment(
+            Some(Uint256::max_value()),
+            amount,
+            Uint256::max_value(),
+            gas_limit
+        ));
+        assert!(!can_afford_payment(
+            Some(Uint256::max_value()),
+            Uint256::max_value(),
+            gas_price,
+            gas_limit
+        ));
+    }
+
+    #[test]
+    fn test_nonce_too_low_detected() {
+        let e = Web3Error::JsonRpcError {
+            code: -32000,
+            message: "Nonce too low".to_string(),
+            data: String::new(),
+        };
+        assert!(is_nonce_too_low(&e));
+        let e = Web3Error::JsonRpcError {
+            code: -32000,
+            message: "insufficient funds for gas * price + value".to_string(),
+            data: String::new(),
+        };
+        assert!(!is_nonce_too_low(&e));
+        assert!(!is_nonce_too_low(&Web3Error::TransactionTimeout));
+    }
+}
@@ -4,6 +4,7 @@ pub mod update_loop;
 pub mod updater;
 extern crate openssh_keys;
 use crate::dashboard::system_chain::set_system_blockchain;
+use crate::exit_manager::exit_switcher::get_exit_switch_counts;
 use crate::exit_manager::{get_client_pub_ipv6, get_current_exit};
 use crate::rita_loop::is_gateway_client;
 use crate::{
@@ -11,10 +12,11 @@ use crate::{
     RitaClientError,
 };
 use althea_kernel_interface::hardware_info::get_hardware_info;
+use althea_kernel_interface::opkg_feeds::{get_release_feed, set_release_feed, CUSTOMFEEDS};
 use althea_types::{get_sequence_num, UsageTrackerTransfer};
 use althea_types::{
     AuthorizedKeys, BillingDetails, ContactStorage, ContactType, CurExitInfo, ExitConnection,
-    HardwareInfo, OperatorAction, OperatorCheckinMessage, OperatorUpdateMessage,
+    HardwareInfo, OperatorAction, OperatorCheckinMessage, OperatorUpdateMessage, ReleaseStatus,
 };
 use num256::Uint256;
 use rita_common::rita_loop::is_gateway;
@@ -47,6 +49,10 @@ const FORBIDDEN_MERGE_VALUES: [&str; 5] = [
     "peer_interfaces",
 ];
 
+/// Settings keys with these suffixes hold urls, or lists of urls, these must have a scheme
+/// or the router will crash when it tries to use them
+const URL_KEY_SUFFIXES: [&str; 5] = ["_url", "_server", "_node", "node_list", "grpc_list"];
+
 lazy_static! {
     /// stores the startup time for Rita, used to compute uptime
     static ref RITA_UPTIME: Instant = Instant::now();
@@ -59,6 +65,9 @@ const TARGET_UPDATE_FREQUENCY: Duration = Duration::from_secs(5);
 /// we will not go above this amount of time
 const UPDATE_FREQUENCY_CAP: Duration = Duration::from_secs(3600);
 
+/// The name of our feed in the opkg customfeeds file
+const FIRMWARE_FEED_NAME: &str = "althea";
+
 /// Checks in with the operator server
 pub async fn operator_update(
     ops_last_seen_usage_hour: Option<u64>,
@@ -137,6 +146,7 @@ pub async fn operator_update(
     let exit_con = Some(ExitConnection {
         cur_exit,
         client_pub_ipv6: get_client_pub_ipv6(),
+        exit_switch_counts: get_exit_switch_counts(),
     });
 
     let client = awc::Client::default();
@@ -336,6 +346,14 @@ fn perform_operator_update(
         }
         None => {}
     }
+    if let Some(firmware_feed) = new_settings.firmware_feed {
+        if let Err(e) = apply_firmware_feed(&firmware_feed) {
+            error!(
+                "Failed to apply firmware feed {:?} with {}",
+                firmware_feed, e
+            );
+        }
+    }
     if let Some(shaper_settings) = new_settings.shaper_settings {
         network.shaper_settings = shaper_settings;
     }
@@ -347,6 +365,42 @@ fn perform_operator_update(
     trace!("Successfully completed OperatorUpdate");
 }
 
+/// Returns the opkg feed url for the given release status based on the current althea line of the
+/// feeds file, rejecting urls without a scheme since opkg will fail on every update once such a url
+/// is written into the feeds file
+fn firmware_feed_url(feed: &ReleaseStatus, current_line: &str) -> Result<String, RitaClientError> {
+    // the line is 'src/gz althea <url>'
+    let current = current_line.split_whitespace().last().unwrap_or_default();
+    let url = match feed.feed_url(current) {
+        Some(url) => url,
+        None => {
+            return Err(RitaClientError::MiscStringError(format!(
+                "Can't set the firmware feed from the current feed {current_line}"
+            )))
+        }
+    };
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(url)
+    } else {
+        Err(RitaClientError::MiscStringError(format!(
+            "Firmware feed url {url} has no http:// or https:// scheme"
+        )))
+    }
+}
+
+/// Points the althea opkg feed at the given release, this is a disk write so it only
+/// happens when the feed actually changes
+fn apply_firmware_feed(feed: &ReleaseStatus) -> Result<(), RitaClientError> {
+    let current = get_release_feed(CUSTOMFEEDS, FIRMWARE_FEED_NAME)?;
+    let url = firmware_feed_url(feed, &current)?;
+    if current.ends_with(&url) {
+        return Ok(());
+    }
+    info!("Setting firmware feed to {}", url);
+    set_release_feed(&url, FIRMWARE_FEED_NAME, CUSTOMFEEDS)?;
+    Ok(())
+}
+
 // cycles in/out ssh pubkeys for recovery access
 fn update_authorized_keys(
     add_list: Vec<String>,
@@ -558,6 +612,11 @@ fn merge_settings_safely(client_settings: &mut RitaClientSettings, new_settings:
     trace!("Got new settings from server {:?}", new_settings);
     // merge in arbitrary setting change string if it's not blank
     if new_settings != "" {
+        let mut new_settings = new_settings;
+        if let Err(e) = normalize_url_fields(&mut new_settings) {
+            error!("Rejecting OperatorUpdate settings merge, {}", e);
+            return;
+        }
         if let Value::Object(map) = new_settings.clone() {
             let contains_forbidden_key = contains_forbidden_key(map, &FORBIDDEN_MERGE_VALUES);
             if !contains_forbidden_key {
@@ -575,6 +634,44 @@ fn merge_settings_safely(client_settings: &mut RitaClientSettings, new_settings:
     }
 }
 
+/// Recursively traverses down a json object looking for url settings, see URL_KEY_SUFFIXES.
+/// A url without a scheme has https:// prepended, a url with a scheme other than http or https
+/// is an error
+fn normalize_url_fields(value: &mut Value) -> Result<(), String> {
+    if let Value::Object(map) = value {
+        for (key, item) in map.iter_mut() {
+            if URL_KEY_SUFFIXES.iter().any(|suffix| key.ends_with(suffix)) {
+                match item {
+                    Value::String(url) => normalize_url(key, url)?,
+                    Value::Array(urls) => {
+                        for url in urls.iter_mut() {
+                            if let Value::String(url) = url {
+                                normalize_url(key, url)?;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            } else {
+                normalize_url_fields(item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn normalize_url(key: &str, url: &mut String) -> Result<(), String> {
+    if url.is_empty() || url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else if url.contains("://") {
+        Err(format!("{key} has unsupported url scheme {url}"))
+    } else {
+        warn!("{} url {} has no scheme, assuming https://", key, url);
+        *url = format!("https://{url}");
+        Ok(())
+    }
+}
+
 /// Recursively traverses down a json object looking for items in the
 /// forbidden keys list
 fn contains_forbidden_key(map: Map<String, Value>, forbidden_values: &[&str]) -> bool {
@@ -1,8 +1,11 @@
 #[cfg(test)]
 mod test {
     use crate::operator_update::contains_forbidden_key;
+    use crate::operator_update::firmware_feed_url;
+    use crate::operator_update::normalize_url_fields;
     use crate::operator_update::prepare_usage_data_for_upload;
     use crate::operator_update::update_authorized_keys;
+    use althea_types::ReleaseStatus;
     use serde_json::json;
     use serde_json::Value;
     use std::fs::File;
@@ -11,6 +14,26 @@ mod test {
 
     const FORBIDDEN_MERGE_VALUES: [&str; 2] = ["test_key", "other_test_key"];
 
+    #[test]
+    fn test_firmware_feed_url_requires_scheme() {
+        let current =
+            "src/gz althea https://updates.altheamesh.com/beta20rc1/packages/mipsel_24kc/althea";
+        assert_eq!(
+            firmware_feed_url(&ReleaseStatus::GeneralAvailability, current).unwrap(),
+            "https://updates.altheamesh.com/ga/packages/mipsel_24kc/althea"
+        );
+        assert!(firmware_feed_url(
+            &ReleaseStatus::Custom("updates.example.com".to_string()),
+            current
+        )
+        .is_err());
+        assert!(firmware_feed_url(
+            &ReleaseStatus::GeneralAvailability,
+            "src/gz althea www.dummyurl.com"
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_contains_key() {
         // exact key match should fail
@@ -29,6 +52,29 @@ mod test {
             panic!("Not a json map!");
         }
     }
+
+    #[test]
+    fn test_normalize_url_fields() {
+        let mut object = json!({
+            "log": { "dest_url": "stats.altheamesh.com:9999/sink/", "enabled": true },
+            "payment": { "eth_node_list": ["https://dai.althea.net", "xdai.example.com:8545"] },
+        });
+        normalize_url_fields(&mut object).unwrap();
+        assert_eq!(
+            object["log"]["dest_url"],
+            "https://stats.altheamesh.com:9999/sink/"
+        );
+        assert_eq!(object["log"]["enabled"], true);
+        assert_eq!(
+            object["payment"]["eth_node_list"],
+            json!(["https://dai.althea.net", "https://xdai.example.com:8545"])
+        );
+
+        // an unusable scheme rejects the merge entirely
+        let mut object = json!({"log": { "dest_url": "ftp://stats.altheamesh.com" }});
+        assert!(normalize_url_fields(&mut object).is_err());
+    }
+
     fn touch_temp_file(file_name: &str) -> &str {
         let test_file = std::fs::OpenOptions::new()
             .create(true)
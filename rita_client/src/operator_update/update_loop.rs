@@ -31,9 +31,12 @@ pub fn start_operator_update_loop() {
 
                     let runner = AsyncSystem::new();
                     runner.block_on(async {
-                        // timeout should never exceed this amount, beyond here we want to back off, but not
-                        // wait that long for a response
-                        let timeout = min(Duration::from_secs(120), wait_unti_next_update);
+                        // timeout should never exceed the configured checkin timeout, beyond here we want
+                        // to back off, but not wait that long for a response
+                        let checkin_timeout = Duration::from_secs(
+                            settings::get_rita_client().operator.checkin_timeout_seconds,
+                        );
+                        let timeout = min(checkin_timeout, wait_unti_next_update);
                         // Check in with Operatortools
                         match operator_update(ops_last_seen_usage_hour, timeout).await {
                             Ok(last) => {
@@ -14,14 +14,15 @@ use crate::InterfaceMode;
 use actix_async::System as AsyncSystem;
 use althea_kernel_interface::KernelInterfaceError;
 use althea_kernel_interface::KI;
-use althea_types::ExitState;
 use antenna_forwarding_client::start_antenna_forwarding_proxy;
+use rita_common::rita_loop::is_gateway;
 use rita_common::rita_loop::set_gateway;
+use rita_common::rita_loop::slow_loop::get_babel_interface_up;
 use rita_common::tunnel_manager::tm_get_neighbors;
 use rita_common::usage_tracker::get_current_hour;
 use rita_common::usage_tracker::get_last_saved_usage_hour;
 use settings::client::RitaClientSettings;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::File;
 use std::fs::OpenOptions;
@@ -45,6 +46,52 @@ lazy_static! {
     /// to identify why this variable is needed. In short it identifies
     /// a specific billing corner case.
     static ref IS_GATEWAY_CLIENT: Arc<RwLock<HashMap<u32, bool>>> = Arc::new(RwLock::new(HashMap::new()));
+    /// The resolv servers we last set up dns routes for, see manage_gateway
+    static ref LAST_RESOLV_ROUTES: Arc<RwLock<Option<AppliedResolvRoutes>>> = Arc::new(RwLock::new(None));
+}
+
+/// Even if the resolv servers don't change we re-apply their routes this often, in case the
+/// default route they go through has changed
+const RESOLV_ROUTE_REFRESH: Duration = Duration::from_secs(300);
+
+/// The set of resolv servers dns routes were last successfully set up for
+#[derive(Debug, Clone)]
+struct AppliedResolvRoutes {
+    servers: HashSet<IpAddr>,
+    applied: Instant,
+}
+
+/// The dns routes to add and remove this tick
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ResolvRouteChanges {
+    add: HashSet<IpAddr>,
+    remove: HashSet<IpAddr>,
+}
+
+/// Works out which dns routes need to change, only servers that have appeared since the routes
+/// were last applied are added and only servers that have gone away are removed. Once
+/// RESOLV_ROUTE_REFRESH has passed every server is added again
+fn resolv_route_changes(
+    last: &Option<AppliedResolvRoutes>,
+    servers: &HashSet<IpAddr>,
+    now: Instant,
+) -> ResolvRouteChanges {
+    match last {
+        Some(last) if now.saturating_duration_since(last.applied) < RESOLV_ROUTE_REFRESH => {
+            ResolvRouteChanges {
+                add: servers.difference(&last.servers).cloned().collect(),
+                remove: last.servers.difference(servers).cloned().collect(),
+            }
+        }
+        Some(last) => ResolvRouteChanges {
+            add: servers.clone(),
+            remove: last.servers.difference(servers).cloned().collect(),
+        },
+        None => ResolvRouteChanges {
+            add: servers.clone(),
+            remove: HashSet::new(),
+        },
+    }
 }
 
 pub fn is_gateway_client() -> bool {
@@ -179,7 +226,7 @@ fn check_for_gateway_client_billing_corner_case() {
 
     let neighbors = res;
     if let Some(exit) = exit_server {
-        if let ExitState::Registered { .. } = exit.info {
+        if exit.info.is_registered() {
             for neigh in neighbors {
                 info!("Neighbor is {:?}", neigh);
                 // we have a neighbor who is also our selected exit!
@@ -234,11 +281,22 @@ fn manage_gateway() {
     // the is_up detection is mostly useless because these ports reside on switches which mark
     // all ports as up all the time.
     if let Some(external_nic) = settings::get_rita_common().network.external_nic {
-        if KI.is_iface_up(&external_nic).unwrap_or(false) {
+        let kernel_up = KI.is_iface_up(&external_nic).unwrap_or(false);
+        check_babel_iface_agreement(
+            &external_nic,
+            kernel_up,
+            get_babel_interface_up(&external_nic),
+        );
+        // routes applied before we became a gateway may not have survived, so the
+        // resolv routes are set up from scratch exactly once on becoming one
+        if should_reset_resolv_routes(kernel_up, is_gateway()) {
+            *LAST_RESOLV_ROUTES.write().unwrap() = None;
+        }
+        // this flag is used to handle billing around the corner case
+        set_gateway(kernel_up);
+        if kernel_up {
             if let Ok(interfaces) = get_interfaces() {
                 info!("We are a Gateway");
-                // this flag is used to handle billing around the corner case
-                set_gateway(true);
 
                 // This is used to insert a route for each dns server in /etc/resolv.conf to override
                 // the wg_exit default route, this is needed for bootstrapping as a gateway can not
@@ -247,19 +305,16 @@ fn manage_gateway() {
                 // this check
                 if let Some(mode) = interfaces.get(&external_nic) {
                     if matches!(mode, InterfaceMode::Wan | InterfaceMode::StaticWan { .. }) {
-                        let mut common = settings::get_rita_common();
                         match KI.get_resolv_servers() {
                             Ok(s) => {
-                                for ip in s.iter() {
-                                    trace!("Resolv route {:?}", ip);
-
-                                    KI.manual_peers_route(
-                                        ip,
-                                        &mut common.network.last_default_route,
-                                    )
-                                    .unwrap();
+                                let servers: HashSet<IpAddr> = s.into_iter().collect();
+                                let mut last = LAST_RESOLV_ROUTES.write().unwrap();
+                                let now = Instant::now();
+                                let changes = resolv_route_changes(&last, &servers, now);
+                                if changes != ResolvRouteChanges::default() {
+                                    *last =
+                                        Some(apply_resolv_route_changes(last.take(), changes, now));
                                 }
-                                settings::set_rita_common(common);
                             }
                             Err(e) => warn!("Failed to add DNS routes with {:?}", e),
                         }
@@ -267,6 +322,78 @@ fn manage_gateway() {
                 }
             }
         }
+    } else {
+        set_gateway(false);
+    }
+}
+
+/// Returns true on the transition into being a gateway, staying a gateway or staying a client
+/// must not reset anything
+fn should_reset_resolv_routes(is_gateway: bool, was_gateway: bool) -> bool {
+    is_gateway && !was_gateway
+}
+
+/// Adds and removes dns routes and returns the routes now in place. A server whose route fails to
+/// be added is left out, and one whose route fails to be removed is kept, so that either is retried
+/// next tick
+fn apply_resolv_route_changes(
+    last: Option<AppliedResolvRoutes>,
+    changes: ResolvRouteChanges,
+    now: Instant,
+) -> AppliedResolvRoutes {
+    let refreshed = match &last {
+        Some(last) => now.saturating_duration_since(last.applied) >= RESOLV_ROUTE_REFRESH,
+        None => true,
+    };
+    let mut applied = last.unwrap_or(AppliedResolvRoutes {
+        servers: HashSet::new(),
+        applied: now,
+    });
+    if refreshed {
+        applied.applied = now;
+    }
+
+    let mut common = settings::get_rita_common();
+    for ip in changes.add {
+        trace!("Resolv route {:?}", ip);
+        match KI.manual_peers_route(&ip, &mut common.network.last_default_route) {
+            Ok(_) => {
+                applied.servers.insert(ip);
+            }
+            Err(e) => {
+                error!("Failed to add DNS route for {} with {:?}", ip, e);
+                applied.servers.remove(&ip);
+            }
+        }
+    }
+    settings::set_rita_common(common);
+
+    for ip in changes.remove {
+        match KI.remove_manual_peers_route(&ip) {
+            Ok(()) => {
+                applied.servers.remove(&ip);
+            }
+            Err(e) => error!("Failed to remove DNS route for {} with {:?}", ip, e),
+        }
+    }
+    applied
+}
+
+/// Cross checks the kernel's view of an interface's up state against babel's, these can disagree and
+/// when they do gateway detection may flap, so we log it. Returns false if the two disagree, if babel
+/// does not know about the interface there is nothing to compare and true is returned
+fn check_babel_iface_agreement(iface: &str, kernel_up: bool, babel_up: Option<bool>) -> bool {
+    match babel_up {
+        Some(babel_up) if babel_up != kernel_up => {
+            warn!(
+                "Interface {} is {} according to the kernel but {} according to babel",
+                iface,
+                if kernel_up { "up" } else { "down" },
+                if babel_up { "up" } else { "down" }
+            );
+            false
+        }
+        _ => true,
     }
 }
 
@@ -476,3 +603,67 @@ fn maybe_parse_ip(
         Err(e) => Err(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::check_babel_iface_agreement;
+    use super::resolv_route_changes;
+    use super::should_reset_resolv_routes;
+    use super::AppliedResolvRoutes;
+    use super::ResolvRouteChanges;
+    use super::RESOLV_ROUTE_REFRESH;
+    use std::collections::HashSet;
+    use std::net::IpAddr;
+    use std::time::Instant;
+
+    #[test]
+    fn test_unchanged_resolv_servers_not_reapplied() {
+        let a: IpAddr = "1.1.1.1".parse().unwrap();
+        let b: IpAddr = "8.8.8.8".parse().unwrap();
+        let c: IpAddr = "9.9.9.9".parse().unwrap();
+        let servers: HashSet<IpAddr> = [a, b].into_iter().collect();
+        let now = Instant::now();
+        // nothing applied yet
+        let changes = resolv_route_changes(&None, &servers, now);
+        assert_eq!(changes.add, servers);
+        assert!(changes.remove.is_empty());
+
+        let last = Some(AppliedResolvRoutes {
+            servers: servers.clone(),
+            applied: now,
+        });
+        assert_eq!(
+            resolv_route_changes(&last, &servers, now),
+            ResolvRouteChanges::default()
+        );
+
+        // only the new server is added and only the vanished one removed
+        let changed: HashSet<IpAddr> = [a, c].into_iter().collect();
+        let changes = resolv_route_changes(&last, &changed, now);
+        assert_eq!(changes.add, [c].into_iter().collect());
+        assert_eq!(changes.remove, [b].into_iter().collect());
+
+        // periodically refreshed even when unchanged
+        let changes = resolv_route_changes(&last, &servers, now + RESOLV_ROUTE_REFRESH);
+        assert_eq!(changes.add, servers);
+        assert!(changes.remove.is_empty());
+    }
+
+    #[test]
+    fn test_resolv_routes_reset_on_becoming_gateway() {
+        assert!(should_reset_resolv_routes(true, false));
+        assert!(!should_reset_resolv_routes(true, true));
+        assert!(!should_reset_resolv_routes(false, true));
+        assert!(!should_reset_resolv_routes(false, false));
+    }
+
+    #[test]
+    fn test_babel_kernel_iface_disagreement() {
+        // babel reports the wan down while the kernel reports it up
+        assert!(!check_babel_iface_agreement("eth0", true, Some(false)));
+        assert!(!check_babel_iface_agreement("eth0", false, Some(true)));
+        assert!(check_babel_iface_agreement("eth0", true, Some(true)));
+        // babel is not running on this interface
+        assert!(check_babel_iface_agreement("eth0", true, None));
+    }
+}
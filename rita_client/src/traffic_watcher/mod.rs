@@ -27,6 +27,7 @@ use althea_types::Identity;
 use babel_monitor::parsing::get_installed_route;
 use babel_monitor::structs::BabelMonitorError;
 use babel_monitor::structs::Route;
+use babel_monitor::sum_path_price;
 use num256::Int256;
 use num_traits::identities::Zero;
 use rita_common::debt_keeper::{gateway_traffic_update, traffic_replace, traffic_update, Traffic};
@@ -277,11 +278,9 @@ pub fn local_traffic_calculation(
     // the price we pay to send traffic through the exit
     info!("exit price {}", exit_price);
 
-    // price to get traffic to the exit as a u64 to make the type rules for math easy
-    let exit_route_price: i128 = exit_route.price.into();
     // the total price for the exit returning traffic to us, in the future we should ask
     // the exit for this because TODO assumes symetric route
-    let exit_dest_price: i128 = exit_route_price + i128::from(exit_price);
+    let exit_dest_price: i128 = sum_path_price([exit_route.price.into(), exit_price]).into();
 
     // send the exit dest price over to the light client manager for consumption there
     history.last_exit_dest_price = exit_dest_price as u128;
@@ -7,16 +7,26 @@ use crate::debt_keeper::normalize_payment_amount;
 use crate::rita_loop::fast_loop::FAST_LOOP_TIMEOUT;
 use crate::rita_loop::get_altheal1_server;
 use crate::rita_loop::get_web3_server;
+use crate::rita_loop::get_web3_server_excluding;
+use crate::rita_loop::node_blacklist::blacklist_node;
+use crate::rita_loop::node_blacklist::release_net_version_quarantine;
+use crate::rita_loop::node_blacklist::BlacklistReason;
+use crate::RitaCommonError;
 use althea_types::Denom;
 use althea_types::SystemChain;
 use althea_types::ALTHEA_PREFIX;
+use auto_bridge::XDAI_RELAY_TOKENS_GAS;
 use clarity::Address;
 use deep_space::Address as CosmosAddress;
 use deep_space::Contact;
+use futures::future::join;
+use futures::future::join_all;
 use num256::Int256;
 use num256::Uint256;
+use settings::payment::PaymentSettings;
 use settings::DEBT_KEEPER_DENOM;
 use settings::DEBT_KEEPER_DENOM_DECIMAL;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::time::Duration;
@@ -36,6 +46,14 @@ const CLOSE_THRESH_MULT: i32 = 10;
 /// in the rita_common fast loop
 pub const ORACLE_TIMEOUT: Duration = FAST_LOOP_TIMEOUT;
 
+/// How many different full nodes we will try in a single update before giving up until the
+/// next tick, each attempt may take up to ORACLE_TIMEOUT
+pub const ORACLE_MAX_ATTEMPTS: usize = 3;
+
+/// How many net_version checks in a row a full node may disagree with the quorum before it is
+/// quarantined, a single bad answer may just be a node that is restarting or resyncing
+pub const NET_VERSION_MISMATCH_LIMIT: u32 = 3;
+
 lazy_static! {
     /// This lazy static hold info about gas, thresholds and payment info for the router
     static ref ORACLE: Arc<RwLock<BlockchainOracle>> =
@@ -49,6 +67,53 @@ pub struct BlockchainOracle {
     /// ignore the update, none if not yet set
     pub last_seen_block: Option<Uint256>,
     pub last_updated: Option<Instant>,
+    /// The network id agreed on by a quorum of full nodes in the last net_version check,
+    /// none if no check has reached quorum yet
+    pub net_version: Option<u64>,
+    /// When each field was last refreshed, see due_oracle_fields
+    pub last_refreshed: HashMap<OracleField, Instant>,
+    /// Full node requests that have failed in a row, reset by any successful update
+    pub failed_attempts: u32,
+    /// How many net_version checks in a row each full node has disagreed with the quorum
+    pub net_version_mismatches: HashMap<String, u32>,
+}
+
+/// The values the oracle keeps up to date, each is refreshed on its own cadence set in
+/// the payment settings since they change at very different rates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OracleField {
+    Block,
+    Balance,
+    NetVersion,
+}
+
+impl OracleField {
+    fn interval(&self, payment: &PaymentSettings) -> Duration {
+        Duration::from_secs(match self {
+            OracleField::Block => payment.oracle_block_interval_seconds,
+            OracleField::Balance => payment.oracle_balance_interval_seconds,
+            OracleField::NetVersion => payment.oracle_net_version_interval_seconds,
+        })
+    }
+}
+
+/// Returns the fields whose configured interval has passed since they were last refreshed
+fn due_oracle_fields(
+    last_refreshed: &HashMap<OracleField, Instant>,
+    payment: &PaymentSettings,
+    now: Instant,
+) -> Vec<OracleField> {
+    [
+        OracleField::Block,
+        OracleField::Balance,
+        OracleField::NetVersion,
+    ]
+    .into_iter()
+    .filter(|field| match last_refreshed.get(field) {
+        Some(last) => now.saturating_duration_since(*last) >= field.interval(payment),
+        None => true,
+    })
+    .collect()
 }
 
 /// payment_threshold : This is the amount at which a router will make a payment. Below this value, the router will not may a payment since
@@ -79,6 +144,10 @@ impl BlockchainOracle {
             balance: None,
             last_seen_block: None,
             last_updated: None,
+            net_version: None,
+            last_refreshed: HashMap::new(),
+            failed_attempts: 0,
+            net_version_mismatches: HashMap::new(),
         }
     }
 }
@@ -112,8 +181,52 @@ pub fn set_oracle_last_updated(update: Instant) {
     ORACLE.write().unwrap().last_updated = Some(update)
 }
 
+pub fn get_oracle_net_version() -> Option<u64> {
+    ORACLE.read().unwrap().net_version
+}
+
+pub fn get_oracle_failed_attempts() -> u32 {
+    ORACLE.read().unwrap().failed_attempts
+}
+
+/// Records that the given fields were refreshed at the given time, see due_oracle_fields
+fn mark_oracle_refreshed(fields: &[OracleField], refreshed: Instant) {
+    let mut oracle = ORACLE.write().unwrap();
+    for field in fields {
+        oracle.last_refreshed.insert(*field, refreshed);
+    }
+}
+
+fn record_oracle_attempt(success: bool) {
+    let mut oracle = ORACLE.write().unwrap();
+    if success {
+        oracle.failed_attempts = 0;
+    } else {
+        oracle.failed_attempts = oracle.failed_attempts.saturating_add(1);
+    }
+}
+
+/// Refreshes whichever oracle fields are due, this is called every fast loop tick but each field
+/// is only fetched as often as its interval in the payment settings allows
 pub async fn update() {
     let payment_settings = settings::get_rita_common().payment;
+    let now = Instant::now();
+    let due = due_oracle_fields(
+        &ORACLE.read().unwrap().last_refreshed,
+        &payment_settings,
+        now,
+    );
+    let refresh_balance = due.contains(&OracleField::Balance);
+    // the block check guards the balance against stale full nodes so it runs with every balance update
+    let refresh_block = refresh_balance || due.contains(&OracleField::Block);
+    let refresh_net_version = due.contains(&OracleField::NetVersion);
+    // fields are only stamped once they have actually been fetched, so that a failed fetch is
+    // retried on the next tick rather than after a full interval
+    let mut block_fields = vec![OracleField::Block];
+    if refresh_balance {
+        block_fields.push(OracleField::Balance);
+    }
+
     let our_address = payment_settings.eth_address.expect("No address!");
     let our_althea_address = settings::get_rita_common()
         .get_identity()
@@ -127,16 +240,61 @@ pub async fn update() {
 
     match payment_settings.system_chain {
         SystemChain::Ethereum | SystemChain::Sepolia | SystemChain::Xdai => {
-            let full_node = get_web3_server();
-            info!("About to make web3 requests to {}", full_node);
-            let web3 = Web3::new(&full_node, ORACLE_TIMEOUT);
-            update_blockchain_info_gnosis(our_address, web3, full_node).await;
+            if refresh_block {
+                // a flaky node would otherwise cost us the whole tick, so on failure we retry
+                // right away against a node we have not tried yet
+                let mut tried = Vec::new();
+                while tried.len() < ORACLE_MAX_ATTEMPTS {
+                    let full_node = match get_web3_server_excluding(&tried) {
+                        Ok(node) => node,
+                        Err(e) => {
+                            warn!("Skipping oracle update with {}", e);
+                            break;
+                        }
+                    };
+                    info!("About to make web3 requests to {}", full_node);
+                    let web3 = Web3::new(&full_node, ORACLE_TIMEOUT);
+                    let success = update_blockchain_info_gnosis(
+                        our_address,
+                        web3,
+                        full_node.clone(),
+                        refresh_balance,
+                    )
+                    .await;
+                    record_oracle_attempt(success);
+                    if success {
+                        mark_oracle_refreshed(&block_fields, now);
+                        break;
+                    }
+                    tried.push(full_node);
+                }
+            }
+            if refresh_net_version && revalidate_net_version().await.quorum.is_some() {
+                mark_oracle_refreshed(&[OracleField::NetVersion], now);
+            }
         }
         SystemChain::AltheaL1 => {
-            let full_node = get_altheal1_server();
-            let contact = Contact::new(&full_node, ORACLE_TIMEOUT, ALTHEA_PREFIX).unwrap();
-            update_blockchain_info_althea(our_althea_address, contact, althea_denom, full_node)
-                .await;
+            if refresh_block {
+                let full_node = match get_altheal1_server() {
+                    Ok(node) => node,
+                    Err(e) => {
+                        warn!("Skipping oracle update with {}", e);
+                        return;
+                    }
+                };
+                let contact = Contact::new(&full_node, ORACLE_TIMEOUT, ALTHEA_PREFIX).unwrap();
+                if update_blockchain_info_althea(
+                    our_althea_address,
+                    contact,
+                    althea_denom,
+                    full_node,
+                    refresh_balance,
+                )
+                .await
+                {
+                    mark_oracle_refreshed(&block_fields, now);
+                }
+            }
         }
     }
 }
@@ -164,12 +322,14 @@ pub fn potential_payment_issues_detected() -> bool {
     false
 }
 
+/// Returns true if every requested value was updated from this full node
 async fn update_blockchain_info_althea(
     our_address: CosmosAddress,
     contact: Contact,
     denom: Denom,
     full_node: String,
-) {
+    refresh_balance: bool,
+) -> bool {
     let latest_block = contact.get_chain_status().await;
     match latest_block {
         Ok(deep_space::client::ChainStatus::Moving { block_height }) => {
@@ -180,7 +340,7 @@ async fn update_blockchain_info_althea(
                         "Got stale blockchain oracle data! {} < {}",
                         latest_block, last_seen_block
                     );
-                    return;
+                    return false;
                 }
             }
             set_oracle_last_seen_block(latest_block);
@@ -188,37 +348,63 @@ async fn update_blockchain_info_althea(
         }
         Ok(_) => {
             warn!("Failed to get latest block number and balance for Althea L1");
-            return;
+            return false;
         }
         Err(e) => {
             warn!("Failed to get latest block number with {:?}", e);
-            return;
+            return false;
         }
     }
+    if !refresh_balance {
+        return true;
+    }
 
     let balance = contact.get_balance(our_address, denom.denom.clone()).await;
     match balance {
-        Ok(Some(balance)) => update_balance(
-            &full_node,
-            normalize_payment_amount(
-                balance.amount,
-                denom,
-                Denom {
-                    denom: DEBT_KEEPER_DENOM.to_string(),
-                    decimal: DEBT_KEEPER_DENOM_DECIMAL,
-                },
-            ),
-        ),
-        Ok(None) => update_balance(&full_node, 0u32.into()),
-        Err(e) => warn!("Failed to update balance with {:?}", e),
+        Ok(Some(balance)) => {
+            update_balance(
+                &full_node,
+                normalize_payment_amount(
+                    balance.amount,
+                    denom,
+                    Denom {
+                        denom: DEBT_KEEPER_DENOM.to_string(),
+                        decimal: DEBT_KEEPER_DENOM_DECIMAL,
+                    },
+                ),
+            );
+            true
+        }
+        Ok(None) => {
+            update_balance(&full_node, 0u32.into());
+            true
+        }
+        Err(e) => {
+            warn!("Failed to update balance with {:?}", e);
+            false
+        }
     }
 }
 
-async fn update_blockchain_info_gnosis(our_address: Address, web3: Web3, full_node: String) {
+/// Returns true if every requested value was updated from this full node
+async fn update_blockchain_info_gnosis(
+    our_address: Address,
+    web3: Web3,
+    full_node: String,
+    refresh_balance: bool,
+) -> bool {
     // all web30 functions check if the node is syncing, but sometimes the nodes lie about
     // syncing, this block checks the actual block number we've last seen and if we get a lower
-    // value returns early, refusing to update our state with stale data.
-    let latest_block = web3.eth_block_number().await;
+    // value returns early, refusing to update our state with stale data. When the balance is due
+    // both requests are made to the node at once so that they come from the same node in a single
+    // round trip, the balance is discarded if the block check fails
+    let (latest_block, balance) = if refresh_balance {
+        let (latest_block, balance) =
+            join(web3.eth_block_number(), web3.eth_get_balance(our_address)).await;
+        (latest_block, Some(balance))
+    } else {
+        (web3.eth_block_number().await, None)
+    };
     match latest_block {
         Ok(latest_block) => {
             if let Some(last_seen_block) = get_oracle_last_seen_block() {
@@ -227,7 +413,8 @@ async fn update_blockchain_info_gnosis(our_address: Address, web3: Web3, full_no
                         "Got stale blockchain oracle data! {} < {}",
                         latest_block, last_seen_block
                     );
-                    return;
+                    blacklist_node(&full_node, BlacklistReason::StaleBlock);
+                    return false;
                 }
             }
             set_oracle_last_seen_block(latest_block);
@@ -235,14 +422,37 @@ async fn update_blockchain_info_gnosis(our_address: Address, web3: Web3, full_no
         }
         Err(e) => {
             warn!("Failed to get latest block number with {:?}", e);
-            return;
+            blacklist_node(&full_node, BlacklistReason::Unresponsive);
+            return false;
         }
     }
-
-    let balance = web3.eth_get_balance(our_address).await;
     match balance {
-        Ok(balance) => update_balance(&full_node, balance),
-        Err(e) => warn!("Failed to update balance with {:?}", e),
+        None => true,
+        Some(Ok(balance)) => {
+            update_balance(&full_node, balance);
+            true
+        }
+        Some(Err(e)) => {
+            warn!("Failed to update balance with {:?}", e);
+            false
+        }
+    }
+}
+
+/// Fetches the balance of an address straight from a full node, for callers that need a fresh
+/// reading rather than the balance from the last oracle update. The oracle state is not touched.
+/// Only meaningful on evm chains, Althea L1 balances are not available over web3
+pub async fn fetch_balance_now(address: Address) -> Result<Uint256, RitaCommonError> {
+    let full_node = get_web3_server()?;
+    let web3 = Web3::new(&full_node, ORACLE_TIMEOUT);
+    match web3.eth_get_balance(address).await {
+        Ok(balance) => Ok(balance),
+        Err(e) => {
+            warn!("Failed to fetch balance from {} with {:?}", full_node, e);
+            Err(RitaCommonError::MiscStringError(format!(
+                "Failed to fetch balance with {e}"
+            )))
+        }
     }
 }
 
@@ -271,10 +481,327 @@ pub fn low_balance() -> bool {
         None => false,
     }
 }
+
+/// Gas used by a plain value transfer with no payload on any evm chain
+pub const TRANSFER_GAS: u32 = 21000;
+
+/// The kinds of transactions rita builds, each with a different gas cost
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionType {
+    /// A plain value transfer, used for bandwidth, operator and txfee payments as well as withdraws
+    Transfer,
+    /// A relayTokens contract call on the xdai bridge, moving funds from xdai to ethereum
+    XdaiBridgeWithdraw,
+}
+
+/// Returns the gas limit to use for a transaction of the given type on the given chain
+/// this is also used to estimate the cost of a transaction before it is sent
+pub fn get_gas_limit(chain: SystemChain, tx_type: TransactionType) -> Uint256 {
+    match (chain, tx_type) {
+        (_, TransactionType::Transfer) => TRANSFER_GAS.into(),
+        (_, TransactionType::XdaiBridgeWithdraw) => XDAI_RELAY_TOKENS_GAS.into(),
+    }
+}
+
+/// The result of asking every full node in the node list for its net_version
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct NetVersionSummary {
+    /// The net_version reported by a strict majority of the nodes, if any
+    pub quorum: Option<u64>,
+    /// The quorum value before this check
+    pub previous: Option<u64>,
+    /// Nodes that responded with something other than the quorum value
+    pub disagreeing: Vec<(String, u64)>,
+    /// Nodes that did not respond
+    pub unreachable: Vec<String>,
+}
+
+/// Finds the net_version reported by a strict majority of the node list and any nodes that
+/// disagree with it. Unreachable nodes count toward the size of the node list but never toward
+/// quorum, so a mostly offline node list can not commit a value
+fn summarize_net_versions(
+    responses: Vec<(String, Option<u64>)>,
+    previous: Option<u64>,
+) -> NetVersionSummary {
+    let mut counts: Vec<(u64, usize)> = Vec::new();
+    for (_, version) in responses.iter() {
+        if let Some(version) = version {
+            match counts.iter_mut().find(|(v, _)| v == version) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((*version, 1)),
+            }
+        }
+    }
+    let quorum = counts
+        .iter()
+        .find(|(_, count)| *count * 2 > responses.len())
+        .map(|(version, _)| *version);
+
+    let mut disagreeing = Vec::new();
+    let mut unreachable = Vec::new();
+    for (node, version) in responses {
+        match version {
+            Some(version) if Some(version) != quorum => disagreeing.push((node, version)),
+            Some(_) => {}
+            None => unreachable.push(node),
+        }
+    }
+    NetVersionSummary {
+        quorum,
+        previous,
+        disagreeing,
+        unreachable,
+    }
+}
+
+/// Updates the per node mismatch counts from a net_version check. Returns the nodes that have now
+/// disagreed with the quorum NET_VERSION_MISMATCH_LIMIT times in a row, followed by the nodes that
+/// agreed with it and so may leave quarantine. An unreachable node is left as is since it told us nothing
+fn count_net_version_mismatches(
+    mismatches: &mut HashMap<String, u32>,
+    summary: &NetVersionSummary,
+    node_list: &[String],
+) -> (Vec<String>, Vec<String>) {
+    // without a quorum there is nothing to disagree with
+    if summary.quorum.is_none() {
+        return (Vec::new(), Vec::new());
+    }
+    let mut quarantined = Vec::new();
+    let mut passed = Vec::new();
+    for node in node_list {
+        if summary.unreachable.contains(node) {
+            continue;
+        }
+        if summary.disagreeing.iter().any(|(n, _)| n == node) {
+            let count = mismatches.entry(node.clone()).or_insert(0);
+            *count += 1;
+            if *count >= NET_VERSION_MISMATCH_LIMIT {
+                quarantined.push(node.clone());
+            }
+        } else {
+            mismatches.remove(node);
+            passed.push(node.clone());
+        }
+    }
+    (quarantined, passed)
+}
+
+/// Checks a net_version reported by full nodes against the chain id of the configured SystemChain,
+/// a whole node list agreeing on the wrong network would otherwise pass the quorum check
+fn net_version_matches_chain(net_version: u64, system_chain: SystemChain) -> bool {
+    net_version == system_chain.chain_id()
+}
+
+/// Queries every node in the eth node list for its net_version and commits the value a quorum
+/// agrees on. This runs on the oracle_net_version_interval_seconds and can also be run by hand after changing
+/// the node list or chain, rather than waiting for the next scheduled check
+pub async fn revalidate_net_version() -> NetVersionSummary {
+    let node_list = settings::get_rita_common().payment.eth_node_list;
+    let queries = node_list.iter().map(|node| async move {
+        let web3 = Web3::new(node, ORACLE_TIMEOUT);
+        match web3.net_version().await {
+            Ok(version) => (node.clone(), Some(version)),
+            Err(e) => {
+                warn!("Failed to get net_version from {} with {:?}", node, e);
+                (node.clone(), None)
+            }
+        }
+    });
+    let responses = join_all(queries).await;
+
+    let summary = summarize_net_versions(responses, get_oracle_net_version());
+    for (node, version) in summary.disagreeing.iter() {
+        error!(
+            "Full node {} reports net_version {} which disagrees with quorum {:?}",
+            node, version, summary.quorum
+        );
+    }
+    let (quarantined, passed) = count_net_version_mismatches(
+        &mut ORACLE.write().unwrap().net_version_mismatches,
+        &summary,
+        &node_list,
+    );
+    for node in quarantined {
+        blacklist_node(&node, BlacklistReason::NetVersionMismatch);
+    }
+    for node in passed {
+        release_net_version_quarantine(&node);
+    }
+    let system_chain = settings::get_rita_common().payment.system_chain;
+    if let Some(quorum) = summary.quorum {
+        if !net_version_matches_chain(quorum, system_chain) {
+            error!(
+                "Full nodes agree on net_version {} but {} has chain id {}, check the node list!",
+                quorum,
+                system_chain,
+                system_chain.chain_id()
+            );
+        }
+    }
+    match summary.quorum {
+        Some(quorum) if summary.previous != Some(quorum) => {
+            info!(
+                "Full node net_version quorum changed from {:?} to {}",
+                summary.previous, quorum
+            );
+            ORACLE.write().unwrap().net_version = Some(quorum);
+        }
+        Some(_) => {}
+        None => error!("Full nodes failed to reach quorum on net_version!"),
+    }
+    summary
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_net_version_matches_chain() {
+        assert!(net_version_matches_chain(100, SystemChain::Xdai));
+        assert!(net_version_matches_chain(1, SystemChain::Ethereum));
+        assert!(!net_version_matches_chain(1, SystemChain::Xdai));
+        assert!(!net_version_matches_chain(100, SystemChain::Sepolia));
+    }
+
+    #[test]
+    fn test_net_version_disagreement_reported() {
+        let responses = vec![
+            ("https://a.example.com".to_string(), Some(100)),
+            ("https://b.example.com".to_string(), Some(100)),
+            ("https://c.example.com".to_string(), Some(1)),
+        ];
+        let summary = summarize_net_versions(responses, None);
+        assert_eq!(summary.quorum, Some(100));
+        assert_eq!(
+            summary.disagreeing,
+            vec![("https://c.example.com".to_string(), 1)]
+        );
+        assert!(summary.unreachable.is_empty());
+
+        // unreachable nodes prevent a minority from committing
+        let responses = vec![
+            ("https://a.example.com".to_string(), Some(100)),
+            ("https://b.example.com".to_string(), None),
+            ("https://c.example.com".to_string(), None),
+        ];
+        let summary = summarize_net_versions(responses, Some(100));
+        assert_eq!(summary.quorum, None);
+        assert_eq!(summary.unreachable.len(), 2);
+    }
+
+    #[test]
+    fn test_net_version_mismatch_quarantine() {
+        let good: Vec<String> = (0..3)
+            .map(|i| format!("https://good{i}.example.com"))
+            .collect();
+        let bad = "https://bad.example.com".to_string();
+        let flaky = "https://flaky.example.com".to_string();
+        let mut node_list = good.clone();
+        node_list.push(bad.clone());
+        node_list.push(flaky.clone());
+        let mut mismatches = HashMap::new();
+        let check = |bad_version: Option<u64>, flaky_version: Option<u64>| {
+            let mut responses: Vec<(String, Option<u64>)> =
+                good.iter().map(|node| (node.clone(), Some(100))).collect();
+            responses.push((bad.clone(), bad_version));
+            responses.push((flaky.clone(), flaky_version));
+            summarize_net_versions(responses, Some(100))
+        };
+
+        // the flaky node disagrees once, then is unreachable, then agrees again and is never
+        // quarantined, the bad node is quarantined on its third disagreement in a row
+        let (quarantined, passed) =
+            count_net_version_mismatches(&mut mismatches, &check(Some(1), Some(1)), &node_list);
+        assert!(quarantined.is_empty());
+        assert_eq!(passed, good);
+        let (quarantined, _) =
+            count_net_version_mismatches(&mut mismatches, &check(Some(1), None), &node_list);
+        assert!(quarantined.is_empty());
+        let (quarantined, passed) =
+            count_net_version_mismatches(&mut mismatches, &check(Some(1), Some(100)), &node_list);
+        assert_eq!(quarantined, vec![bad.clone()]);
+        assert!(passed.contains(&flaky));
+        assert!(!mismatches.contains_key(&flaky));
+
+        // the bad node stays quarantined until it answers with the quorum value
+        let (quarantined, passed) =
+            count_net_version_mismatches(&mut mismatches, &check(None, Some(100)), &node_list);
+        assert!(quarantined.is_empty());
+        assert!(!passed.contains(&bad));
+        let (quarantined, passed) =
+            count_net_version_mismatches(&mut mismatches, &check(Some(100), Some(100)), &node_list);
+        assert!(quarantined.is_empty());
+        assert!(passed.contains(&bad));
+        assert!(mismatches.is_empty());
+
+        // without a quorum nobody is counted
+        let no_quorum = summarize_net_versions(
+            vec![(good[0].clone(), Some(100)), (bad, Some(1)), (flaky, None)],
+            Some(100),
+        );
+        let (quarantined, passed) =
+            count_net_version_mismatches(&mut mismatches, &no_quorum, &node_list);
+        assert!(quarantined.is_empty());
+        assert!(passed.is_empty());
+        assert!(mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_oracle_field_cadence() {
+        let mut payment = PaymentSettings::default();
+        payment.oracle_block_interval_seconds = 10;
+        payment.oracle_balance_interval_seconds = 30;
+        payment.oracle_net_version_interval_seconds = 3600;
+        let start = Instant::now();
+
+        // everything is fetched the first time around
+        let mut last_refreshed = HashMap::new();
+        let due = due_oracle_fields(&last_refreshed, &payment, start);
+        assert_eq!(due.len(), 3);
+        for field in due {
+            last_refreshed.insert(field, start);
+        }
+
+        let now = start + Duration::from_secs(5);
+        assert!(due_oracle_fields(&last_refreshed, &payment, now).is_empty());
+
+        let now = start + Duration::from_secs(10);
+        assert_eq!(
+            due_oracle_fields(&last_refreshed, &payment, now),
+            vec![OracleField::Block]
+        );
+        last_refreshed.insert(OracleField::Block, now);
+
+        let now = start + Duration::from_secs(30);
+        assert_eq!(
+            due_oracle_fields(&last_refreshed, &payment, now),
+            vec![OracleField::Block, OracleField::Balance]
+        );
+
+        let now = start + Duration::from_secs(3600);
+        assert_eq!(
+            due_oracle_fields(&last_refreshed, &payment, now),
+            vec![
+                OracleField::Block,
+                OracleField::Balance,
+                OracleField::NetVersion
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gas_limit() {
+        let transfer = get_gas_limit(SystemChain::Xdai, TransactionType::Transfer);
+        assert_eq!(transfer, 21000u32.into());
+        assert_eq!(
+            get_gas_limit(SystemChain::Ethereum, TransactionType::Transfer),
+            transfer
+        );
+        let bridge = get_gas_limit(SystemChain::Xdai, TransactionType::XdaiBridgeWithdraw);
+        assert!(bridge > transfer);
+    }
+
     #[test]
     fn test_update_blockchain_info() {
         let runner = actix_async::System::new();
@@ -295,6 +822,7 @@ mod tests {
                     decimal: 18,
                 },
                 "https://rpc.althea.zone:9090".to_string(),
+                true,
             )
             .await;
         });
@@ -5,7 +5,9 @@
 pub mod babel;
 pub mod debts;
 pub mod development;
+pub mod net_version;
 pub mod nickname;
+pub mod node_blacklist;
 pub mod own_info;
 pub mod settings;
 pub mod token_bridge;
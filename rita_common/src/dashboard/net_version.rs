@@ -0,0 +1,8 @@
+use crate::blockchain_oracle::revalidate_net_version;
+use actix_web_async::{HttpRequest, HttpResponse};
+
+/// Asks every full node for its net_version and returns a summary of which nodes agree
+pub async fn revalidate_full_node_net_version(_req: HttpRequest) -> HttpResponse {
+    debug!("/full_nodes/net_version/revalidate hit");
+    HttpResponse::Ok().json(revalidate_net_version().await)
+}
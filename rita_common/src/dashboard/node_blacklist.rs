@@ -0,0 +1,21 @@
+use crate::rita_loop::node_blacklist::clear_blacklisted_node;
+use crate::rita_loop::node_blacklist::get_blacklisted_nodes;
+use actix_web_async::{web::Json, HttpRequest, HttpResponse};
+
+pub async fn get_node_blacklist(_req: HttpRequest) -> HttpResponse {
+    debug!("/full_nodes/blacklist hit");
+    HttpResponse::Ok().json(get_blacklisted_nodes())
+}
+
+/// Puts all blacklisted full nodes back into rotation
+pub async fn clear_node_blacklist(_req: HttpRequest) -> HttpResponse {
+    debug!("/full_nodes/blacklist/clear hit");
+    HttpResponse::Ok().json(clear_blacklisted_node(None))
+}
+
+/// Puts a single blacklisted full node, specified by url in the request body, back into rotation
+pub async fn remove_from_node_blacklist(node: Json<String>) -> HttpResponse {
+    let node = node.into_inner();
+    debug!("/full_nodes/blacklist/remove hit with {}", node);
+    HttpResponse::Ok().json(clear_blacklisted_node(Some(&node)))
+}
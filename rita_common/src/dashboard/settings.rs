@@ -18,3 +18,22 @@ pub async fn set_settings(new_settings: Json<serde_json::Value>) -> HttpResponse
 
     HttpResponse::Ok().finish()
 }
+
+/// Re-reads the config file from disk and applies it without a restart, responds with the
+/// list of settings that were changed in the file but only take effect after a restart
+pub async fn reload_settings(_req: HttpRequest) -> HttpResponse {
+    debug!("Reload settings endpoint hit!");
+    match settings::reload_config() {
+        Ok(requires_restart) => {
+            if !requires_restart.is_empty() {
+                warn!(
+                    "Reloaded settings, changes to {:?} require a restart to apply",
+                    requires_restart
+                );
+            }
+            HttpResponse::Ok().json(requires_restart)
+        }
+        Err(e) => HttpResponse::build(StatusCode::BAD_REQUEST)
+            .json(format!("Unable to reload settings: {e}")),
+    }
+}
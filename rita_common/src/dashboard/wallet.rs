@@ -1,4 +1,6 @@
+use crate::blockchain_oracle::get_gas_limit;
 use crate::blockchain_oracle::get_oracle_balance;
+use crate::blockchain_oracle::TransactionType;
 use crate::rita_loop::get_web3_server;
 use crate::token_bridge::setup_withdraw as bridge_withdraw;
 use crate::token_bridge::Withdraw as WithdrawMsg;
@@ -10,6 +12,7 @@ use clarity::Address;
 use num256::Uint256;
 use std::time::Duration;
 use web30::client::Web3;
+use web30::types::SendTxOption;
 
 pub const WITHDRAW_TIMEOUT: Duration = Duration::from_secs(10);
 
@@ -19,7 +22,10 @@ async fn withdraw_handler(address: Address, amount: Option<Uint256>) -> HttpResp
     let system_chain = payment_settings.system_chain;
     let withdraw_chain = payment_settings.withdraw_chain;
     let balance = get_oracle_balance();
-    let full_node = get_web3_server();
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    };
     let web3 = Web3::new(&full_node, WITHDRAW_TIMEOUT);
     let mut gas_price = match web3.eth_gas_price().await {
         Ok(gp) => gp,
@@ -40,10 +46,10 @@ async fn withdraw_handler(address: Address, amount: Option<Uint256>) -> HttpResp
         if (system_chain, withdraw_chain) == (SystemChain::Xdai, SystemChain::Ethereum) {
             // this is the hardcoded gas price over in token bridge so we have to use it
             gas_price = 10_000_000_000u128.into();
-            // this is a contract call
-            80000u32.into()
+            // this is a contract call to relayTokens on the xdai bridge
+            get_gas_limit(system_chain, TransactionType::XdaiBridgeWithdraw)
         } else {
-            21000u32.into()
+            get_gas_limit(system_chain, TransactionType::Transfer)
         };
 
     let tx_cost = gas_price * tx_gas;
@@ -84,9 +90,13 @@ pub async fn withdraw_all(path: Path<Address>) -> HttpResponse {
 /// Withdraw for eth compatible chains, pulls from the queued withdraw
 /// and executes it
 pub async fn eth_compatible_withdraw(dest: Address, amount: Uint256) -> HttpResponse {
-    let full_node = get_web3_server();
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    };
     let web3 = Web3::new(&full_node, WITHDRAW_TIMEOUT);
     let payment_settings = settings::get_rita_common().payment;
+    let gas_limit = get_gas_limit(payment_settings.system_chain, TransactionType::Transfer);
 
     let tx = web3
         .prepare_transaction(
@@ -94,7 +104,7 @@ pub async fn eth_compatible_withdraw(dest: Address, amount: Uint256) -> HttpResp
             Vec::new(),
             amount,
             payment_settings.eth_private_key.unwrap(),
-            vec![],
+            vec![SendTxOption::GasLimit(gas_limit)],
         )
         .await;
     match tx {
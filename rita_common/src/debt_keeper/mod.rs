@@ -41,6 +41,10 @@ lazy_static! {
     static ref DEBT_DATA: Arc<RwLock<HashMap<u32,DebtKeeper>>> = Arc::new(RwLock::new(HashMap::new()));
 }
 
+/// The longest we will ever hold a payment for batching regardless of payment_batch_window_seconds, our
+/// neighbor does not know we are batching and will enforce once our debt reaches its close threshold
+pub const MAX_PAYMENT_BATCH_WINDOW: Duration = Duration::from_secs(60);
+
 /// Resets the debt keeper, this is used in tests to ensure that the debt keeper is in a known state
 #[cfg(test)]
 pub fn reset_debt_keeper() {
@@ -102,6 +106,10 @@ pub struct NodeDebtData {
     /// case, where when we get payments from the exit there is a race condition where the
     /// exit may not update that we have paid it fast enough
     pub last_successful_payment: Option<Instant>,
+    #[serde(skip_serializing, skip_deserializing)]
+    /// When our debt to this node first became payable, the payment is held until the batch
+    /// window has passed so that the debt accumulated in the meantime is paid in one transaction
+    pub payment_batch_started: Option<Instant>,
 }
 
 impl Default for NodeDebtData {
@@ -114,6 +122,7 @@ impl Default for NodeDebtData {
             action: DebtAction::OpenTunnel,
             payment_in_flight: false,
             last_successful_payment: None,
+            payment_batch_started: None,
         }
     }
 }
@@ -127,6 +136,7 @@ impl NodeDebtData {
             action: DebtAction::OpenTunnel,
             payment_in_flight: false,
             last_successful_payment: None,
+            payment_batch_started: None,
         }
     }
 }
@@ -673,6 +683,13 @@ impl DebtKeeper {
         let debt_limit_enabled = payment_settings.debt_limit_enabled;
         let apply_incoming_credit_immediately = payment_settings.apply_incoming_credit_immediately;
         let enable_enforcement = payment_settings.enable_enforcement;
+        // a minimum past our neighbor's close threshold would see us enforced on before we ever
+        // paid, so it is capped at half of the close threshold
+        let min_payment_amount = payment_settings.min_payment_amount.min(
+            (close_threshold.abs() / Int256::from(2))
+                .to_uint256()
+                .unwrap_or_default(),
+        );
 
         trace!(
             "Debt is {} and close is {}",
@@ -720,6 +737,39 @@ impl DebtKeeper {
                     )
                 })?;
 
+                // too small to be worth the gas, keep accumulating
+                if to_pay < min_payment_amount {
+                    trace!(
+                        "Debt {} to {} is below the minimum payment {}, accumulating",
+                        to_pay,
+                        ident.wg_public_key,
+                        min_payment_amount
+                    );
+                    debt_data.action = DebtAction::OpenTunnel;
+                    return Ok(DebtAction::OpenTunnel);
+                }
+
+                // hold the payment for the batch window so that further debt is settled in the same
+                // transaction, unless we are already halfway to our neighbor's close threshold
+                let batch_window =
+                    Duration::from_secs(payment_settings.payment_batch_window_seconds)
+                        .min(MAX_PAYMENT_BATCH_WINDOW);
+                let batch_started = *debt_data
+                    .payment_batch_started
+                    .get_or_insert_with(Instant::now);
+                if batch_started.elapsed() < batch_window
+                    && debt_data.debt < close_threshold.abs() / Int256::from(2)
+                {
+                    trace!(
+                        "Holding payment of {} to {} for batching",
+                        to_pay,
+                        ident.wg_public_key
+                    );
+                    debt_data.action = DebtAction::OpenTunnel;
+                    return Ok(DebtAction::OpenTunnel);
+                }
+                debt_data.payment_batch_started = None;
+
                 debt_data.payment_in_flight = true;
 
                 info!("Make payment to {} for {}", ident.wg_public_key, to_pay);
@@ -921,6 +971,113 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_dust_payment_accumulated() {
+        settings::set_rita_client(RitaClientSettings::default());
+        let mut common = settings::get_rita_common();
+        // a close threshold of 500 leaves the minimum uncapped
+        common.payment.payment_threshold = 50.into();
+        common.payment.min_payment_amount = 150u32.into();
+        common.payment.debt_limit_enabled = false;
+        settings::set_rita_common(common);
+
+        let mut d = DebtKeeper::new();
+        let ident = get_test_identity();
+
+        // over the pay threshold but below the minimum payment
+        d.traffic_update(&ident, Int256::from(100));
+        assert_eq!(
+            d.update_debt_keeper_state_machine(&ident).unwrap(),
+            DebtAction::OpenTunnel
+        );
+        assert_eq!(d.get_debt_data_mut(&ident).debt, Int256::from(100));
+
+        d.traffic_update(&ident, Int256::from(100));
+        assert_eq!(
+            d.update_debt_keeper_state_machine(&ident).unwrap(),
+            DebtAction::MakePayment {
+                amount: Uint256::from(200u32),
+                to: Box::new(ident),
+            }
+        );
+    }
+
+    #[test]
+    fn test_min_payment_capped_by_close_threshold() {
+        settings::set_rita_client(RitaClientSettings::default());
+        let mut common = settings::get_rita_common();
+        common.payment.payment_threshold = 100.into();
+        // far past the close threshold of 1000
+        common.payment.min_payment_amount = 1_000_000u32.into();
+        common.payment.debt_limit_enabled = true;
+        settings::set_rita_common(common);
+
+        let mut d = DebtKeeper::new();
+        let ident = get_test_identity();
+
+        d.traffic_update(&ident, Int256::from(400));
+        assert_eq!(
+            d.update_debt_keeper_state_machine(&ident).unwrap(),
+            DebtAction::OpenTunnel
+        );
+        d.traffic_update(&ident, Int256::from(100));
+        assert_eq!(
+            d.update_debt_keeper_state_machine(&ident).unwrap(),
+            DebtAction::MakePayment {
+                amount: Uint256::from(500u32),
+                to: Box::new(ident),
+            }
+        );
+    }
+
+    #[test]
+    fn test_payment_batched() {
+        settings::set_rita_client(RitaClientSettings::default());
+        let mut common = settings::get_rita_common();
+        common.payment.payment_threshold = 100.into();
+        common.payment.payment_batch_window_seconds = 30;
+        common.payment.debt_limit_enabled = false;
+        settings::set_rita_common(common);
+
+        let mut d = DebtKeeper::new();
+        let ident = get_test_identity();
+
+        // payable debt is held while the window is open
+        d.traffic_update(&ident, Int256::from(150));
+        assert_eq!(
+            d.update_debt_keeper_state_machine(&ident).unwrap(),
+            DebtAction::OpenTunnel
+        );
+        d.traffic_update(&ident, Int256::from(150));
+        assert_eq!(
+            d.update_debt_keeper_state_machine(&ident).unwrap(),
+            DebtAction::OpenTunnel
+        );
+
+        // once the window has passed everything accumulated is paid at once
+        d.get_debt_data_mut(&ident).payment_batch_started =
+            Some(Instant::now() - Duration::from_secs(30));
+        assert_eq!(
+            d.update_debt_keeper_state_machine(&ident).unwrap(),
+            DebtAction::MakePayment {
+                amount: Uint256::from(300u32),
+                to: Box::new(ident),
+            }
+        );
+        assert_eq!(d.get_debt_data_mut(&ident).payment_batch_started, None);
+
+        // a debt halfway to the close threshold is paid without waiting
+        let _ = d.payment_succeeded(&ident, Uint256::from(300u32));
+        d.traffic_update(&ident, Int256::from(600));
+        assert_eq!(
+            d.update_debt_keeper_state_machine(&ident).unwrap(),
+            DebtAction::MakePayment {
+                amount: Uint256::from(600u32),
+                to: Box::new(ident),
+            }
+        );
+    }
+
     #[test]
     fn test_single_pay_limited() {
         settings::set_rita_client(RitaClientSettings::default());
@@ -1416,6 +1573,7 @@ mod tests {
             action: DebtAction::OpenTunnel,
             payment_in_flight: false,
             last_successful_payment: None,
+            payment_batch_started: None,
         };
 
         let id2 = Identity {
@@ -1437,6 +1595,7 @@ mod tests {
             action: DebtAction::OpenTunnel,
             payment_in_flight: false,
             last_successful_payment: None,
+            payment_batch_started: None,
         };
 
         debt_data.insert(id, node_debts);
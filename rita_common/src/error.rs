@@ -37,6 +37,7 @@ pub enum RitaCommonError {
     DuplicatePayment,
     PaymentFailed(String),
     TunnelManagerError(TunnelManagerError),
+    NoFullNodes,
 }
 
 impl From<LoggerError> for RitaCommonError {
@@ -130,6 +131,7 @@ impl Display for RitaCommonError {
             RitaCommonError::BincodeError(e) => write!(f, "{e}"),
             RitaCommonError::SendRequestError(e) => write!(f, "{e}"),
             RitaCommonError::JsonPayloadError(e) => write!(f, "{e}"),
+            RitaCommonError::NoFullNodes => write!(f, "No full nodes configured!"),
         }
     }
 }
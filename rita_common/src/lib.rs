@@ -43,6 +43,7 @@ pub use crate::dashboard::babel::*;
 pub use crate::dashboard::debts::*;
 pub use crate::dashboard::development::*;
 pub use crate::dashboard::nickname::*;
+pub use crate::dashboard::node_blacklist::*;
 pub use crate::dashboard::own_info::*;
 pub use crate::dashboard::settings::*;
 pub use crate::dashboard::token_bridge::*;
@@ -154,8 +154,9 @@ where
 
         async move {
             // the /exits path is exempted from authenticaiton so that the
-            // checkup.ash cron script can continue to query it without issue
-            if password.is_none() || req_path == "/exits" {
+            // checkup.ash cron script can continue to query it without issue,
+            // the dashboard needs the password salt before it can log in
+            if password.is_none() || req_path == "/exits" || req_path == "/router/password/salt" {
                 let resp = fut.await?;
                 return Ok(resp);
             }
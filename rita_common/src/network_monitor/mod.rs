@@ -8,7 +8,7 @@
 //! as a bird flying through the connection rather than actual bloat. The solution here would be to also collect stats
 //! on traffic over every interface and base our action off of spikes in throughput as well as spikes in latency.
 
-use crate::rita_loop::fast_loop::FAST_LOOP_SPEED;
+use crate::rita_loop::fast_loop::get_fast_loop_speed;
 use crate::set_to_shape;
 use crate::tunnel_manager::shaping::ShapingAdjust;
 use crate::tunnel_manager::shaping::ShapingAdjustAction;
@@ -152,9 +152,16 @@ fn observe_network(
     latency_history: &mut HashMap<String, RunningLatencyStats>,
     packet_loss_history: &mut HashMap<String, RunningPacketLossStats>,
 ) {
-    // if this assertion is failing you're running this slowly enough
-    // that all the sample period logic is not relevent, go disable it
-    assert_eq!(SAMPLE_PERIOD as u64, FAST_LOOP_SPEED.as_secs());
+    // the latency and packet loss samples assume one tick every SAMPLE_PERIOD seconds, at any
+    // other rita_tick_interval they would be misread so we leave shaping alone
+    let tick_interval = get_fast_loop_speed();
+    if tick_interval.as_secs() != SAMPLE_PERIOD as u64 {
+        warn!(
+            "Skipping bufferbloat detection, it needs a {}s tick but rita_tick_interval is {:?}",
+            SAMPLE_PERIOD, tick_interval
+        );
+        return;
+    }
     if !SAMPLE_PERIOD <= 16 {
         panic!("NetworkMonitor is running too slowly! Please adjust constants");
     }
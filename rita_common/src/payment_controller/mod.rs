@@ -3,7 +3,9 @@
 //! until it is successfully in a block, see payment_validator, once the payment is on
 //! the blockchain it's up to the reciever to validate that it's correct
 
+use crate::blockchain_oracle::get_gas_limit;
 use crate::blockchain_oracle::get_oracle_balance;
+use crate::blockchain_oracle::TransactionType;
 use crate::debt_keeper::normalize_payment_amount;
 use crate::debt_keeper::payment_failed;
 use crate::payment_validator::ToValidate;
@@ -13,6 +15,7 @@ use althea_types::interop::UnpublishedPaymentTx;
 use althea_types::{Denom, PaymentTx};
 use althea_types::{Identity, SystemChain};
 use awc;
+use clarity::Address;
 use deep_space::client::ChainStatus;
 use deep_space::{Coin, Contact, EthermintPrivateKey};
 use futures::future::{join, join_all};
@@ -25,16 +28,65 @@ use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::Result as DisplayResult;
 use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::sync::RwLock;
 use std::time::Duration;
 use std::time::Instant;
 use web30::client::Web3;
+use web30::types::SendTxOption;
 
 pub const TRANSACTION_SUBMISSION_TIMEOUT: Duration = Duration::from_secs(15);
-pub const MAX_TXID_RETRIES: u8 = 15u8;
 /// How many blocks after submission a MicroTX will be valid for. If we wait this many blocks after submitting the
 /// tx we can be sure that it will not be included in a block and we can safely retry it
 pub const ALTHEA_L1_MICROTX_TIMEOUT: u64 = 25;
 
+lazy_static! {
+    /// Recent payment outcomes for each peer we pay, see PaymentHealth
+    static ref PAYMENT_HEALTH: Arc<RwLock<HashMap<Identity, PaymentHealth>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// Which side a failed payment is attributable to. Failures on our side, such as a low balance or
+/// an unreachable full node, would follow us to any peer. Failures on the peer's side, such as
+/// refusing to acknowledge our payments, are specific to that peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaymentFailure {
+    OurFault,
+    PeerFault,
+}
+
+/// Consecutive payment failures to a single peer, reset by any successful payment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentHealth {
+    pub our_failures: u32,
+    pub peer_failures: u32,
+}
+
+impl PaymentHealth {
+    fn record(&mut self, outcome: Option<PaymentFailure>) {
+        match outcome {
+            None => *self = PaymentHealth::default(),
+            Some(PaymentFailure::OurFault) => self.our_failures += 1,
+            Some(PaymentFailure::PeerFault) => self.peer_failures += 1,
+        }
+    }
+}
+
+/// Records the outcome of a payment to this peer, None for a success
+fn record_payment_outcome(to: Identity, outcome: Option<PaymentFailure>) {
+    PAYMENT_HEALTH
+        .write()
+        .unwrap()
+        .entry(to)
+        .or_default()
+        .record(outcome);
+}
+
+/// Returns the payment health of every peer we have tried to pay
+pub fn get_payment_health() -> HashMap<Identity, PaymentHealth> {
+    PAYMENT_HEALTH.read().unwrap().clone()
+}
+
 #[derive(Default, Clone)]
 pub struct PaymentController {
     /// this is a vec of outgoing transactions for the payment
@@ -83,8 +135,11 @@ impl PaymentController {
         // if payments fail they are passed back to debt keeper to handle retrying
         // or passed onto payment_validator becuase they might be published
         while let Some(pmt) = self.outgoing_queue.pop() {
+            let to = pmt.to;
             match make_payment(pmt, &previously_sent_payments).await {
                 Ok((pmt, resend)) => {
+                    // the funds are sent, but the peer is not paid until it has accepted the txid
+                    record_payment_outcome(to, resend.as_ref().map(|_| PaymentFailure::PeerFault));
                     // resend info contains info required to notify our neighbor that the payment
                     // has been made. Since we have already sent the payment on the blockchain
                     // and the neighbor is not watching their account but instead needs to be notified
@@ -100,6 +155,7 @@ impl PaymentController {
                     // anything that is definately not publsihed needs to go back to debt keeper
                     // anything that might be published must go to payment validator
                     payment_failed(pmt.to);
+                    record_payment_outcome(to, Some(e.failure_kind()));
                     warn!("Failed to send payment with {:?}!", e);
                 }
             }
@@ -110,27 +166,46 @@ impl PaymentController {
         // a long time to timeout, payments are done in series to reduce
         // nonce races
         let mut retry_futures = Vec::new();
-        let network_settings = settings::get_rita_common().network;
+        let mut waiting = Vec::new();
+        let common = settings::get_rita_common();
+        let network_settings = common.network;
+        let payment_settings = common.payment;
         while let Some(resend) = self.resend_queue.pop() {
-            if resend.attempt >= MAX_TXID_RETRIES {
-                error!(
-                    "Failed to resend txid {} after all attempts!",
-                    resend.pmt.txid
-                );
-            } else {
-                let fut = send_make_payment_endpoints(
-                    resend.pmt,
-                    network_settings.clone(),
-                    resend.full_node.clone(),
-                    &previously_sent_payments,
-                    resend.attempt,
-                );
-                retry_futures.push(fut);
+            match resend.retry.decide(
+                payment_settings.max_payment_retries,
+                Duration::from_secs(payment_settings.payment_retry_backoff_seconds),
+            ) {
+                RetryDecision::GiveUp => {
+                    error!(
+                        "Failed to resend txid {} after all attempts!",
+                        resend.pmt.txid
+                    );
+                    record_payment_outcome(
+                        resend.pmt.to,
+                        Some(PaymentControllerError::ResendFailed.failure_kind()),
+                    );
+                }
+                RetryDecision::Wait => waiting.push(resend),
+                RetryDecision::Attempt => {
+                    let to = resend.pmt.to;
+                    let fut = send_make_payment_endpoints(
+                        resend.pmt,
+                        network_settings.clone(),
+                        resend.full_node.clone(),
+                        &previously_sent_payments,
+                        resend.retry,
+                    );
+                    retry_futures.push(async move { (to, fut.await) });
+                }
             }
         }
+        self.resend_queue.extend(waiting);
         // if yet another retry is needed we'll get Some(ResendInfo) back and requeue
-        for resend in join_all(retry_futures).await.into_iter().flatten() {
-            self.resend_queue.push(resend);
+        for (to, resend) in join_all(retry_futures).await {
+            record_payment_outcome(to, resend.as_ref().map(|_| PaymentFailure::PeerFault));
+            if let Some(resend) = resend {
+                self.resend_queue.push(resend);
+            }
         }
 
         payments_sent_this_round
@@ -148,6 +223,22 @@ pub enum PaymentControllerError {
     },
     ZeroPayment,
     FailedToSendPayment,
+    /// the payment failed PaymentTx::validate and was not sent
+    InvalidPayment(String),
+}
+
+impl PaymentControllerError {
+    /// Which side this failure is attributable to, only a peer that will not accept our
+    /// payment notifications is at fault, every other failure happens before the peer is involved
+    pub fn failure_kind(&self) -> PaymentFailure {
+        match self {
+            Self::ResendFailed => PaymentFailure::PeerFault,
+            Self::InsufficientFunds { .. }
+            | Self::ZeroPayment
+            | Self::FailedToSendPayment
+            | Self::InvalidPayment(_) => PaymentFailure::OurFault,
+        }
+    }
 }
 
 impl Display for PaymentControllerError {
@@ -159,6 +250,7 @@ impl Display for PaymentControllerError {
             }
             Self::ZeroPayment => write!(f, "Attempted to send zero value payment!"),
             Self::FailedToSendPayment => write!(f, "Failed to send payment!"),
+            Self::InvalidPayment(reason) => write!(f, "Refusing to send invalid payment: {reason}"),
         }
     }
 }
@@ -171,6 +263,10 @@ async fn make_payment(
     previously_sent_payments: &HashMap<Identity, HashSet<PaymentTx>>,
 ) -> Result<(ToValidate, Option<ResendInfo>), PaymentControllerError> {
     let common = settings::get_rita_common();
+    if let Some(our_id) = common.get_identity() {
+        pmt.validate(&our_id)
+            .map_err(|e| PaymentControllerError::InvalidPayment(e.to_string()))?;
+    }
     let network_settings = common.network;
     let payment_settings = common.payment;
     let system_chain = payment_settings.system_chain;
@@ -325,7 +421,7 @@ async fn make_althea_payment(
         network_settings,
         cosmos_node_grpc,
         previously_sent_payments,
-        0,
+        PaymentRetry::default(),
     )
     .await;
 
@@ -366,7 +462,13 @@ async fn make_xdai_payment(
         balance, pmt.amount, our_address, pmt.to.eth_address
     );
 
-    let full_node = get_web3_server();
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => {
+            error!("Unable to send payment {:?} with {}", pmt, e);
+            return Err(PaymentControllerError::FailedToSendPayment);
+        }
+    };
     let web3 = Web3::new(&full_node, TRANSACTION_SUBMISSION_TIMEOUT);
 
     let tx = web3
@@ -376,7 +478,10 @@ async fn make_xdai_payment(
             pmt.amount,
             our_private_key.to_address(),
             *our_private_key,
-            vec![],
+            vec![SendTxOption::GasLimit(get_gas_limit(
+                payment_settings.system_chain,
+                TransactionType::Transfer,
+            ))],
         )
         .await;
 
@@ -408,7 +513,7 @@ async fn make_xdai_payment(
                 network_settings,
                 full_node,
                 previously_sent_payments,
-                0,
+                PaymentRetry::default(),
             )
             .await;
 
@@ -476,7 +581,7 @@ async fn send_make_payment_endpoints(
     network_settings: NetworkSettings,
     full_node: String,
     previously_sent_payments: &HashMap<Identity, HashSet<PaymentTx>>,
-    attempt: u8,
+    mut retry: PaymentRetry,
 ) -> Option<ResendInfo> {
     // testing hack
     let neighbor_url = if cfg!(not(test)) {
@@ -516,10 +621,12 @@ async fn send_make_payment_endpoints(
         .timeout(TRANSACTION_SUBMISSION_TIMEOUT)
         .send_json(&txid_history);
 
+    // only used if this attempt fails
+    retry.failed();
     let resend_info = ResendInfo {
         full_node: full_node.clone(),
         pmt,
-        attempt: attempt + 1,
+        retry,
     };
 
     // Hit both endpoints, in the case of a node having both endpoints, validator will simply get a duplicate transaction txid and discard it.
@@ -626,7 +733,62 @@ async fn send_make_payment_endpoints(
 struct ResendInfo {
     full_node: String,
     pmt: PaymentTx,
-    attempt: u8,
+    retry: PaymentRetry,
+}
+
+/// What to do with a failed payment, see PaymentRetry::decide
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Go ahead and attempt the payment
+    Attempt,
+    /// The backoff since the last failure has not yet elapsed, try again later
+    Wait,
+    /// The payment has failed max_payment_retries times, stop trying. This lasts until the
+    /// retry state is cleared by a successful payment or a change of payee
+    GiveUp,
+}
+
+/// Tracks the retries of a failed payment against the max_payment_retries and payment_retry_backoff_seconds
+/// limits in PaymentSettings. Every payment path uses this so that retry behavior is tuned in one place
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PaymentRetry {
+    failures: u8,
+    last_failure: Option<Instant>,
+    /// The address the retried payment is going to, if known
+    payee: Option<Address>,
+}
+
+impl PaymentRetry {
+    pub fn decide(&self, max_retries: u8, backoff: Duration) -> RetryDecision {
+        if self.failures >= max_retries {
+            return RetryDecision::GiveUp;
+        }
+        match self.last_failure {
+            Some(last_failure) if last_failure.elapsed() < backoff => RetryDecision::Wait,
+            _ => RetryDecision::Attempt,
+        }
+    }
+
+    /// Records a failed attempt
+    pub fn failed(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+        self.last_failure = Some(Instant::now());
+    }
+
+    /// Clears the retry state if the payment is now going to a different address, a payment
+    /// we gave up on should not stop payments to a newly configured payee
+    pub fn reset_for_payee(&mut self, payee: Address) {
+        if self.payee != Some(payee) {
+            *self = PaymentRetry {
+                payee: Some(payee),
+                ..Default::default()
+            };
+        }
+    }
+
+    pub fn failures(&self) -> u8 {
+        self.failures
+    }
 }
 
 #[test]
@@ -636,3 +798,57 @@ fn parse_althea_txhash() {
     println!("Parsed: {:?}", parsed);
     println!("{:?}", parsed.to_str_radix(16));
 }
+
+#[test]
+fn payment_retry_gives_up_after_max_retries() {
+    let max_retries = 3;
+    let backoff = Duration::from_millis(50);
+    let mut retry = PaymentRetry::default();
+    for _ in 0..max_retries {
+        assert_eq!(retry.decide(max_retries, backoff), RetryDecision::Attempt);
+        retry.failed();
+        if retry.failures() < max_retries {
+            // we must wait out the backoff before trying again
+            assert_eq!(retry.decide(max_retries, backoff), RetryDecision::Wait);
+            std::thread::sleep(backoff);
+        }
+    }
+    assert_eq!(retry.failures(), max_retries);
+    assert_eq!(retry.decide(max_retries, backoff), RetryDecision::GiveUp);
+    // giving up is sticky for the same payee
+    let payee: Address = "0x9CAFD25b8b5982F1edA0691DEF8997C55a4d8188"
+        .parse()
+        .unwrap();
+    retry.reset_for_payee(payee);
+    retry.failed();
+    retry.failed();
+    retry.failed();
+    retry.reset_for_payee(payee);
+    assert_eq!(retry.decide(max_retries, backoff), RetryDecision::GiveUp);
+    // but a new payee starts over
+    let other: Address = "0x8ba1f109551bD432803012645Ac136ddd64DBA72"
+        .parse()
+        .unwrap();
+    retry.reset_for_payee(other);
+    assert_eq!(retry.failures(), 0);
+    assert_eq!(retry.decide(max_retries, backoff), RetryDecision::Attempt);
+}
+
+#[test]
+fn payment_health_tracks_fault() {
+    let mut health = PaymentHealth::default();
+    health.record(Some(
+        PaymentControllerError::FailedToSendPayment.failure_kind(),
+    ));
+    health.record(Some(PaymentFailure::PeerFault));
+    health.record(Some(PaymentFailure::PeerFault));
+    assert_eq!(
+        health,
+        PaymentHealth {
+            our_failures: 1,
+            peer_failures: 2
+        }
+    );
+    health.record(None);
+    assert_eq!(health, PaymentHealth::default());
+}
@@ -706,7 +706,13 @@ fn decode_althea_microtx(response: GetTxResponse) -> Vec<MsgMicrotx> {
 /// is valid or invalid Some(true) or Some(false) respectively is returned. If the transaction
 /// is still pending None is returned.
 async fn handle_xdai_tx_checking(ts: ToValidate) -> Option<(ToValidate, TxValidationStatus)> {
-    let full_node = get_web3_server();
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => {
+            warn!("Unable to check transaction {:?} with {}", ts, e);
+            return None;
+        }
+    };
     let web3 = Web3::new(&full_node, TRANSACTION_VERIFICATION_TIMEOUT);
 
     let txid = ts.payment.txid;
@@ -21,6 +21,19 @@ use std::time::{Duration, Instant};
 pub const FAST_LOOP_SPEED: Duration = Duration::from_secs(5);
 pub const FAST_LOOP_TIMEOUT: Duration = Duration::from_secs(4);
 
+/// The configured speed of the common loops, from rita_tick_interval in the network settings.
+/// This is read every tick so a change takes effect without a restart, zero is treated as unset
+pub fn get_fast_loop_speed() -> Duration {
+    fast_loop_speed(settings::get_rita_common().network.rita_tick_interval)
+}
+
+fn fast_loop_speed(tick_interval: u64) -> Duration {
+    match tick_interval {
+        0 => FAST_LOOP_SPEED,
+        secs => Duration::from_secs(secs),
+    }
+}
+
 /// if we haven't heard a hello from a peer after this time we clean up the tunnel
 /// 15 minutes currently, this is not the final say on this value we check if the tunnel
 /// has seen any handshakes in TUNNEL_HANDSHAKE_TIMEOUT seconds, if it has we spare it from
@@ -50,6 +63,7 @@ pub fn start_rita_fast_loop() {
                     let mut outgoing_payments = Vec::new();
                     loop {
                         trace!("Common tick!");
+                        let tick_start = Instant::now();
 
                         let res = tm_get_neighbors();
                         trace!("Currently open tunnels: {:?}", res);
@@ -108,6 +122,11 @@ pub fn start_rita_fast_loop() {
                             .tick_payment_controller(payments_to_send, previously_sent_payments)
                             .await;
                         info!("Finished tick payment controller!");
+
+                        let loop_speed = get_fast_loop_speed();
+                        if tick_start.elapsed() < loop_speed {
+                            thread::sleep(loop_speed - tick_start.elapsed());
+                        }
                     }
                 });
                 info!(
@@ -165,15 +184,16 @@ pub fn peer_discovery_loop() {
                         tm_contact_peers(&pl).await;
                         info!("Done contacting peers");
 
-                        // sleep until it has been FAST_LOOP_SPEED seconds from start, whenever that may be
-                        // if it has been more than FAST_LOOP_SPEED seconds from start, go right ahead
+                        // sleep until it has been the loop speed from start, whenever that may be
+                        // if it has been more than the loop speed from start, go right ahead
                         info!("Peer Listener loop elapsed in = {:?}", start.elapsed());
-                        if start.elapsed() < FAST_LOOP_SPEED {
+                        let loop_speed = get_fast_loop_speed();
+                        if start.elapsed() < loop_speed {
                             info!(
                                 "Peer listener sleeping for {:?}",
-                                FAST_LOOP_SPEED - start.elapsed()
+                                loop_speed - start.elapsed()
                             );
-                            thread::sleep(FAST_LOOP_SPEED - start.elapsed());
+                            thread::sleep(loop_speed - start.elapsed());
                         }
                         info!("Peer Listener sleeping Done!");
                     }
@@ -196,3 +216,14 @@ pub fn peer_discovery_loop() {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fast_loop_speed() {
+        assert_eq!(fast_loop_speed(0), FAST_LOOP_SPEED);
+        assert_eq!(fast_loop_speed(10), Duration::from_secs(10));
+    }
+}
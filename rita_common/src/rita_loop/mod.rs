@@ -6,7 +6,9 @@
 //! halt essential functions like opening tunnels and managing peers
 
 use crate::network_endpoints::*;
+use crate::rita_loop::node_blacklist::is_blacklisted;
 use crate::traffic_watcher::init_traffic_watcher;
+use crate::RitaCommonError;
 use actix_async::System;
 use actix_web_async::{web, App, HttpServer};
 use rand::thread_rng;
@@ -16,6 +18,7 @@ use std::sync::atomic::Ordering;
 use std::thread;
 
 pub mod fast_loop;
+pub mod node_blacklist;
 pub mod slow_loop;
 pub mod write_to_disk;
 
@@ -37,34 +40,92 @@ pub fn set_gateway(input: bool) {
     IS_GATEWAY.store(input, Ordering::Relaxed)
 }
 
-/// Checks the list of full nodes, panics if none exist, if there exist
+/// Checks the list of full nodes, errors if none exist, if there exist
 /// one or more a random entry from the list is returned in an attempt
-/// to load balance across fullnodes
-pub fn get_web3_server() -> String {
+/// to load balance across fullnodes. Blacklisted nodes are skipped unless
+/// every configured node is blacklisted. If a full node is pinned in the
+/// payment settings it is always returned instead. On a fresh install the
+/// node list may be empty until the first operator checkin populates it
+pub fn get_web3_server() -> Result<String, RitaCommonError> {
     let common = settings::get_rita_common();
-    if common.payment.eth_node_list.is_empty() {
-        panic!("no full nodes configured!");
+    select_web3_server(
+        &common.payment.eth_node_list,
+        common.payment.pinned_full_node.as_deref(),
+    )
+}
+
+fn select_web3_server(
+    eth_node_list: &[String],
+    pinned_full_node: Option<&str>,
+) -> Result<String, RitaCommonError> {
+    if let Some(pinned) = pinned_full_node {
+        if eth_node_list.iter().any(|node| node == pinned) {
+            warn!(
+                "Full node pinning is active! All web3 requests go to {}",
+                pinned
+            );
+        } else {
+            warn!(
+                "Full node pinning is active! All web3 requests go to {} which is not in the node list",
+                pinned
+            );
+        }
+        return Ok(pinned.to_string());
+    }
+    if eth_node_list.is_empty() {
+        return Err(RitaCommonError::NoFullNodes);
+    }
+    let mut node_list: Vec<&String> = eth_node_list
+        .iter()
+        .filter(|node| !is_blacklisted(node))
+        .collect();
+    if node_list.is_empty() {
+        warn!("All full nodes are blacklisted! Ignoring blacklist");
+        node_list = eth_node_list.iter().collect();
     }
-    let node_list = common.payment.eth_node_list;
     let mut rng = thread_rng();
     let val = rng.gen_range(0..node_list.len());
 
-    node_list[val].clone()
+    Ok(node_list[val].clone())
 }
 
-/// Checks the list of full nodes, panics if none exist, if there exist
+/// Like get_web3_server but avoids the given nodes, used to retry a failed request against a
+/// different full node. Pinning still takes priority and once every node has been excluded any
+/// node may be returned again
+pub fn get_web3_server_excluding(exclude: &[String]) -> Result<String, RitaCommonError> {
+    let common = settings::get_rita_common();
+    select_web3_server(
+        &remaining_nodes(&common.payment.eth_node_list, exclude),
+        common.payment.pinned_full_node.as_deref(),
+    )
+}
+
+fn remaining_nodes(eth_node_list: &[String], exclude: &[String]) -> Vec<String> {
+    let remaining: Vec<String> = eth_node_list
+        .iter()
+        .filter(|node| !exclude.contains(node))
+        .cloned()
+        .collect();
+    if remaining.is_empty() {
+        eth_node_list.to_vec()
+    } else {
+        remaining
+    }
+}
+
+/// Checks the list of full nodes, errors if none exist, if there exist
 /// one or more a random entry from the list is returned in an attempt
 /// to load balance across fullnodes
-pub fn get_altheal1_server() -> String {
+pub fn get_altheal1_server() -> Result<String, RitaCommonError> {
     let common = settings::get_rita_common();
     if common.payment.althea_grpc_list.is_empty() {
-        panic!("no full nodes configured!");
+        return Err(RitaCommonError::NoFullNodes);
     }
     let node_list = common.payment.althea_grpc_list;
     let mut rng = thread_rng();
     let val = rng.gen_range(0..node_list.len());
 
-    node_list[val].clone()
+    Ok(node_list[val].clone())
 }
 
 pub fn start_core_rita_endpoints(workers: usize) {
@@ -114,3 +175,47 @@ pub fn start_rita_common_loops() {
     crate::rita_loop::fast_loop::start_rita_fast_loop();
     crate::rita_loop::fast_loop::peer_discovery_loop();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinned_full_node_overrides_selection() {
+        let node_list: Vec<String> = (0..10)
+            .map(|i| format!("https://node{i}.althea.net:8545"))
+            .collect();
+        let pinned = "https://pinned.althea.net:8545";
+        for _ in 0..100 {
+            assert_eq!(
+                select_web3_server(&node_list, Some(pinned)).unwrap(),
+                pinned
+            );
+        }
+        // a pinned node in the list is also returned exclusively
+        for _ in 0..100 {
+            assert_eq!(
+                select_web3_server(&node_list, Some(&node_list[3])).unwrap(),
+                node_list[3]
+            );
+        }
+        // without pinning we pick from the list
+        assert!(node_list.contains(&select_web3_server(&node_list, None).unwrap()));
+        // an empty node list is an error rather than a panic
+        assert!(matches!(
+            select_web3_server(&[], None),
+            Err(RitaCommonError::NoFullNodes)
+        ));
+    }
+
+    #[test]
+    fn test_excluded_full_nodes_skipped() {
+        let node_list: Vec<String> = (0..3)
+            .map(|i| format!("https://node{i}.althea.net:8545"))
+            .collect();
+        let remaining = remaining_nodes(&node_list, &node_list[..2]);
+        assert_eq!(remaining, vec![node_list[2].clone()]);
+        // with nothing left to try we fall back to the whole list
+        assert_eq!(remaining_nodes(&node_list, &node_list), node_list);
+    }
+}
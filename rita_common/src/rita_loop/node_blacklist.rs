@@ -0,0 +1,188 @@
+//! Full nodes that have misbehaved are placed on a blacklist for a while so that get_web3_server
+//! stops handing them out. Entries expire on their own after BLACKLIST_DURATION but can also be
+//! cleared by the operator from the dashboard, for example if a node was only briefly down. Nodes
+//! quarantined for a net_version mismatch do not expire, they stay out of rotation until a later
+//! net_version check shows them agreeing with the quorum again
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// How long a full node is kept out of rotation after it misbehaves
+pub const BLACKLIST_DURATION: Duration = Duration::from_secs(600);
+
+lazy_static! {
+    static ref NODE_BLACKLIST: Arc<RwLock<HashMap<String, BlacklistEntry>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// Why a full node was taken out of rotation
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub enum BlacklistReason {
+    /// The node failed to respond to a request
+    Unresponsive,
+    /// The node returned a block lower than one we have already seen
+    StaleBlock,
+    /// The node reported a net_version that disagrees with the quorum
+    NetVersionMismatch,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct BlacklistEntry {
+    pub node: String,
+    pub reason: BlacklistReason,
+    /// When the node was blacklisted
+    pub timestamp: SystemTime,
+}
+
+impl BlacklistEntry {
+    fn is_expired(&self) -> bool {
+        if self.reason == BlacklistReason::NetVersionMismatch {
+            return false;
+        }
+        match self.timestamp.elapsed() {
+            Ok(elapsed) => elapsed > BLACKLIST_DURATION,
+            // clock went backwards, keep the entry until it expires normally
+            Err(_) => false,
+        }
+    }
+}
+
+/// Takes a full node out of rotation for BLACKLIST_DURATION, or until it is released in the case of
+/// a net_version mismatch
+pub fn blacklist_node(node: &str, reason: BlacklistReason) {
+    warn!("Blacklisting full node {} for {:?}", node, reason);
+    NODE_BLACKLIST.write().unwrap().insert(
+        node.to_string(),
+        BlacklistEntry {
+            node: node.to_string(),
+            reason,
+            timestamp: SystemTime::now(),
+        },
+    );
+}
+
+/// Returns true if the given node is currently blacklisted
+pub fn is_blacklisted(node: &str) -> bool {
+    match NODE_BLACKLIST.read().unwrap().get(node) {
+        Some(entry) => !entry.is_expired(),
+        None => false,
+    }
+}
+
+/// Returns all currently blacklisted nodes, expired entries are cleaned up as a side effect
+pub fn get_blacklisted_nodes() -> Vec<BlacklistEntry> {
+    let mut blacklist = NODE_BLACKLIST.write().unwrap();
+    blacklist.retain(|_, entry| !entry.is_expired());
+    blacklist.values().cloned().collect()
+}
+
+/// Returns a node to rotation if it was quarantined for a net_version mismatch, used once a later
+/// net_version check passes. Nodes blacklisted for other reasons are left to expire
+pub fn release_net_version_quarantine(node: &str) {
+    let mut blacklist = NODE_BLACKLIST.write().unwrap();
+    if let Some(entry) = blacklist.get(node) {
+        if entry.reason == BlacklistReason::NetVersionMismatch {
+            info!(
+                "Full node {} agrees on net_version again, releasing it",
+                node
+            );
+            blacklist.remove(node);
+        }
+    }
+}
+
+/// Removes a node from the blacklist, if no node is specified the whole blacklist is cleared,
+/// returns the number of entries removed
+pub fn clear_blacklisted_node(node: Option<&str>) -> usize {
+    clear_from_blacklist(&mut NODE_BLACKLIST.write().unwrap(), node)
+}
+
+fn clear_from_blacklist(
+    blacklist: &mut HashMap<String, BlacklistEntry>,
+    node: Option<&str>,
+) -> usize {
+    match node {
+        Some(node) => match blacklist.remove(node) {
+            Some(_) => 1,
+            None => 0,
+        },
+        None => {
+            let removed = blacklist.len();
+            blacklist.clear();
+            removed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_then_clear_blacklist() {
+        let node_a = "https://test-blacklist-a.althea.net";
+        let node_b = "https://test-blacklist-b.althea.net";
+        blacklist_node(node_a, BlacklistReason::Unresponsive);
+        blacklist_node(node_b, BlacklistReason::StaleBlock);
+        assert!(is_blacklisted(node_a));
+        assert!(is_blacklisted(node_b));
+
+        let list = get_blacklisted_nodes();
+        let entry = list.iter().find(|e| e.node == node_b).unwrap();
+        assert_eq!(entry.reason, BlacklistReason::StaleBlock);
+        assert!(list.iter().any(|e| e.node == node_a));
+
+        assert_eq!(clear_blacklisted_node(Some(node_a)), 1);
+        assert!(!is_blacklisted(node_a));
+        assert!(is_blacklisted(node_b));
+        assert_eq!(clear_blacklisted_node(Some(node_a)), 0);
+        assert_eq!(clear_blacklisted_node(Some(node_b)), 1);
+        assert!(!is_blacklisted(node_b));
+    }
+
+    #[test]
+    fn test_net_version_quarantine_released() {
+        let node_a = "https://test-quarantine-a.althea.net";
+        let node_b = "https://test-quarantine-b.althea.net";
+        NODE_BLACKLIST.write().unwrap().insert(
+            node_a.to_string(),
+            BlacklistEntry {
+                node: node_a.to_string(),
+                reason: BlacklistReason::NetVersionMismatch,
+                timestamp: SystemTime::now() - BLACKLIST_DURATION * 2,
+            },
+        );
+        blacklist_node(node_b, BlacklistReason::Unresponsive);
+
+        // a quarantined node does not expire, only a passing check releases it
+        assert!(is_blacklisted(node_a));
+        release_net_version_quarantine(node_a);
+        assert!(!is_blacklisted(node_a));
+
+        // other blacklist reasons are not released by a net_version check
+        release_net_version_quarantine(node_b);
+        assert!(is_blacklisted(node_b));
+        assert_eq!(clear_blacklisted_node(Some(node_b)), 1);
+    }
+
+    #[test]
+    fn test_clear_whole_blacklist() {
+        // the global blacklist is shared with other tests, so clearing everything is tested on a local one
+        let entry = |node: &str| BlacklistEntry {
+            node: node.to_string(),
+            reason: BlacklistReason::Unresponsive,
+            timestamp: SystemTime::now(),
+        };
+        let mut blacklist = HashMap::new();
+        blacklist.insert("a".to_string(), entry("a"));
+        blacklist.insert("b".to_string(), entry("b"));
+        assert_eq!(clear_from_blacklist(&mut blacklist, Some("a")), 1);
+        assert_eq!(clear_from_blacklist(&mut blacklist, Some("a")), 0);
+        blacklist.insert("c".to_string(), entry("c"));
+        assert_eq!(clear_from_blacklist(&mut blacklist, None), 2);
+        assert!(blacklist.is_empty());
+    }
+}
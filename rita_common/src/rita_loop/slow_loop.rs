@@ -6,10 +6,14 @@ use crate::KI;
 use actix_async::System as AsyncSystem;
 use babel_monitor::open_babel_stream;
 use babel_monitor::parse_interfaces;
+use babel_monitor::parsing::get_interface_up_changes;
 use babel_monitor::set_local_fee;
 use babel_monitor::set_metric_factor;
 use babel_monitor::structs::BabelMonitorError;
+use babel_monitor::structs::Interface;
 use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::RwLock;
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
@@ -20,6 +24,35 @@ pub const SLOW_LOOP_TIMEOUT: Duration = Duration::from_secs(15);
 /// How many times we must fail to contact babel (consecutive) before we send a babel restart
 pub const BABEL_RESTART_COUNT: usize = 10;
 
+lazy_static! {
+    /// The interfaces babel reported in the last successful interface dump
+    static ref BABEL_INTERFACES: Arc<RwLock<Vec<Interface>>> = Arc::new(RwLock::new(Vec::new()));
+}
+
+/// Returns babel's view of whether the given interface is up, None if babel does not
+/// know about the interface or we have not yet talked to babel
+pub fn get_babel_interface_up(iface: &str) -> Option<bool> {
+    BABEL_INTERFACES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|i| i.name == iface)
+        .map(|i| i.up)
+}
+
+/// Stores the latest babel interface dump, logging any interfaces whose up state has changed
+fn update_babel_interfaces(babel_interfaces: &[Interface]) {
+    let mut stored = BABEL_INTERFACES.write().unwrap();
+    for iface in get_interface_up_changes(&stored, babel_interfaces) {
+        info!(
+            "Babel reports interface {} is now {}",
+            iface.name,
+            if iface.up { "up" } else { "down" }
+        );
+    }
+    *stored = babel_interfaces.to_vec();
+}
+
 pub fn start_rita_slow_loop() {
     let mut last_restart = Instant::now();
     // the number of times we have failed to contact babel consecutively,
@@ -59,6 +92,7 @@ pub fn start_rita_slow_loop() {
 
                         match parse_interfaces(&mut stream) {
                             Ok(babel_interfaces) => {
+                                update_babel_interfaces(&babel_interfaces);
                                 // performs tunnel GC + checks babel interfaces
                                 tm_common_slow_loop_helper(babel_interfaces);
 
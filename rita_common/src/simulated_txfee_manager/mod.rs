@@ -1,22 +1,57 @@
 //! The maintainer fee is a fraction of all payments that is sent to the firmware maintainer
 
+use crate::blockchain_oracle::get_gas_limit;
 use crate::blockchain_oracle::get_pay_thresh;
+use crate::blockchain_oracle::TransactionType;
+use crate::payment_controller::PaymentRetry;
+use crate::payment_controller::RetryDecision;
 use crate::payment_controller::TRANSACTION_SUBMISSION_TIMEOUT;
 use crate::rita_loop::get_web3_server;
 use crate::usage_tracker::update_payments;
 use crate::KI;
 use althea_types::Identity;
-use althea_types::PaymentTx;
+use althea_types::UnpublishedPaymentTx;
 use num256::Uint256;
 use num_traits::{Signed, Zero};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::time::Duration;
 use web30::client::Web3;
+use web30::types::SendTxOption;
 
 lazy_static! {
     static ref AMOUNT_OWED: Arc<RwLock<HashMap<u32, Uint256>>> =
         Arc::new(RwLock::new(HashMap::new()));
+    /// Failed attempts to pay the simulated txfee, limited by the payment retry settings
+    static ref PAYMENT_RETRY: Arc<RwLock<HashMap<u32, PaymentRetry>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+fn get_payment_retry() -> PaymentRetry {
+    let netns = KI.check_integration_test_netns();
+    PAYMENT_RETRY
+        .read()
+        .unwrap()
+        .get(&netns)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn set_payment_retry(retry: PaymentRetry) {
+    let netns = KI.check_integration_test_netns();
+    PAYMENT_RETRY.write().unwrap().insert(netns, retry);
+}
+
+fn record_payment_failure(mut retry: PaymentRetry, max_payment_retries: u8) {
+    retry.failed();
+    if retry.failures() >= max_payment_retries {
+        error!(
+            "Failed to pay the simulated txfee after {} attempts, giving up until the fee address changes",
+            retry.failures()
+        );
+    }
+    set_payment_retry(retry);
 }
 
 /// Gets Amount owed copy from the static ref, or default if no value has been set
@@ -68,6 +103,9 @@ pub async fn tick_simulated_tx() {
     let simulated_transaction_fee = payment_settings.simulated_transaction_fee;
     let amount_to_pay = get_amount_owed();
     let should_pay = amount_to_pay > pay_threshold.abs().to_uint256().unwrap();
+    let max_payment_retries = payment_settings.max_payment_retries;
+    let payment_retry_backoff = Duration::from_secs(payment_settings.payment_retry_backoff_seconds);
+    let gas_limit = get_gas_limit(payment_settings.system_chain, TransactionType::Transfer);
     drop(payment_settings);
     trace!(
         "We should pay the simulated tx fee {} of 1/{} % to {}",
@@ -79,6 +117,19 @@ pub async fn tick_simulated_tx() {
         return;
     }
 
+    let mut retry = get_payment_retry();
+    retry.reset_for_payee(simulated_transaction_fee_address);
+    match retry.decide(max_payment_retries, payment_retry_backoff) {
+        RetryDecision::Attempt => {}
+        RetryDecision::Wait => return,
+        RetryDecision::GiveUp => {
+            // the amount owed is retained, nothing more is paid until the fee address changes
+            trace!("Gave up on paying the simulated txfee");
+            set_payment_retry(retry);
+            return;
+        }
+    }
+
     let txfee_identity = Identity {
         eth_address: simulated_transaction_fee_address,
         // this key has no meaning, it's here so that we don't have to change
@@ -89,8 +140,23 @@ pub async fn tick_simulated_tx() {
         mesh_ip: "::1".parse().unwrap(),
         nickname: None,
     };
+    let payment = UnpublishedPaymentTx {
+        to: txfee_identity,
+        from: our_id,
+        amount: amount_to_pay,
+    };
+    if let Err(e) = payment.validate(&our_id) {
+        error!("Not paying the simulated txfee: {}", e);
+        return;
+    }
 
-    let full_node = get_web3_server();
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => {
+            warn!("Unable to pay the simulated txfee with {}", e);
+            return;
+        }
+    };
     let web3 = Web3::new(&full_node, TRANSACTION_SUBMISSION_TIMEOUT);
 
     let tx = web3
@@ -99,19 +165,14 @@ pub async fn tick_simulated_tx() {
             Vec::new(),
             amount_to_pay,
             eth_private_key,
-            vec![],
+            vec![SendTxOption::GasLimit(gas_limit)],
         )
         .await;
     match tx {
         Ok(tx) => match web3.send_prepared_transaction(tx).await {
             Ok(txid) => {
                 info!("Successfully paid the simulated txfee {:#066x}!", txid);
-                update_payments(PaymentTx {
-                    to: txfee_identity,
-                    from: our_id,
-                    amount: amount_to_pay,
-                    txid,
-                });
+                update_payments(payment.publish(txid));
 
                 // update the billing now that the payment has gone through
                 let amount_owed = &mut *AMOUNT_OWED.write().unwrap();
@@ -126,13 +187,16 @@ pub async fn tick_simulated_tx() {
                     // and the lack of 'pub' prevents the former
                     error!("Maintainer fee overpayment!")
                 }
+                set_payment_retry(PaymentRetry::default());
             }
             Err(e) => {
                 warn!("Failed to pay simulated txfee! {:?}", e);
+                record_payment_failure(retry, max_payment_retries);
             }
         },
         Err(e) => {
             warn!("Failed to pay simulated txfee! {:?}", e);
+            record_payment_failure(retry, max_payment_retries);
         }
     }
 }
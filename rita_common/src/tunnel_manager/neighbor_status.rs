@@ -1,9 +1,44 @@
 use super::get_tunnel_manager;
+use super::Neighbor;
 use super::PaymentState;
 use althea_types::Identity;
 use althea_types::NeighborStatus;
+use babel_monitor::structs::Neighbor as BabelNeighbor;
 use std::collections::HashMap;
 
+/// A babel neighbor joined with the Rita identity on the other end of the link, if we know it
+#[derive(Debug, Clone)]
+pub struct EnrichedNeighbor {
+    pub babel_neighbor: BabelNeighbor,
+    pub identity: Option<Identity>,
+}
+
+/// Babel only knows its neighbors by address and interface, this joins the parsed babel neighbors
+/// to the peers known by tunnel manager so that they can be correlated with billing identities. A
+/// babel neighbor is matched to a peer if it's on that peer's tunnel interface or if its link local
+/// address is the peer's tunnel address
+pub fn enrich_babel_neighbors(
+    babel_neighbors: Vec<BabelNeighbor>,
+    known_peers: &[Neighbor],
+) -> Vec<EnrichedNeighbor> {
+    babel_neighbors
+        .into_iter()
+        .map(|babel_neighbor| {
+            let identity = known_peers
+                .iter()
+                .find(|peer| {
+                    peer.iface_name == babel_neighbor.iface
+                        || peer.tunnel_ip == babel_neighbor.address
+                })
+                .map(|peer| peer.identity.global);
+            EnrichedNeighbor {
+                babel_neighbor,
+                identity,
+            }
+        })
+        .collect()
+}
+
 /// A cross thread accessible function for requesting the status of a given interface, this is not perfect as it's
 /// a mapping by identity, meaning that if a given id has multiple tunnels using different shaped speeds it may not
 /// paint the full picture, that being said my observation is that this never seems to be the case, I can of course be wrong
@@ -42,3 +77,57 @@ pub fn get_neighbor_status() -> HashMap<Identity, NeighborStatus> {
     }
     external_list
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use althea_types::LocalIdentity;
+    use clarity::Address;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_enrich_babel_neighbors() {
+        let id = Identity::new(
+            "fd00::1".parse().unwrap(),
+            Address::from_str("ffffffffffffffffffffffffffffffffffffffff").unwrap(),
+            "8BeCExnthLe5ou0EYec5jNqJ/PduZ1x2o7lpXJOpgXk="
+                .parse()
+                .unwrap(),
+            None,
+        );
+        let peer = Neighbor {
+            identity: LocalIdentity {
+                wg_port: 60000,
+                have_tunnel: None,
+                global: id,
+            },
+            iface_name: "wg0".to_string(),
+            tunnel_ip: "fe80::1".parse().unwrap(),
+            speed_limit: None,
+        };
+        let babel_neighbor = |address: &str, iface: &str| BabelNeighbor {
+            id: "a".to_string(),
+            address: address.parse().unwrap(),
+            iface: iface.to_string(),
+            reach: 65535,
+            txcost: 96,
+            rxcost: 96,
+            rtt: 0.0,
+            rttcost: 0,
+            cost: 96,
+        };
+
+        let enriched = enrich_babel_neighbors(
+            vec![
+                // joined by link local address
+                babel_neighbor("fe80::1", "br-pbs"),
+                // nobody we know
+                babel_neighbor("fe80::2", "br-pbs"),
+            ],
+            &[peer],
+        );
+        assert_eq!(enriched.len(), 2);
+        assert_eq!(enriched[0].identity, Some(id));
+        assert_eq!(enriched[1].identity, None);
+    }
+}
@@ -9,12 +9,14 @@ use crate::RitaCommonError;
 use althea_types::convert_flat_to_map_usage_data;
 use althea_types::convert_map_to_flat_usage_data;
 use althea_types::user_info::Usage;
+use althea_types::Identity;
 use althea_types::IndexedUsageHour;
 use althea_types::PaymentTx;
 use bincode::Error as BincodeError;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
+use num256::Uint256;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
@@ -52,6 +54,10 @@ pub const MINIMUM_NUMBER_OF_TRANSACTIONS_SMALL_STORAGE: usize = 75;
 /// The maximum amount of usage data that may be unsaved before we save out to the disk
 pub const MAX_UNSAVED_USAGE: u64 = 10 * 1000u64.pow(3);
 
+/// The granularity of usage history queries, one day. Usage is stored per hour, the hourly data
+/// is available from get_usage_data
+pub const USAGE_HISTORY_BUCKET_HOURS: u64 = 24;
+
 /// Just a storage wrapper for the usage tracker lazy static, to wrap the persisted data
 /// (usage tracker) and the non persistated data (throughput tracker) without using raw tuple
 pub struct UsageTrackerWrapper {
@@ -409,20 +415,10 @@ impl UsageTrackerStorage {
     }
 }
 
+/// Records a payment that has been made, payments are validated before they are sent so every
+/// payment passed in here has actually happened and must be recorded
 pub fn update_payments(payment: PaymentTx) {
     let mut history = USAGE_TRACKER_STORAGE.write().unwrap();
-
-    // This handles the following edge case:
-    // Router A is paying router B. Router B reboots and loses all data in
-    // payment vaildator datastore. When A sends a make_payment_v2, payments that have
-    // already been accounted for get counted twice.
-    // This checks the usage history to see if this tx exists
-    // thereby preventing the above case.
-    if history.usage_tracker.get_txids().contains(&payment.txid) {
-        error!("Tried to insert duplicate txid into usage tracker!");
-        return;
-    }
-
     history.usage_tracker.handle_payments(&payment);
 }
 
@@ -430,6 +426,18 @@ impl UsageTrackerStorage {
     /// Internal handler function that deals with adding a payment to the list
     /// and saving if required
     fn handle_payments(&mut self, payment: &PaymentTx) {
+        // This handles the following edge case:
+        // Router A is paying router B. Router B reboots and loses all data in
+        // payment vaildator datastore. When A sends a make_payment_v2, payments that have
+        // already been accounted for get counted twice. The same applies to a payment
+        // that is retried after a suspected failure that actually went through.
+        // This checks the usage history to see if this payment exists
+        // thereby preventing the above case.
+        if self.has_payment(&payment.idempotency_key()) {
+            error!("Tried to insert duplicate payment into usage tracker!");
+            return;
+        }
+
         let current_hour = match get_current_hour() {
             Ok(hour) => hour,
             Err(e) => {
@@ -508,6 +516,69 @@ pub fn get_payments_data() -> VecDeque<PaymentHour> {
     convert_payment_set_to_payment_hour(usage_tracker_var.usage_tracker.payments.clone())
 }
 
+/// Requests the usage and payment history for the time range [from, to)
+#[derive(Clone, Copy, Debug)]
+pub struct GetUsageHistory {
+    pub kind: UsageType,
+    pub from: SystemTime,
+    pub to: SystemTime,
+}
+
+/// Gets bucketed usage and payment totals for the requested time range, buckets with no usage and
+/// no payments are omitted. Returns an error if either end of the range is before the unix epoch
+pub fn get_usage_history(msg: GetUsageHistory) -> Result<Vec<UsageHistoryBucket>, RitaCommonError> {
+    let from_hour = msg.from.duration_since(UNIX_EPOCH)?.as_secs() / (60 * 60);
+    let to_hour = msg.to.duration_since(UNIX_EPOCH)?.as_secs() / (60 * 60);
+    let our_id = settings::get_rita_common().get_identity();
+    let usage_tracker = USAGE_TRACKER_STORAGE.read().unwrap();
+    Ok(usage_tracker
+        .usage_tracker
+        .get_usage_history(msg.kind, from_hour, to_hour, our_id))
+}
+
+impl UsageTrackerStorage {
+    /// Buckets usage and payments between from_hour (inclusive) and to_hour (exclusive), payments
+    /// from our_id are counted as sent and all others as received
+    pub fn get_usage_history(
+        &self,
+        kind: UsageType,
+        from_hour: u64,
+        to_hour: u64,
+        our_id: Option<Identity>,
+    ) -> Vec<UsageHistoryBucket> {
+        let history = match kind {
+            UsageType::Client => &self.client_bandwidth,
+            UsageType::Relay => &self.relay_bandwidth,
+            UsageType::Exit => &self.exit_bandwidth,
+        };
+        let in_range = |hour: &u64| *hour >= from_hour && *hour < to_hour;
+        let bucket_index = |hour: u64| hour - (hour % USAGE_HISTORY_BUCKET_HOURS);
+
+        let mut buckets: HashMap<u64, UsageHistoryBucket> = HashMap::new();
+        for (hour, usage) in history.iter().filter(|(hour, _)| in_range(hour)) {
+            let bucket = buckets
+                .entry(bucket_index(*hour))
+                .or_insert_with(|| UsageHistoryBucket::empty(bucket_index(*hour)));
+            bucket.up += usage.up;
+            bucket.down += usage.down;
+        }
+        for payment in self.payments.iter().filter(|p| in_range(&p.index)) {
+            let bucket = buckets
+                .entry(bucket_index(payment.index))
+                .or_insert_with(|| UsageHistoryBucket::empty(bucket_index(payment.index)));
+            if Some(payment.from) == our_id {
+                bucket.payments_sent += payment.amount;
+            } else {
+                bucket.payments_received += payment.amount;
+            }
+        }
+
+        let mut out: Vec<UsageHistoryBucket> = buckets.into_values().collect();
+        out.sort_by_key(|b| b.index);
+        out
+    }
+}
+
 /// On an interupt (SIGTERM), saving USAGE_TRACKER before exiting, this is essentially
 /// a reboot or restart only, most common form of shutdown is power being pulled
 pub fn save_usage_on_shutdown() {
@@ -1,4 +1,4 @@
-use althea_types::{Identity, IndexedUsageHour, PaymentTx, Usage};
+use althea_types::{Identity, IndexedUsageHour, PaymentIdempotencyKey, PaymentTx, Usage};
 use num256::Uint256;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::Hash;
@@ -59,13 +59,35 @@ pub struct UsageTrackerStorage {
     pub payments: HashSet<UsageTrackerPayment>,
 }
 
-impl UsageTrackerStorage {
-    pub fn get_txids(&self) -> HashSet<Uint256> {
-        let mut set = HashSet::new();
-        for p in &self.payments {
-            set.insert(p.txid);
+/// One bucket of usage history, buckets are USAGE_HISTORY_BUCKET_HOURS long and are indexed by the
+/// unix timestamp in hours of the start of the bucket
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UsageHistoryBucket {
+    pub index: u64,
+    pub up: u64,
+    pub down: u64,
+    /// The total amount of payments sent by this router in this bucket
+    pub payments_sent: Uint256,
+    /// The total amount of payments received by this router in this bucket
+    pub payments_received: Uint256,
+}
+
+impl UsageHistoryBucket {
+    pub fn empty(index: u64) -> UsageHistoryBucket {
+        UsageHistoryBucket {
+            index,
+            up: 0,
+            down: 0,
+            payments_sent: 0u8.into(),
+            payments_received: 0u8.into(),
         }
-        set
+    }
+}
+
+impl UsageTrackerStorage {
+    /// Returns true if a payment with this idempotency key has already been recorded
+    pub fn has_payment(&self, key: &PaymentIdempotencyKey) -> bool {
+        self.payments.iter().any(|p| p.idempotency_key() == *key)
     }
 }
 
@@ -148,6 +170,11 @@ impl Hash for UsageTrackerPayment {
 }
 
 impl UsageTrackerPayment {
+    /// See PaymentTx::idempotency_key
+    pub fn idempotency_key(&self) -> PaymentIdempotencyKey {
+        PaymentIdempotencyKey { txid: self.txid }
+    }
+
     pub fn from_payment_tx(input: PaymentTx, index: u64) -> UsageTrackerPayment {
         UsageTrackerPayment {
             to: input.to,
@@ -6,13 +6,13 @@ pub mod test {
         convert_payment_set_to_payment_hour, UsageTrackerPayment, UsageTrackerStorageOld,
     };
     use crate::usage_tracker::{
-        get_current_hour, IOError, PaymentHour, Usage, UsageTrackerStorage, MAX_USAGE_ENTRIES,
-        MINIMUM_NUMBER_OF_TRANSACTIONS_LARGE_STORAGE,
+        get_current_hour, IOError, PaymentHour, Usage, UsageTrackerStorage, UsageType,
+        MAX_USAGE_ENTRIES, MINIMUM_NUMBER_OF_TRANSACTIONS_LARGE_STORAGE,
     };
     use crate::RitaCommonError;
     use althea_types::{
-        convert_map_to_flat_usage_data, Identity, IndexedUsageHour, UnpublishedPaymentTx,
-        UsageTrackerTransfer,
+        convert_map_to_flat_usage_data, Identity, IndexedUsageHour, PaymentTx,
+        UnpublishedPaymentTx, UsageTrackerTransfer,
     };
     use flate2::write::ZlibEncoder;
     use flate2::Compression;
@@ -111,6 +111,94 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn usage_history_range() {
+        let our_id = random_identity();
+        let neighbor = random_identity();
+        let mut bandwidth = HashMap::new();
+        // two hours in the first bucket, one in the second and one outside the range
+        for (hour, up) in [(48, 1), (50, 2), (72, 4), (96, 8)] {
+            bandwidth.insert(
+                hour,
+                Usage {
+                    up,
+                    down: up * 10,
+                    price: 1,
+                },
+            );
+        }
+        let mut payments = HashSet::new();
+        for (index, from, to, amount) in [
+            (49, our_id, neighbor, 5u32),
+            (49, neighbor, our_id, 7),
+            (100, our_id, neighbor, 11),
+        ] {
+            payments.insert(UsageTrackerPayment {
+                to,
+                from,
+                amount: amount.into(),
+                txid: (index as u32 + amount).into(),
+                index,
+            });
+        }
+        let tracker = UsageTrackerStorage {
+            last_save_hour: 0,
+            client_bandwidth: bandwidth,
+            relay_bandwidth: HashMap::new(),
+            exit_bandwidth: HashMap::new(),
+            payments,
+        };
+
+        let history = tracker.get_usage_history(UsageType::Client, 48, 96, Some(our_id));
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].index, 48);
+        assert_eq!(history[0].up, 3);
+        assert_eq!(history[0].down, 30);
+        assert_eq!(history[0].payments_sent, 5u32.into());
+        assert_eq!(history[0].payments_received, 7u32.into());
+        assert_eq!(history[1].index, 72);
+        assert_eq!(history[1].up, 4);
+
+        // nothing is returned outside of the range
+        assert!(tracker
+            .get_usage_history(UsageType::Client, 0, 48, Some(our_id))
+            .is_empty());
+        assert!(tracker
+            .get_usage_history(UsageType::Relay, 48, 96, Some(our_id))
+            .is_empty());
+    }
+
+    #[test]
+    fn duplicate_payment_recorded_once() {
+        let mut tracker = UsageTrackerStorage {
+            last_save_hour: 0,
+            client_bandwidth: HashMap::new(),
+            relay_bandwidth: HashMap::new(),
+            exit_bandwidth: HashMap::new(),
+            payments: HashSet::new(),
+        };
+        let payment = UnpublishedPaymentTx {
+            to: random_identity(),
+            from: random_identity(),
+            amount: 100u32.into(),
+        }
+        .publish(1234u32.into());
+
+        // a retry reports the same payment again, it must only be counted once
+        tracker.handle_payments(&payment);
+        tracker.handle_payments(&payment);
+        assert_eq!(tracker.payments.len(), 1);
+        assert!(tracker.has_payment(&payment.idempotency_key()));
+
+        // the same txid reported with a different amount is still the same payment
+        let misreported = PaymentTx {
+            amount: 200u32.into(),
+            ..payment
+        };
+        tracker.handle_payments(&misreported);
+        assert_eq!(tracker.payments.len(), 1);
+    }
+
     // generates a nontrivial usage tracker struct for testing
     pub fn generate_dummy_usage_tracker() -> UsageTrackerStorage {
         let current_hour = get_current_hour().unwrap();
@@ -188,6 +276,8 @@ pub mod test {
         let secret: [u8; 32] = rand::random();
         let mut ip: [u8; 16] = [0; 16];
         ip.copy_from_slice(&secret[0..16]);
+        // mesh ips are always in fd00::/8
+        ip[0] = 0xfd;
 
         // the starting location of the funds
         let eth_key = PrivateKey::from_bytes(secret).unwrap();
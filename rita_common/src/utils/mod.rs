@@ -2,7 +2,8 @@ use std::env;
 use std::time::Duration;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use babel_monitor::open_babel_stream;
+use babel_monitor::open_babel_stream_with_router_id;
+use babel_monitor::parsing::verify_router_id;
 use babel_monitor::structs::BabeldConfig;
 
 /// Random utilities that don't go anywhere else, many of these are used only in one or the other of rita_exit or rita_client so one will use it and the other will
@@ -37,14 +38,22 @@ pub fn secs_since_unix_epoch() -> i64 {
 /// tunnel manager starts operating or tunnels will be setup that don't respect the defaults
 /// we are trying to configure. All of these values can be changed at runtime but this function is
 /// intended for startup only
-pub fn apply_babeld_settings_defaults(babeld_port: u16, config: BabeldConfig) {
+/// If an expected router id is provided it is checked against the one babel reports
+pub fn apply_babeld_settings_defaults(
+    babeld_port: u16,
+    config: BabeldConfig,
+    expected_router_id: Option<String>,
+) {
     // how long before we give up trying to contact babel, since this is a startup process babeld
     // many not be reachable due to just being started so we want to wait a bit, but not indefinately
     const BABEL_CONTACT_TIMEOUT: Duration = Duration::from_secs(20);
     let start = Instant::now();
     while Instant::now() < start + BABEL_CONTACT_TIMEOUT {
-        match open_babel_stream(babeld_port, BABEL_CONTACT_TIMEOUT) {
-            Ok(mut stream) => {
+        match open_babel_stream_with_router_id(babeld_port, BABEL_CONTACT_TIMEOUT) {
+            Ok((mut stream, router_id)) => {
+                if let Some(expected) = &expected_router_id {
+                    verify_router_id(&router_id, expected);
+                }
                 if let Err(e) = babel_monitor::set_local_fee(&mut stream, config.local_fee) {
                     error!("Failed to set babel local fee with {:?}", e);
                 }
@@ -7,10 +7,11 @@ use std::convert::TryInto;
 use std::fmt::Write;
 use std::hash::{Hash, Hasher};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
 
 use crate::RitaExitError;
 
-use super::RITA_EXIT_STATE;
+use super::{RitaExitState, RITA_EXIT_STATE};
 
 /// Wg exit port on client side
 pub const CLIENT_WG_PORT: u16 = 59999;
@@ -64,6 +65,80 @@ pub fn add_new_internal_ip_assignement(addr: IpAddr, key: WgKey) {
         .insert(addr, key);
 }
 
+/// Records that a client was seen at the given time, either checking in or handshaking
+/// with the exit tunnel, this renews its lease
+pub fn mark_client_seen(key: WgKey, seen: Instant) {
+    let mut state = RITA_EXIT_STATE.write().unwrap();
+    let last_seen = state.client_last_seen.entry(key).or_insert(seen);
+    if seen > *last_seen {
+        *last_seen = seen;
+    }
+}
+
+/// Filters the registered clients down to those with an active lease, freeing the internal ip and
+/// ipv6 subnet of any client that has not been seen within the lease timeout so the pool is not
+/// exhausted by routers that have gone away. Clients we have never seen start their lease now,
+/// this way every client gets a full lease after the exit restarts
+pub fn expire_client_leases(clients: Vec<Identity>, timeout: Duration) -> Vec<Identity> {
+    if timeout.is_zero() {
+        return clients;
+    }
+    expire_leases(
+        &mut RITA_EXIT_STATE.write().unwrap(),
+        clients,
+        timeout,
+        Instant::now(),
+    )
+}
+
+fn expire_leases(
+    state: &mut RitaExitState,
+    clients: Vec<Identity>,
+    timeout: Duration,
+    now: Instant,
+) -> Vec<Identity> {
+    // clients that are no longer registered will not be seen again, an expired client that is still
+    // registered keeps its entry since forgetting it would start it a new lease on the next tick
+    let registered: HashSet<WgKey> = clients.iter().map(|c| c.wg_public_key).collect();
+    state
+        .client_last_seen
+        .retain(|key, _| registered.contains(key));
+
+    let mut active = Vec::new();
+    let mut expired = HashSet::new();
+    for client in clients {
+        let key = client.wg_public_key;
+        let last_seen = *state.client_last_seen.entry(key).or_insert(now);
+        if now.saturating_duration_since(last_seen) <= timeout {
+            active.push(client);
+        } else {
+            expired.insert(key);
+        }
+    }
+    if expired.is_empty() {
+        return active;
+    }
+
+    let assignments = &mut state.ip_assignment_map;
+    let before = assignments.internal_ip_assignments.len() + assignments.ipv6_assignments.len();
+    assignments
+        .internal_ip_assignments
+        .retain(|_, assigned| !expired.contains(assigned));
+    assignments
+        .ipv6_assignments
+        .retain(|_, assigned| !expired.contains(assigned));
+    let freed =
+        before - (assignments.internal_ip_assignments.len() + assignments.ipv6_assignments.len());
+    if freed > 0 {
+        info!(
+            "Leases for {} clients expired, freed {} addresses",
+            expired.len(),
+            freed
+        );
+    }
+    active
+}
+
 /// Take an index i, a larger subnet and a smaller subnet length and generate the ith smaller subnet in the larger subnet
 /// For instance, if our larger subnet is fd00::1330/120, smaller sub len is 124, and index is 1, our generated subnet would be fd00::1310/124
 pub fn generate_iterative_client_subnet(
@@ -317,13 +392,15 @@ pub fn display_hashset<T: ToString>(input: &HashSet<T>) -> String {
 mod tests {
     use althea_types::Identity;
     use ipnetwork::IpNetwork;
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
 
     use crate::database::in_memory_database::{
         generate_iterative_client_subnet, get_client_internal_ip, get_internal_ip_assignments,
         get_ipv6_assignments,
     };
 
-    use super::{get_client_ipv6, hash_wgkey};
+    use super::{expire_leases, get_client_ipv6, hash_wgkey, RitaExitState};
 
     #[test]
     fn test_internet_ipv6_assignment() {
@@ -541,6 +618,56 @@ mod tests {
         println!("Internal ip client 2: {}", ip);
     }
 
+    #[test]
+    fn test_client_lease_expiry() {
+        let client = Identity {
+            mesh_ip: "fd00::1337".parse().unwrap(),
+            eth_address: "0x4Af6D4125f3CBF07EBAD056E2eCa7b17c58AFEa4"
+                .parse()
+                .unwrap(),
+            wg_public_key: "TgR85AcLBY/7cLHXZIICcwVDU+1Pj/cjFeduCUNvLVU="
+                .parse()
+                .unwrap(),
+            nickname: None,
+        };
+        let ip: IpAddr = "172.168.0.101".parse().unwrap();
+        let mut state = RitaExitState::default();
+        state
+            .ip_assignment_map
+            .internal_ip_assignments
+            .insert(ip, client.wg_public_key);
+        let timeout = Duration::from_secs(60);
+        let start = Instant::now();
+
+        // a client we have not seen before starts its lease now
+        let active = expire_leases(&mut state, vec![client], timeout, start);
+        assert_eq!(active, vec![client]);
+
+        let now = start + Duration::from_secs(30);
+        let active = expire_leases(&mut state, vec![client], timeout, now);
+        assert_eq!(active, vec![client]);
+        assert!(state
+            .ip_assignment_map
+            .internal_ip_assignments
+            .contains_key(&ip));
+
+        // idle past the timeout, the client is dropped and its ip reclaimed
+        let now = start + Duration::from_secs(61);
+        let active = expire_leases(&mut state, vec![client], timeout, now);
+        assert!(active.is_empty());
+        assert!(state.ip_assignment_map.internal_ip_assignments.is_empty());
+
+        // seeing the client again renews the lease
+        state.client_last_seen.insert(client.wg_public_key, now);
+        let active = expire_leases(&mut state, vec![client], timeout, now);
+        assert_eq!(active, vec![client]);
+
+        // a client that is no longer registered is forgotten
+        let active = expire_leases(&mut state, Vec::new(), timeout, now);
+        assert!(active.is_empty());
+        assert!(state.client_last_seen.is_empty());
+    }
+
     /// Test iterative subnet generation
     #[test]
     fn test_generate_iterative_subnet() {
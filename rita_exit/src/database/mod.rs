@@ -7,11 +7,13 @@ use crate::database::geoip::verify_ip;
 use crate::database::in_memory_database::display_hashset;
 use crate::database::in_memory_database::get_client_internal_ip;
 use crate::database::in_memory_database::get_client_ipv6;
+use crate::database::in_memory_database::mark_client_seen;
 use crate::database::in_memory_database::to_exit_client;
 use crate::database::in_memory_database::DEFAULT_CLIENT_SUBNET_SIZE;
 use crate::rita_loop::EXIT_INTERFACE;
 use crate::rita_loop::EXIT_LOOP_TIMEOUT;
 use crate::rita_loop::LEGACY_INTERFACE;
+use crate::traffic_watcher::get_exit_price;
 use crate::IpAssignmentMap;
 use crate::RitaExitError;
 use althea_kernel_interface::ExitClient;
@@ -44,6 +46,8 @@ pub mod in_memory_database;
 pub struct RitaExitState {
     ip_assignment_map: IpAssignmentMap,
     geoip_cache: HashMap<IpAddr, Regions>,
+    /// When each client last checked in or handshaked with the exit tunnel, see expire_client_leases
+    client_last_seen: HashMap<WgKey, Instant>,
 }
 
 lazy_static! {
@@ -64,11 +68,12 @@ pub fn get_exit_info() -> ExitDetails {
     ExitDetails {
         server_internal_ip: exit_settings.exit_network.own_internal_ip.into(),
         wg_exit_port: exit_settings.exit_network.wg_tunnel_port,
-        exit_price: exit_settings.exit_network.exit_price,
+        exit_price: get_exit_price(),
         exit_currency: exit_settings.payment.system_chain,
         netmask: exit_settings.exit_network.netmask,
         description: exit_settings.description,
         verif_mode: ExitVerifMode::Phone,
+        region: exit_settings.region,
     }
 }
 
@@ -198,6 +203,7 @@ pub async fn client_status(
     {
         Ok(their_record) => {
             trace!("record exists, updating");
+            mark_client_seen(their_record.wg_public_key, Instant::now());
 
             let current_ip: IpAddr = get_client_internal_ip(
                 their_record,
@@ -429,6 +435,16 @@ pub fn setup_clients(
         .expect("There should be a wg_exit interface")
         .into_iter()
         .collect();
+    for (key, handshake) in new_wg_exit_clients_timestamps
+        .iter()
+        .chain(wg_exit_clients_timestamps.iter())
+    {
+        if let Ok(elapsed) = handshake.elapsed() {
+            if let Some(seen) = Instant::now().checked_sub(elapsed) {
+                mark_client_seen(*key, seen);
+            }
+        }
+    }
 
     let client_list_for_setup: Vec<Identity> = key_to_client_map
         .clone()
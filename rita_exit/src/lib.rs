@@ -26,6 +26,7 @@ use rita_common::dashboard::babel::*;
 use rita_common::dashboard::debts::*;
 use rita_common::dashboard::development::*;
 use rita_common::dashboard::nickname::*;
+use rita_common::dashboard::node_blacklist::*;
 use rita_common::dashboard::own_info::READABLE_VERSION;
 use rita_common::dashboard::own_info::*;
 use rita_common::dashboard::settings::*;
@@ -75,6 +76,7 @@ pub fn start_rita_exit_dashboard(startup_status: Arc<RwLock<Option<String>>>) {
                     .route("/metric_factor/{factor}", web::post().to(set_metric_factor))
                     .route("/settings", web::get().to(get_settings))
                     .route("/settings", web::post().to(set_settings))
+                    .route("/settings/reload", web::post().to(reload_settings))
                     .route("/version", web::get().to(version))
                     .route("/wg_public_key", web::get().to(get_wg_public_key))
                     .route("/wipe", web::post().to(wipe))
@@ -84,6 +86,15 @@ pub fn start_rita_exit_dashboard(startup_status: Arc<RwLock<Option<String>>>) {
                     .route("/withdraw_all/{address}", web::post().to(withdraw_all))
                     .route("/nickname/get/", web::get().to(get_nickname))
                     .route("/nickname/set/", web::post().to(set_nickname))
+                    .route("/full_nodes/blacklist", web::get().to(get_node_blacklist))
+                    .route(
+                        "/full_nodes/blacklist/clear",
+                        web::post().to(clear_node_blacklist),
+                    )
+                    .route(
+                        "/full_nodes/blacklist/remove",
+                        web::post().to(remove_from_node_blacklist),
+                    )
                     .route("/usage/payments", web::get().to(get_payments))
                     .route("/token_bridge/status", web::get().to(get_bridge_status))
                     .app_data(startup_status.clone())
@@ -227,7 +227,14 @@ pub async fn secure_status_request(request: Json<EncryptedExitClientIdentity>) -
         .expect("Why dont we have a private key?")
         .to_address();
     let contract_addr = exit_settings.exit_network.registered_users_contract_addr;
-    let contact = Web3::new(&get_web3_server(), CLIENT_STATUS_TIMEOUT);
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => {
+            error!("Unable to look up client registration with {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let contact = Web3::new(&full_node, CLIENT_STATUS_TIMEOUT);
 
     let their_wg_pubkey = request.pubkey;
     let their_nacl_pubkey = request.pubkey.into();
@@ -310,7 +317,14 @@ pub async fn get_exit_list(request: Json<EncryptedExitClientIdentity>) -> HttpRe
 
     let their_nacl_pubkey = request.pubkey.into();
 
-    let contact = Web3::new(&get_web3_server(), CLIENT_STATUS_TIMEOUT);
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => {
+            error!("Unable to look up client registration with {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let contact = Web3::new(&full_node, CLIENT_STATUS_TIMEOUT);
     let rita_exit = get_rita_exit();
     let our_id = rita_exit.get_identity().unwrap();
     let our_addr = rita_exit
@@ -390,7 +404,14 @@ pub async fn get_exit_list_v2(request: Json<EncryptedExitClientIdentity>) -> Htt
 
     let their_nacl_pubkey = request.pubkey.into();
 
-    let contact = Web3::new(&get_web3_server(), CLIENT_STATUS_TIMEOUT);
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => {
+            error!("Unable to look up client registration with {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let contact = Web3::new(&full_node, CLIENT_STATUS_TIMEOUT);
     let rita_exit = get_rita_exit();
     let our_addr = rita_exit
         .payment
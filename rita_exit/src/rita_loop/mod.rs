@@ -10,6 +10,7 @@
 //! Two threads are generated by this, one actual worker thread and a watchdog restarting thread that only
 //! wakes up to restart the inner thread if anything goes wrong.
 
+use crate::database::in_memory_database::expire_client_leases;
 use crate::database::{
     enforce_exit_clients, setup_clients, validate_clients_region, ExitClientSetupStates,
 };
@@ -111,7 +112,13 @@ async fn update_client_list(reg_clients_list: Vec<Identity>) -> Vec<Identity> {
         .exit_network
         .registered_users_contract_addr;
     let our_address = payment_settings.eth_address.expect("No address!");
-    let full_node = get_web3_server();
+    let full_node = match get_web3_server() {
+        Ok(node) => node,
+        Err(e) => {
+            warn!("Unable to update the client list with {}", e);
+            return reg_clients_list;
+        }
+    };
     let web3 = web30::client::Web3::new(&full_node, Duration::from_secs(5));
 
     let get_clients_benchmark = Instant::now();
@@ -157,9 +164,14 @@ async fn rita_exit_loop(
 
     info!("About to setup clients");
     let start_setup_benchmark = Instant::now();
+    // only clients with an active lease get a tunnel, the others have their addresses freed
+    let leased_clients = expire_client_leases(
+        reg_clients_list.clone(),
+        Duration::from_secs(rita_exit.exit_network.client_lease_timeout_seconds),
+    );
     // Create and update client tunnels
     match setup_clients(
-        reg_clients_list.clone(),
+        leased_clients,
         rita_exit_cache.geoip_blacklist.clone(),
         ExitClientSetupStates {
             old_clients: rita_exit_cache.wg_clients.clone(),
@@ -18,6 +18,7 @@ use althea_kernel_interface::KI;
 use althea_types::Identity;
 use althea_types::WgKey;
 use babel_monitor::structs::Route;
+use babel_monitor::sum_path_price;
 use ipnetwork::IpNetwork;
 use rita_common::debt_keeper::traffic_update;
 use rita_common::debt_keeper::Traffic;
@@ -26,6 +27,37 @@ use rita_common::usage_tracker::update_usage_data;
 use rita_common::usage_tracker::UpdateUsage;
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+lazy_static! {
+    /// The average price in wei per byte of the routes to our clients as of the last traffic
+    /// watcher run, this is the cost input for dynamic exit pricing
+    static ref AVERAGE_CLIENT_ROUTE_PRICE: Arc<RwLock<u64>> = Arc::new(RwLock::new(0));
+}
+
+/// Returns the price this exit currently charges in wei per byte, this is the static exit price
+/// unless dynamic pricing is configured, see ExitNetworkSettings::get_exit_price
+pub fn get_exit_price() -> u64 {
+    let measured_cost = *AVERAGE_CLIENT_ROUTE_PRICE.read().unwrap();
+    settings::get_rita_exit()
+        .exit_network
+        .get_exit_price(measured_cost)
+}
+
+/// Averages the destination prices computed by get_babel_info, excluding our own entry. The prices
+/// are advertised by other nodes so the total saturates rather than overflowing
+fn average_route_price(destinations: &HashMap<WgKey, u64>, our_key: &WgKey) -> u64 {
+    let prices: Vec<u64> = destinations
+        .iter()
+        .filter(|(key, _)| *key != our_key)
+        .map(|(_, price)| *price)
+        .collect();
+    if prices.is_empty() {
+        return 0;
+    }
+    sum_path_price(prices.iter().copied()) / prices.len() as u64
+}
 
 fn get_babel_info(
     routes: &[Route],
@@ -160,7 +192,6 @@ pub fn watch_exit_traffic(
     // to our own price. In the case Exit -> A -> B -> C the exit pays A a lump sum for it's own
     // fees as well as B's fees. This means the exit pays the transaction fee (a percentage) for
     // that entire series of hops, we use the percentage number to ensure the exit recovers that amount
-    let our_price = settings::get_rita_exit().exit_network.exit_price;
     let tx_fee_percentage = settings::get_rita_common()
         .payment
         .simulated_transaction_fee;
@@ -180,6 +211,10 @@ pub fn watch_exit_traffic(
     let id_from_ip = ret.ip_to_id;
     let destinations = get_babel_info(routes, our_id, id_from_ip);
 
+    *AVERAGE_CLIENT_ROUTE_PRICE.write().unwrap() =
+        average_route_price(&destinations, &our_id.wg_public_key);
+    let our_price = get_exit_price();
+
     let counters = match KI.read_wg_counters(LEGACY_INTERFACE) {
         Ok(res) => res,
         Err(e) => {
@@ -118,11 +118,37 @@ pub struct ExitClientSettings {
     /// traits to get ContactType for actual operations. This struct represents a full range
     /// of possibilities for contact info.
     pub contact_info: Option<ContactStorage>,
-    /// This controls which interfaces will be proxied over the exit tunnel
+    /// This controls which lan networks will be proxied over the exit tunnel, these are OpenWrt
+    /// logical network names such as "lan" rather than kernel devices
     pub lan_nics: HashSet<String>,
     /// Specifies if the user would like to receive low balance messages from the exit
     #[serde(default = "default_balance_notification")]
     pub low_balance_notification: bool,
+    /// A bonus subtracted from the babel metric of exits that share an internal subnet with the exit
+    /// we are currently connected to, this gives the exit switcher a soft preference for staying within
+    /// the current subnet unless an exit in another subnet is substantially better. Zero disables this
+    #[serde(default)]
+    pub subnet_stickiness: u16,
+    /// The region tag of exits we would prefer to use, see ExitDetails::region
+    #[serde(default)]
+    pub preferred_region: Option<String>,
+    /// A bonus subtracted from the babel metric of exits in our preferred region, exits elsewhere are
+    /// only selected if they are better by more than this. Zero disables the region preference
+    #[serde(default)]
+    pub region_tolerance: u16,
+    /// How long in seconds the exit switcher must track a better exit before switching to it, if not
+    /// set this is 15 minutes. Read once at startup
+    #[serde(default)]
+    pub exit_switch_dwell_seconds: Option<u64>,
+    /// How much better, as a percentage of its average metric, a new best exit must be than the exit we are
+    /// currently tracking before we abandon the tracked exit for it. Defaults to 50% if not set
+    #[serde(default)]
+    pub tracking_switch_threshold: Option<u16>,
+    /// How much the price advertised for an exit route counts against it when selecting an exit, in millionths
+    /// of a metric point per unit of price. This lets a cheaper exit win when it is only marginally worse. Zero
+    /// selects exits on babel metric alone
+    #[serde(default)]
+    pub exit_price_weight: u32,
 }
 
 impl Default for ExitClientSettings {
@@ -133,6 +159,12 @@ impl Default for ExitClientSettings {
             contact_info: None,
             lan_nics: HashSet::new(),
             low_balance_notification: true,
+            subnet_stickiness: 0,
+            preferred_region: None,
+            region_tolerance: 0,
+            exit_switch_dwell_seconds: None,
+            tracking_switch_threshold: None,
+            exit_price_weight: 0,
         }
     }
 }
@@ -169,7 +201,9 @@ impl RitaClientSettings {
         }
 
         let config_toml = std::fs::read_to_string(file_name)?;
-        let ret: Self = toml::from_str(&config_toml)?;
+        let mut ret: Self = toml::from_str(&config_toml)?;
+        ret.payment.correct_threshold_signs();
+        ret.check_port_collisions()?;
         Ok(ret)
     }
 
@@ -183,7 +217,9 @@ impl RitaClientSettings {
         }
 
         let config_toml = std::fs::read_to_string(file_name)?;
-        let ret: Self = toml::from_str(&config_toml)?;
+        let mut ret: Self = toml::from_str(&config_toml)?;
+        ret.payment.correct_threshold_signs();
+        ret.check_port_collisions()?;
 
         set_rita_client(ret.clone());
 
@@ -222,8 +258,9 @@ impl RitaClientSettings {
 
         json_merge(&mut settings_value, &changed_settings);
 
-        match serde_json::from_value(settings_value) {
-            Ok(new_settings) => {
+        match serde_json::from_value::<Self>(settings_value) {
+            Ok(mut new_settings) => {
+                new_settings.payment.correct_threshold_signs();
                 *self = new_settings;
                 Ok(())
             }
@@ -235,6 +272,15 @@ impl RitaClientSettings {
         Ok(serde_json::to_value(self.clone())?)
     }
 
+    /// Returns an error if any of the ports rita or babel bind collide
+    pub fn check_port_collisions(&self) -> Result<(), SettingsError> {
+        self.network
+            .check_port_collisions(&[("wg_listen_port", self.exit_client.wg_listen_port)])
+    }
+
+    /// Returns None until the keys and mesh ip have been set up. The wireguard public key is
+    /// derived once when clu generates the keypair and stored in settings, so this never has
+    /// to touch the key file or shell out to wg
     pub fn get_identity(&self) -> Option<Identity> {
         Some(Identity::new(
             self.network.mesh_ip?,
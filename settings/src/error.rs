@@ -11,6 +11,7 @@ pub enum SettingsError {
     IpNetworkError(ipnetwork::IpNetworkError),
     SerdeJsonError(serde_json::Error),
     FileNotFoundError(String),
+    ValidationError(String),
 }
 
 impl From<toml::ser::Error> for SettingsError {
@@ -50,6 +51,7 @@ impl Display for SettingsError {
             SettingsError::FileNotFoundError(e) => {
                 write!(f, "Could not find config file at path {}", e)
             }
+            SettingsError::ValidationError(e) => write!(f, "Invalid settings: {e}"),
         }
     }
 }
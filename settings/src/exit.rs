@@ -20,6 +20,15 @@ pub struct ExitNetworkSettings {
     pub wg_v2_tunnel_port: u16,
     /// Price in wei per byte which is charged to traffic both coming in and out over the internet
     pub exit_price: u64,
+    /// If set the exit price is no longer static, instead it is computed as this base price plus the
+    /// measured average cost of routes to our clients scaled by `exit_price_cost_multiplier`.
+    /// When this is None `exit_price` is used as is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_price_base: Option<u64>,
+    /// Multiplier applied to the measured route cost when computing a dynamic exit price. The
+    /// value is expressed in 1/1000 increments, i.e. 1000 = 1.0, 1500 = 1.5
+    #[serde(default = "default_exit_price_cost_multiplier")]
+    pub exit_price_cost_multiplier: u32,
     /// This is the exit's own ip/gateway ip in the exit wireguard tunnel
     pub own_internal_ip: Ipv4Addr,
     /// The netmask, in bits to mask out, for the exit tunnel
@@ -45,12 +54,25 @@ pub struct ExitNetworkSettings {
     pub enable_enforcement: bool,
     /// Address of the Althea contract to store registered users data
     pub registered_users_contract_addr: Address,
+    /// Clients that have neither checked in nor handshaked with the exit tunnel for this many seconds
+    /// have their tunnel removed and their internal ip and ipv6 subnet freed, they are set up again
+    /// the next time they check in. Zero disables lease expiry
+    #[serde(default = "default_client_lease_timeout_seconds")]
+    pub client_lease_timeout_seconds: u64,
 }
 
 fn enable_enforcement_default() -> bool {
     true
 }
 
+fn default_client_lease_timeout_seconds() -> u64 {
+    7 * 86400
+}
+
+fn default_exit_price_cost_multiplier() -> u32 {
+    1000
+}
+
 impl ExitNetworkSettings {
     /// Generates a configuration that can be used in integration tests, does not use the
     /// default trait to prevent some future code from picking up on the 'default' implementation
@@ -61,6 +83,8 @@ impl ExitNetworkSettings {
             wg_tunnel_port: 59999,
             wg_v2_tunnel_port: 59998,
             exit_price: 10,
+            exit_price_base: None,
+            exit_price_cost_multiplier: default_exit_price_cost_multiplier(),
             own_internal_ip: "172.16.255.254".parse().unwrap(),
             netmask: 12,
             subnet: Some(IpNetwork::V6("ff01::0/128".parse().unwrap())),
@@ -75,6 +99,19 @@ impl ExitNetworkSettings {
             registered_users_contract_addr: "0x9BAbFde52Fe18A5CD00a542b87b4D124a4879582"
                 .parse()
                 .unwrap(),
+            client_lease_timeout_seconds: default_client_lease_timeout_seconds(),
+        }
+    }
+
+    /// Returns the price in wei per byte this exit should charge and advertise, `measured_cost`
+    /// is the average price in wei per byte of the routes to our clients. If dynamic pricing is
+    /// not configured this is simply the static `exit_price`
+    pub fn get_exit_price(&self, measured_cost: u64) -> u64 {
+        match self.exit_price_base {
+            Some(base) => base.saturating_add(
+                measured_cost.saturating_mul(u64::from(self.exit_price_cost_multiplier)) / 1000,
+            ),
+            None => self.exit_price,
         }
     }
 }
@@ -99,6 +136,9 @@ pub struct RitaExitSettingsStruct {
     pub remote_log: bool,
     /// The description of this exit, what is sent to clients and displayed to the user
     pub description: String,
+    /// The region tag of this exit, sent to clients for region based exit selection
+    #[serde(default)]
+    pub region: Option<String>,
     pub payment: PaymentSettings,
     #[serde(default)]
     pub localization: LocalizationSettings,
@@ -124,6 +164,7 @@ impl RitaExitSettingsStruct {
             workers: 1,
             remote_log: false,
             description: "".to_string(),
+            region: None,
             payment: PaymentSettings::default(),
             localization: LocalizationSettings::default(),
             network: NetworkSettings::default(),
@@ -132,6 +173,7 @@ impl RitaExitSettingsStruct {
         }
     }
 
+    /// Same as [crate::client::RitaClientSettings::get_identity]
     pub fn get_identity(&self) -> Option<Identity> {
         Some(Identity::new(
             self.network.mesh_ip?,
@@ -154,6 +196,15 @@ impl RitaExitSettingsStruct {
         }
     }
 
+    /// Returns an error if any of the ports rita, babel or the exit tunnels bind collide
+    pub fn check_port_collisions(&self) -> Result<(), SettingsError> {
+        self.network.check_port_collisions(&[
+            ("exit_hello_port", self.exit_network.exit_hello_port),
+            ("wg_tunnel_port", self.exit_network.wg_tunnel_port),
+            ("wg_v2_tunnel_port", self.exit_network.wg_v2_tunnel_port),
+        ])
+    }
+
     pub fn get_client_subnet_size(&self) -> Option<u8> {
         self.exit_network.client_subnet_size
     }
@@ -167,8 +218,9 @@ impl RitaExitSettingsStruct {
 
         json_merge(&mut settings_value, &changed_settings);
 
-        match serde_json::from_value(settings_value) {
-            Ok(new_settings) => {
+        match serde_json::from_value::<Self>(settings_value) {
+            Ok(mut new_settings) => {
+                new_settings.payment.correct_threshold_signs();
                 *self = new_settings;
                 Ok(())
             }
@@ -182,7 +234,9 @@ impl RitaExitSettingsStruct {
         }
 
         let config_toml = std::fs::read_to_string(file_name)?;
-        let ret: Self = toml::from_str(&config_toml)?;
+        let mut ret: Self = toml::from_str(&config_toml)?;
+        ret.payment.correct_threshold_signs();
+        ret.check_port_collisions()?;
         Ok(ret)
     }
 
@@ -194,10 +248,42 @@ impl RitaExitSettingsStruct {
         }
 
         let config_toml = std::fs::read_to_string(file_name)?;
-        let ret: Self = toml::from_str(&config_toml)?;
+        let mut ret: Self = toml::from_str(&config_toml)?;
+        ret.payment.correct_threshold_signs();
+        ret.check_port_collisions()?;
 
         set_rita_exit(ret.clone());
 
         Ok(ret)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ExitNetworkSettings;
+
+    #[test]
+    fn test_static_exit_price() {
+        let settings = ExitNetworkSettings::test_default();
+        assert_eq!(settings.get_exit_price(0), settings.exit_price);
+        assert_eq!(settings.get_exit_price(5000), settings.exit_price);
+    }
+
+    #[test]
+    fn test_dynamic_exit_price() {
+        let mut settings = ExitNetworkSettings::test_default();
+        settings.exit_price_base = Some(100);
+        assert_eq!(settings.get_exit_price(0), 100);
+        assert_eq!(settings.get_exit_price(5000), 5100);
+
+        settings.exit_price_cost_multiplier = 1500;
+        assert_eq!(settings.get_exit_price(5000), 7600);
+
+        settings.exit_price_cost_multiplier = 500;
+        assert_eq!(settings.get_exit_price(5000), 2600);
+
+        // must not overflow on absurd inputs
+        settings.exit_price_base = Some(u64::MAX);
+        assert_eq!(settings.get_exit_price(u64::MAX), u64::MAX);
+    }
+}
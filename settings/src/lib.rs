@@ -176,6 +176,150 @@ pub fn save_settings_on_shutdown() {
     info!("Shutdown: saving settings");
 }
 
+/// Re-reads the config file from disk and swaps it in as the running settings, this allows
+/// external tooling to edit the config file without restarting rita. The new file is fully
+/// parsed and validated before anything is replaced, so a malformed file leaves the running
+/// settings untouched. Settings which are only read at startup (see keep_restart_only_fields)
+/// keep their running values, the names of any that differ in the file are returned so the
+/// caller can report that a restart is required for them to apply
+pub fn reload_config() -> Result<Vec<String>, SettingsError> {
+    let netns = KI.check_integration_test_netns();
+    let filename = match FLAG_CONFIG.read().unwrap().get(&netns) {
+        Some(filename) => filename.clone(),
+        None => {
+            return Err(SettingsError::FileNotFoundError(
+                "no config file was loaded at startup".to_string(),
+            ))
+        }
+    };
+    if !filename.exists() {
+        return Err(SettingsError::FileNotFoundError(
+            filename.display().to_string(),
+        ));
+    }
+    let config_toml = std::fs::read_to_string(&filename)?;
+
+    let mut settings_ref = SETTINGS.write().unwrap();
+    match settings_ref.get_mut(&netns) {
+        Some(Settings::Adaptor(_)) => Err(SettingsError::ValidationError(
+            "settings are managed by an adaptor and can't be reloaded from file".to_string(),
+        )),
+        Some(Settings::Client(running)) => {
+            let mut new: RitaClientSettings = toml::from_str(&config_toml)?;
+            new.payment.correct_threshold_signs();
+            new.check_port_collisions()?;
+            if !new.validate() {
+                return Err(SettingsError::ValidationError(
+                    "reloaded payment settings are invalid".to_string(),
+                ));
+            }
+            let skipped = keep_restart_only_fields(
+                (&running.network, &running.payment),
+                (&mut new.network, &mut new.payment),
+            );
+            *running = new;
+            Ok(skipped)
+        }
+        Some(Settings::Exit(running)) => {
+            let mut new: RitaExitSettingsStruct = toml::from_str(&config_toml)?;
+            new.payment.correct_threshold_signs();
+            new.check_port_collisions()?;
+            if !new.validate() {
+                return Err(SettingsError::ValidationError(
+                    "reloaded payment settings are invalid".to_string(),
+                ));
+            }
+            let skipped = keep_restart_only_fields(
+                (&running.network, &running.payment),
+                (&mut new.network, &mut new.payment),
+            );
+            *running = new;
+            Ok(skipped)
+        }
+        None => panic!("attempted to reload config to a missing Settings"),
+    }
+}
+
+/// Copies the settings that are only read at startup, such as our identity and the ports
+/// we have already bound, from the running settings into the newly loaded ones. Returns the
+/// names of the fields where the new value was discarded
+fn keep_restart_only_fields(
+    running: (&NetworkSettings, &PaymentSettings),
+    new: (&mut NetworkSettings, &mut PaymentSettings),
+) -> Vec<String> {
+    let (running_network, running_payment) = running;
+    let (network, payment) = new;
+    let mut skipped = Vec::new();
+    keep_field(
+        "network.mesh_ip",
+        running_network.mesh_ip,
+        &mut network.mesh_ip,
+        &mut skipped,
+    );
+    keep_field(
+        "network.wg_private_key",
+        running_network.wg_private_key,
+        &mut network.wg_private_key,
+        &mut skipped,
+    );
+    keep_field(
+        "network.wg_public_key",
+        running_network.wg_public_key,
+        &mut network.wg_public_key,
+        &mut skipped,
+    );
+    keep_field(
+        "network.babel_port",
+        running_network.babel_port,
+        &mut network.babel_port,
+        &mut skipped,
+    );
+    keep_field(
+        "network.rita_hello_port",
+        running_network.rita_hello_port,
+        &mut network.rita_hello_port,
+        &mut skipped,
+    );
+    keep_field(
+        "network.rita_contact_port",
+        running_network.rita_contact_port,
+        &mut network.rita_contact_port,
+        &mut skipped,
+    );
+    keep_field(
+        "network.rita_dashboard_port",
+        running_network.rita_dashboard_port,
+        &mut network.rita_dashboard_port,
+        &mut skipped,
+    );
+    keep_field(
+        "network.wg_start_port",
+        running_network.wg_start_port,
+        &mut network.wg_start_port,
+        &mut skipped,
+    );
+    keep_field(
+        "payment.eth_private_key",
+        running_payment.eth_private_key,
+        &mut payment.eth_private_key,
+        &mut skipped,
+    );
+    keep_field(
+        "payment.eth_address",
+        running_payment.eth_address,
+        &mut payment.eth_address,
+        &mut skipped,
+    );
+    skipped
+}
+
+fn keep_field<T: PartialEq + Copy>(name: &str, running: T, new: &mut T, skipped: &mut Vec<String>) {
+    if *new != running {
+        skipped.push(name.to_string());
+        *new = running;
+    }
+}
+
 /// get a JSON value of all settings
 pub fn get_config_json() -> Result<serde_json::Value, SettingsError> {
     let netns = KI.check_integration_test_netns();
@@ -358,6 +502,9 @@ pub fn json_merge(a: &mut Value, b: &Value) {
 /// In the case of adaptor settings, must be called in the wrapping binary.  
 pub trait FileWrite {
     fn write(&self, file_name: PathBuf) -> Result<(), SettingsError>;
+    /// Writes the settings out as pretty printed json rather than toml, for tooling that
+    /// consumes json. The file written is not loadable as a config file
+    fn write_json(&self, file_name: PathBuf) -> Result<(), SettingsError>;
 }
 
 impl<T> FileWrite for T
@@ -367,15 +514,23 @@ where
     fn write(&self, file_name: PathBuf) -> Result<(), SettingsError> {
         let ser = toml::Value::try_from(self)?;
         let ser = toml::to_string(&ser)?;
-        let mut file = File::create(file_name)?;
-        file.write_all(ser.as_bytes())?;
-        file.flush().unwrap();
-        file.sync_all().unwrap();
-        drop(file);
-        Ok(())
+        write_settings_file(file_name, ser.as_bytes())
+    }
+
+    fn write_json(&self, file_name: PathBuf) -> Result<(), SettingsError> {
+        let ser = serde_json::to_string_pretty(self)?;
+        write_settings_file(file_name, ser.as_bytes())
     }
 }
 
+fn write_settings_file(file_name: PathBuf, contents: &[u8]) -> Result<(), SettingsError> {
+    let mut file = File::create(file_name)?;
+    file.write_all(contents)?;
+    file.flush()?;
+    file.sync_all()?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::client::RitaClientSettings;
@@ -396,4 +551,101 @@ mod tests {
     fn test_exit_settings_example() {
         RitaExitSettingsStruct::new("example_exit.toml").unwrap();
     }
+
+    #[test]
+    fn test_port_collisions() {
+        let mut client = RitaClientSettings::new("test.toml").unwrap();
+        client.check_port_collisions().unwrap();
+        client.network.rita_contact_port = client.network.babel_port;
+        assert!(client.check_port_collisions().is_err());
+
+        let mut client = RitaClientSettings::new("test.toml").unwrap();
+        client.exit_client.wg_listen_port = client.network.wg_start_port + 10;
+        assert!(client.check_port_collisions().is_err());
+
+        let mut client = RitaClientSettings::new("test.toml").unwrap();
+        client.network.babel_port = 0;
+        assert!(client.check_port_collisions().is_err());
+
+        let mut client = RitaClientSettings::new("test.toml").unwrap();
+        client.network.wg_start_port = u16::MAX - 10;
+        assert!(client.check_port_collisions().is_err());
+
+        let mut exit = RitaExitSettingsStruct::new("test_exit.toml").unwrap();
+        exit.check_port_collisions().unwrap();
+        exit.exit_network.wg_v2_tunnel_port = exit.exit_network.wg_tunnel_port;
+        assert!(exit.check_port_collisions().is_err());
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        use crate::FileWrite;
+        let path = std::env::temp_dir().join("rita_write_round_trip_test.toml");
+        let client = RitaClientSettings::new("test.toml").unwrap();
+        client.write(path.clone()).unwrap();
+        let reloaded = RitaClientSettings::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(reloaded, client);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_json() {
+        use crate::FileWrite;
+        let path = std::env::temp_dir().join("rita_write_json_test.json");
+        let client = RitaClientSettings::new("test.toml").unwrap();
+        client.write_json(path.clone()).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        let parsed: RitaClientSettings = serde_json::from_str(&written).unwrap();
+        assert_eq!(parsed, client);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_config() {
+        let path = std::env::temp_dir().join("rita_reload_config_test.toml");
+        let original = std::fs::read_to_string("test.toml").unwrap();
+        std::fs::write(&path, &original).unwrap();
+        let running = RitaClientSettings::new_watched(path.clone()).unwrap();
+        crate::set_flag_config(path.clone());
+
+        // a valid edit is applied, an edit to a startup only field is reported and ignored
+        let mut edited = running.clone();
+        edited.network.rita_tick_interval = running.network.rita_tick_interval + 1;
+        edited.network.babel_port = running.network.babel_port + 1;
+        std::fs::write(&path, toml::to_string(&edited).unwrap()).unwrap();
+        let skipped = crate::reload_config().unwrap();
+        assert_eq!(skipped, vec!["network.babel_port".to_string()]);
+        let reloaded = crate::get_rita_client();
+        assert_eq!(
+            reloaded.network.rita_tick_interval,
+            edited.network.rita_tick_interval
+        );
+        assert_eq!(reloaded.network.babel_port, running.network.babel_port);
+
+        // a malformed file is rejected and the running settings are left alone
+        std::fs::write(&path, "this is not [valid toml").unwrap();
+        assert!(crate::reload_config().is_err());
+        assert_eq!(crate::get_rita_client(), reloaded);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_threshold_sign_correction() {
+        let mut client = RitaClientSettings::new("test.toml").unwrap();
+        assert!(!client.payment.correct_threshold_signs());
+
+        // a negative payment threshold would make the close threshold positive
+        client
+            .merge(serde_json::json!({"payment": {"payment_threshold": "-1000"}}))
+            .unwrap();
+        assert_eq!(
+            client.payment.payment_threshold,
+            crate::payment::default_payment_threshold()
+        );
+
+        client.payment.payment_threshold = 0u8.into();
+        assert!(client.payment.correct_threshold_signs());
+        assert!(client.payment.payment_threshold > 0u8.into());
+    }
 }
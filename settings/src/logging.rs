@@ -22,6 +22,10 @@ pub struct LoggingSettings {
     pub level: String,
     #[serde(default = "default_logging_dest_url")]
     pub dest_url: String,
+    /// When set the router always logs locally, even if an operator address would otherwise
+    /// enable remote logging. This is set by the user and is not modified by operator updates
+    #[serde(default)]
+    pub force_local_logging: bool,
 }
 
 impl Default for LoggingSettings {
@@ -30,6 +34,7 @@ impl Default for LoggingSettings {
             enabled: default_logging(),
             level: default_logging_level(),
             dest_url: default_logging_dest_url(),
+            force_local_logging: false,
         }
     }
 }
@@ -6,8 +6,14 @@ use std::net::{IpAddr, Ipv6Addr};
 
 use althea_types::WgKey;
 
+use crate::SettingsError;
+
 use arrayvec::ArrayString;
 
+/// The fewest ports that must remain above wg_start_port for per hop tunnels, each neighbor
+/// takes a port so a start port close to u16::MAX caps how many neighbors we can have
+pub const MIN_TUNNEL_PORT_RANGE: u16 = 1024;
+
 fn default_discovery_ip() -> Ipv6Addr {
     Ipv6Addr::new(0xff02, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1, 0x8)
 }
@@ -64,6 +70,10 @@ fn default_shaper_settings() -> ShaperSettings {
     }
 }
 
+fn default_min_dashboard_password_length() -> usize {
+    8
+}
+
 fn default_allowed_countries() -> HashSet<Regions> {
     let mut ret = HashSet::new();
     ret.insert(Regions::UnitedStates);
@@ -93,6 +103,10 @@ pub struct NetworkSettings {
     /// and communicated to babel, this value is only used by rita and must be pre-configured in babel
     /// as it can't be changed after startup
     pub babel_port: u16,
+    /// The router id babeld is expected to be running with, if set it is compared against the
+    /// my-id babel reports at startup and a mismatch is logged
+    #[serde(default)]
+    pub babel_router_id: Option<String>,
     /// Port on which rita starts the per hop tunnel handshake on (needs to be constant across an
     /// entire althea deployment)
     pub rita_hello_port: u16,
@@ -103,7 +117,21 @@ pub struct NetworkSettings {
     pub rita_dashboard_port: u16,
     /// The password for dashboard authentication
     pub rita_dashboard_password: Option<String>,
-    /// The tick interval in seconds between rita hellos, traffic watcher measurements and payments
+    /// The salt rita_dashboard_password was hashed with, generated randomly for each router the first
+    /// time the password is set by a dashboard that fetches the salt and kept from then on. When None
+    /// the legacy fixed salt is used.
+    /// The dashboard sends the hash itself as the credential so the stored hash is password equivalent,
+    /// the salt only keeps the plaintext from being looked up by a precomputed table of RitaSalt hashes
+    #[serde(default)]
+    pub rita_dashboard_password_salt: Option<String>,
+    /// The shortest dashboard password we will accept, this password is also the ssh password
+    /// on OpenWRT so it should not be trivially guessable
+    #[serde(default = "default_min_dashboard_password_length")]
+    pub min_dashboard_password_length: usize,
+    /// The tick interval in seconds between rita hellos, traffic watcher measurements and payments.
+    /// Constrained routers may want to slow this down. Only the common loops follow this value, the
+    /// exit metric history is counted in exit loop ticks which stay at 5 seconds, and bufferbloat
+    /// detection in the network monitor needs the default of 5 and is skipped at any other value
     pub rita_tick_interval: u64,
     /// Our private key, encoded with Base64 (what the `wg` command outputs and takes by default)
     /// Note this is the canonical private key for the node
@@ -169,10 +197,13 @@ impl Default for NetworkSettings {
             mesh_ip_v2: None,
             discovery_ip: default_discovery_ip(),
             babel_port: 6872,
+            babel_router_id: None,
             rita_contact_port: 4874,
             rita_hello_port: 4876,
             rita_dashboard_port: 4877,
             rita_dashboard_password: None,
+            rita_dashboard_password_salt: None,
+            min_dashboard_password_length: default_min_dashboard_password_length(),
             rita_tick_interval: 5,
             wg_private_key: None,
             wg_private_key_path: "/tmp/priv".to_string(),
@@ -192,3 +223,48 @@ impl Default for NetworkSettings {
         }
     }
 }
+
+impl NetworkSettings {
+    /// Checks that the ports configured here, plus any `extra_ports` configured elsewhere, are
+    /// all nonzero, distinct and that none of them fall into the range starting at `wg_start_port`
+    /// which is handed out to per hop tunnels. Otherwise the collision only shows up at runtime as
+    /// a confusing bind failure
+    pub fn check_port_collisions(&self, extra_ports: &[(&str, u16)]) -> Result<(), SettingsError> {
+        let mut ports = vec![
+            ("babel_port", self.babel_port),
+            ("rita_hello_port", self.rita_hello_port),
+            ("rita_contact_port", self.rita_contact_port),
+            ("rita_dashboard_port", self.rita_dashboard_port),
+        ];
+        ports.extend_from_slice(extra_ports);
+
+        if self.wg_start_port == 0 || u16::MAX - self.wg_start_port < MIN_TUNNEL_PORT_RANGE {
+            return Err(SettingsError::ValidationError(format!(
+                "wg_start_port {} must be nonzero and leave at least {MIN_TUNNEL_PORT_RANGE} ports for tunnels",
+                self.wg_start_port
+            )));
+        }
+
+        for (i, (name, port)) in ports.iter().enumerate() {
+            if *port == 0 {
+                return Err(SettingsError::ValidationError(format!(
+                    "{name} is set to port 0"
+                )));
+            }
+            for (other_name, other_port) in ports.iter().skip(i + 1) {
+                if port == other_port {
+                    return Err(SettingsError::ValidationError(format!(
+                        "{name} and {other_name} are both set to port {port}"
+                    )));
+                }
+            }
+            if *port >= self.wg_start_port {
+                return Err(SettingsError::ValidationError(format!(
+                    "{name} is set to port {port} which is inside the tunnel port range starting at wg_start_port {}",
+                    self.wg_start_port
+                )));
+            }
+        }
+        Ok(())
+    }
+}
@@ -36,6 +36,11 @@ fn default_force_use_operator_price() -> bool {
     false
 }
 
+/// How long a single operator checkin may take before it is abandoned
+fn default_checkin_timeout_seconds() -> u64 {
+    120
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct OperatorSettings {
     /// The operator managing this router
@@ -44,6 +49,10 @@ pub struct OperatorSettings {
     /// The amount in wei that will be sent to the organizer in one second
     #[serde(default)]
     pub operator_fee: Uint256,
+    /// The most that will be sent to the operator in a single payment, any debt beyond this
+    /// is carried over to later payments. None for no limit
+    #[serde(default)]
+    pub operator_fee_max_payment: Option<Uint256>,
     /// if this router is tracking the operator suggested price
     #[serde(default = "default_use_operator_price")]
     pub use_operator_price: bool,
@@ -59,6 +68,10 @@ pub struct OperatorSettings {
     /// If we should display the operator setup on the dashboard
     #[serde(default = "default_display_operator_setup")]
     pub display_operator_setup: bool,
+    /// The maximum amount of time in seconds an operator checkin request may take, on slow uplinks
+    /// this keeps a checkin from running into the next one
+    #[serde(default = "default_checkin_timeout_seconds")]
+    pub checkin_timeout_seconds: u64,
 }
 
 impl Default for OperatorSettings {
@@ -66,11 +79,13 @@ impl Default for OperatorSettings {
         OperatorSettings {
             operator_address: default_operator_address(),
             operator_fee: 0u32.into(),
+            operator_fee_max_payment: None,
             use_operator_price: default_force_use_operator_price(),
             force_use_operator_price: default_force_use_operator_price(),
             installation_details: None,
             billing_details: None,
             display_operator_setup: true,
+            checkin_timeout_seconds: default_checkin_timeout_seconds(),
         }
     }
 }
@@ -63,6 +63,14 @@ fn default_forgive_on_reboot() -> bool {
     true
 }
 
+fn default_min_payment_amount() -> Uint256 {
+    0u8.into()
+}
+
+fn default_oracle_net_version_interval_seconds() -> u64 {
+    86400
+}
+
 fn default_min_gas() -> Uint256 {
     2_000_000_000u128.into()
 }
@@ -77,6 +85,14 @@ fn default_enable_enforcement() -> bool {
     true
 }
 
+fn default_max_payment_retries() -> u8 {
+    15
+}
+
+fn default_payment_retry_backoff_seconds() -> u64 {
+    10
+}
+
 fn default_node_grpc() -> Vec<String> {
     vec!["https://althea.zone:9090".to_string()]
 }
@@ -126,6 +142,10 @@ pub struct PaymentSettings {
     /// A list of ethereum nodes to query for blockchain data
     #[serde(default = "default_node_list")]
     pub eth_node_list: Vec<String>,
+    /// When set all web3 requests go to this full node, bypassing the random load balancing
+    /// across eth_node_list and the node blacklist. Intended for debugging a misbehaving node
+    #[serde(default)]
+    pub pinned_full_node: Option<String>,
     #[serde(default = "default_system_chain")]
     pub system_chain: SystemChain,
     /// defines the blockchain to use for currency withdraws, this may not
@@ -169,6 +189,37 @@ pub struct PaymentSettings {
     /// post-eip1599 networks that do not respect min-fee
     #[serde(default = "default_min_gas")]
     pub min_gas: Uint256,
+    /// How many times a failed payment is retried before we give up on it, this applies to every
+    /// payment type, neighbor payment notifications, operator fees and simulated transaction fees
+    #[serde(default = "default_max_payment_retries")]
+    pub max_payment_retries: u8,
+    /// The minimum amount of time in seconds to wait after a failed payment before retrying it
+    #[serde(default = "default_payment_retry_backoff_seconds")]
+    pub payment_retry_backoff_seconds: u64,
+    /// How long in seconds to hold a debt that is ready to be paid so that the debt accumulated in the
+    /// meantime is paid in a single transaction, zero disables batching. This should be kept short
+    /// relative to how long it takes the neighbor to reach its close threshold, see MAX_PAYMENT_BATCH_WINDOW
+    #[serde(default)]
+    pub payment_batch_window_seconds: u64,
+    /// Payments smaller than this are not sent, the debt keeps accumulating until it is at least this
+    /// large. Unlike payment_threshold, which decides when a debt is worth paying, this is a floor on
+    /// the size of any single transaction so that we never spend more on gas than the payment settles.
+    /// The debt keeper caps this at half of the close threshold so that we always pay before we are enforced on
+    #[serde(default = "default_min_payment_amount")]
+    pub min_payment_amount: Uint256,
+    /// How often in seconds the blockchain oracle checks the latest block, zero checks on every
+    /// fast loop tick. The oracle is considered outdated after 5 minutes without a block so this
+    /// should be kept well under that
+    #[serde(default)]
+    pub oracle_block_interval_seconds: u64,
+    /// How often in seconds the blockchain oracle refreshes our balance, zero refreshes on every
+    /// fast loop tick. The block is always checked alongside the balance to catch stale full nodes
+    #[serde(default)]
+    pub oracle_balance_interval_seconds: u64,
+    /// How often in seconds the blockchain oracle asks every full node for its net_version, this
+    /// rarely changes and queries the whole node list so it is run infrequently
+    #[serde(default = "default_oracle_net_version_interval_seconds")]
+    pub oracle_net_version_interval_seconds: u64,
 }
 
 /// TODO this is currently a testnet only placeholder it should be replaced
@@ -208,6 +259,25 @@ impl PaymentSettings {
     }
 }
 
+impl PaymentSettings {
+    /// The close threshold is derived as a negative multiple of payment_threshold, so a non positive
+    /// payment_threshold would make the close threshold non negative and invert enforcement, peers would
+    /// be enforced on immediately or never. This resets such a value to the default, returning true if
+    /// a correction was made
+    pub fn correct_threshold_signs(&mut self) -> bool {
+        if self.payment_threshold > 0u8.into() {
+            return false;
+        }
+        error!(
+            "Payment threshold {} is not positive, this would invert enforcement! Resetting to {}",
+            self.payment_threshold,
+            default_payment_threshold()
+        );
+        self.payment_threshold = default_payment_threshold();
+        true
+    }
+}
+
 impl Default for PaymentSettings {
     fn default() -> Self {
         PaymentSettings {
@@ -222,6 +292,7 @@ impl Default for PaymentSettings {
             eth_address: None,
             althea_grpc_list: default_node_grpc(),
             eth_node_list: default_node_list(),
+            pinned_full_node: None,
             system_chain: default_system_chain(),
             withdraw_chain: default_system_chain(),
             debts_file: default_debts_file(),
@@ -233,6 +304,13 @@ impl Default for PaymentSettings {
             simulated_transaction_fee: default_simulated_transaction_fee(),
             forgive_on_reboot: default_forgive_on_reboot(),
             min_gas: default_min_gas(),
+            max_payment_retries: default_max_payment_retries(),
+            payment_retry_backoff_seconds: default_payment_retry_backoff_seconds(),
+            payment_batch_window_seconds: 0,
+            min_payment_amount: default_min_payment_amount(),
+            oracle_block_interval_seconds: 0,
+            oracle_balance_interval_seconds: 0,
+            oracle_net_version_interval_seconds: default_oracle_net_version_interval_seconds(),
             althea_l1_accepted_denoms: vec![default_althea_l1_payment_denom()],
             althea_l1_payment_denom: default_althea_l1_payment_denom(),
         }